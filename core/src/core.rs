@@ -1,9 +1,13 @@
 use std::sync::Arc;
 
-use bevy_ecs::{schedule::Schedule, world::World};
+use bevy_ecs::{
+    schedule::{IntoSystemConfigs, Schedule},
+    world::World,
+};
 use glam::vec3;
 use log::trace;
 use rand::Rng;
+use web_time::Instant;
 use wgpu::{CommandBuffer, TextureFormat};
 
 use crate::{
@@ -15,26 +19,61 @@ use crate::{
         },
         entity_bundles::camera_bundle::CameraBundle,
         resources::{
+            active_simulation_backend::ActiveSimulationBackend,
             apc_resources::{ApcPlatform, ApcQueue},
+            culling_stats::CullingStats,
+            frame_timings::FrameTimings,
             http_resources::HttpPlatform,
             input::Input,
+            morton_reorder::MortonReorder,
             nbody_sim_resources::NBodySimResources,
+            particle_readback::{ParticleReadback, ParticleSnapshot},
+            particle_species::{ParticleLod, ParticleSpecies},
             screen_parameters::ScreenParameters,
+            sim_clock::SimClock,
+            sim_snapshots::SimSnapshots,
+            simulation_step_profiler::SimulationStepProfiler,
             time::Time,
         },
         systems::{
+            compute_task_system::prepare_compute_tasks_system,
+            culling_stats_system::culling_stats_system,
+            escape_detection_system::escape_detection_system,
+            frame_timings_system::frame_timings_system,
+            frustum_culling_system::{
+                toggle_frustum_culling_system, update_frustum_culling_system,
+            },
+            morton_reorder_system::morton_reorder_system,
+            particle_readback_system::{cpu_particle_readback_system, particle_readback_system},
+            particle_render_mode_system::toggle_particle_render_mode_system,
             rotate_transform_system::rotate_transform_system,
+            sim_clock_system::{advance_sim_clock_system, sim_clock_input_system},
+            sim_snapshot_system::{capture_sim_snapshot_system, rewind_input_system},
+            simulation_backend_system::{
+                cpu_simulation_system, gpu_backend_active, gpu_simulation_system,
+                toggle_simulation_backend_system,
+            },
             update_camera_system::{update_camera_bindings, update_camera_system},
             update_input_system::update_input_system,
             update_model_bindings_system::update_model_bindings_system,
-            update_n_body_sim_system::update_n_body_sim_bindings,
+            update_n_body_sim_system::{present_n_body_sim_instances, update_n_body_sim_bindings},
+            workgroup_tuning_system::workgroup_tuning_system,
         },
     },
-    gpu_resources::{self, pipelines::n_body_sim_compute_pipeline},
+    events::{self, update_events_system},
+    gpu_resources::{self, render_resources::RenderResources},
     include_texture,
     render::root_renderer::RootRenderer,
     traits::{apc_traits::ApcHandler, http_traits::HttpRequester},
-    utils::primitives,
+    utils::{buffer::StagingBelt, primitives},
+};
+#[cfg(feature = "debug-shader-hot-reload")]
+use crate::{
+    ecs::{
+        resources::shader_watcher::ShaderWatcher,
+        systems::shader_hot_reload_system::shader_hot_reload_system,
+    },
+    gpu_resources::shader_hot_reload::SHADER_DIRECTORY,
 };
 
 pub struct Core {
@@ -43,6 +82,7 @@ pub struct Core {
     update_schedule: Schedule,
     late_update_schedule: Schedule,
     pre_render_schedule: Schedule,
+    post_render_schedule: Schedule,
     root_renderer: RootRenderer,
 }
 
@@ -72,14 +112,25 @@ impl Core {
 
         world.insert_resource(Input::new());
         world.insert_resource(Time::new());
+        world.insert_resource(SimClock::new());
+        world.insert_resource(SimSnapshots::default());
+        world.insert_resource(ActiveSimulationBackend::default());
+        world.insert_resource(ParticleReadback::default());
+        world.insert_resource(ParticleSnapshot::default());
+        world.insert_resource(CullingStats::default());
         world.insert_resource(ScreenParameters::new(render_width, render_height));
         world.insert_resource(ApcQueue::new());
+        world.insert_resource(FrameTimings::new(
+            device.features().contains(wgpu::Features::TIMESTAMP_QUERY),
+        ));
+        world.insert_resource(SimulationStepProfiler::new(&device, &queue));
         world.insert_resource(ApcPlatform {
             platform: apc_handler,
         });
         world.insert_resource(HttpPlatform {
             requester: http_requester,
         });
+        events::init_events(&mut world);
 
         let camera_bundle = CameraBundle::new(
             &world,
@@ -94,25 +145,96 @@ impl Core {
         // spawn a cube
         let texture = include_texture!("assets/textures/handsome.jpg", &device, &queue);
 
-        let cube_mesh_filter = primitives::create_sphere(&device, 0.25, 32, 32);
+        let particle_radius = 0.25;
+        let cube_mesh_filter = primitives::create_sphere(&device, particle_radius, 32, 32);
         let cube_material = UnlitDiffuseMaterial::new(&world, &texture);
-
-        let n_body_sim_resources = NBodySimResources::new(&world, cube_mesh_filter, cube_material);
+        let particle_species =
+            ParticleSpecies::new(cube_mesh_filter, cube_material).with_lods(vec![
+                ParticleLod::new(primitives::create_sphere(&device, particle_radius, 12, 8)),
+                ParticleLod::new(primitives::create_sphere(&device, particle_radius, 6, 4)),
+                ParticleLod::billboard(primitives::create_quad(
+                    &device,
+                    particle_radius * 2.0,
+                    particle_radius * 2.0,
+                )),
+            ]);
+
+        let mut n_body_sim_resources = NBodySimResources::new(&world, particle_species);
+        n_body_sim_resources.set_bounding_radius(&queue, particle_radius);
+        n_body_sim_resources.set_lod_screen_sizes(&queue, &[48.0, 12.0, 3.0]);
+        n_body_sim_resources.set_frustum_culling(&queue, true);
 
         world.insert_resource(n_body_sim_resources);
+        let morton_reorder = MortonReorder::new(&world);
+        world.insert_resource(morton_reorder);
 
         let mut early_update_schedule = Schedule::default();
         let mut update_schedule = Schedule::default();
         let mut late_update_schedule = Schedule::default();
         let mut pre_render_schedule = Schedule::default();
+        let mut post_render_schedule = Schedule::default();
 
         early_update_schedule.add_systems(update_camera_system);
+        #[cfg(feature = "debug-shader-hot-reload")]
+        {
+            world.insert_resource(ShaderWatcher::new(SHADER_DIRECTORY, 0.5));
+            early_update_schedule.add_systems(shader_hot_reload_system);
+        }
         update_schedule.add_systems(rotate_transform_system);
+        update_schedule.add_systems(frame_timings_system);
+        update_schedule.add_systems(toggle_frustum_culling_system);
+        update_schedule.add_systems(toggle_particle_render_mode_system);
+        update_schedule.add_systems(workgroup_tuning_system);
+        // snapshots are captured and restored before the clock advances to this frame's step,
+        // they only cover the gpu simulation state
+        update_schedule.add_systems(
+            (
+                sim_clock_input_system,
+                toggle_simulation_backend_system,
+                rewind_input_system.run_if(gpu_backend_active),
+                capture_sim_snapshot_system.run_if(gpu_backend_active),
+                particle_readback_system.run_if(gpu_backend_active),
+                cpu_particle_readback_system,
+                advance_sim_clock_system,
+            )
+                .chain(),
+        );
+        // the slots change, but the escapes and readbacks in flight carry ids
+        update_schedule.add_systems(
+            morton_reorder_system
+                .run_if(gpu_backend_active)
+                .after(advance_sim_clock_system),
+        );
+        update_schedule.add_systems(
+            escape_detection_system
+                .run_if(gpu_backend_active)
+                .after(advance_sim_clock_system),
+        );
+        // reads back whichever backend generated the drawn instances
+        update_schedule.add_systems(culling_stats_system.after(advance_sim_clock_system));
         late_update_schedule.add_systems(update_input_system);
+        late_update_schedule.add_systems(update_events_system);
 
         pre_render_schedule.add_systems(update_camera_bindings);
         pre_render_schedule.add_systems(update_model_bindings_system);
-        pre_render_schedule.add_systems(update_n_body_sim_bindings);
+        // the view-projection can mark the instances stale, so it goes before either backend
+        pre_render_schedule.add_systems(
+            update_frustum_culling_system
+                .after(update_camera_bindings)
+                .before(update_n_body_sim_bindings)
+                .before(cpu_simulation_system),
+        );
+        // the step swaps the particle buffers, marking the instances stale
+        pre_render_schedule.add_systems(
+            gpu_simulation_system
+                .run_if(gpu_backend_active)
+                .before(update_n_body_sim_bindings),
+        );
+        pre_render_schedule.add_systems(update_n_body_sim_bindings.run_if(gpu_backend_active));
+        pre_render_schedule.add_systems(cpu_simulation_system);
+        pre_render_schedule.add_systems(prepare_compute_tasks_system);
+
+        post_render_schedule.add_systems(present_n_body_sim_instances.run_if(gpu_backend_active));
 
         Self {
             world,
@@ -120,6 +242,7 @@ impl Core {
             update_schedule,
             late_update_schedule,
             pre_render_schedule,
+            post_render_schedule,
             root_renderer,
         }
     }
@@ -160,9 +283,17 @@ impl Core {
             });
 
         // run the schedules
-        self.early_update_schedule.run(&mut self.world);
-        self.update_schedule.run(&mut self.world);
-        self.late_update_schedule.run(&mut self.world);
+        run_timed(
+            &mut self.early_update_schedule,
+            &mut self.world,
+            "Early Update",
+        );
+        run_timed(&mut self.update_schedule, &mut self.world, "Update");
+        run_timed(
+            &mut self.late_update_schedule,
+            &mut self.world,
+            "Late Update",
+        );
     }
 
     /// Render the current state of the World
@@ -170,8 +301,24 @@ impl Core {
     /// render the current state into the given texture view
     pub fn render(&mut self, texture_view: &wgpu::TextureView) -> CommandBuffer {
         trace!("render");
-        self.pre_render_schedule.run(&mut self.world);
-        self.root_renderer.render(&self.world, texture_view)
+        run_timed(&mut self.pre_render_schedule, &mut self.world, "Pre Render");
+
+        // the uploads staged this frame are submitted ahead of the frame's command buffer
+        let queue = self.world.resource::<RenderResources>().queue.clone();
+        self.world.resource_mut::<StagingBelt>().flush(&queue);
+
+        let start = Instant::now();
+        let command_buffer = self.root_renderer.render(&self.world, texture_view);
+        self.world
+            .resource_mut::<FrameTimings>()
+            .record_cpu_schedule("Render Graph", elapsed_milliseconds(start));
+
+        run_timed(
+            &mut self.post_render_schedule,
+            &mut self.world,
+            "Post Render",
+        );
+        command_buffer
     }
 
     pub fn key_down(&mut self, key_code: winit::keyboard::KeyCode) {
@@ -234,3 +381,16 @@ impl Core {
         &mut self.root_renderer
     }
 }
+
+/// Runs a schedule and records how long it took in the `FrameTimings`
+fn run_timed(schedule: &mut Schedule, world: &mut World, label: &'static str) {
+    let start = Instant::now();
+    schedule.run(world);
+    world
+        .resource_mut::<FrameTimings>()
+        .record_cpu_schedule(label, elapsed_milliseconds(start));
+}
+
+fn elapsed_milliseconds(start: Instant) -> f32 {
+    start.elapsed().as_secs_f32() * 1000.0
+}
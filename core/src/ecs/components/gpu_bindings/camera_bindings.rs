@@ -1,4 +1,5 @@
 use bevy_ecs::{component::Component, world::World};
+use glam::Mat4;
 use wgpu::Queue;
 use wgpu::util::DeviceExt;
 
@@ -45,4 +46,14 @@ impl CameraBindings {
             queue.write_buffer(&self.buffer, 0, &self.gpu_camera.as_buffer());
         }
     }
+
+    /// The view-projection as of the last update
+    pub fn get_view_proj(&self) -> Mat4 {
+        self.gpu_camera.view_proj
+    }
+
+    /// The projection as of the last update
+    pub fn get_proj(&self) -> Mat4 {
+        self.gpu_camera.proj
+    }
 }
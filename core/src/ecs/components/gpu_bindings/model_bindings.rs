@@ -1,46 +1,67 @@
 use bevy_ecs::component::Component;
-use bevy_ecs::world::World;
-use wgpu::Queue;
-use wgpu::util::DeviceExt;
+use bevy_ecs::world::{Mut, World};
 
 use crate::ecs::components::transform::Transform;
+use crate::ecs::resources::model_uniforms::ModelUniforms;
 
 use crate::gpu_resources::{
-    layouts::model_uniform_layout::ModelUniformLayout, types::gpu_model::GpuModel,
+    layouts::model_uniform_layout::ModelUniformLayout, render_resources::RenderResources,
+    types::gpu_model::GpuModel,
 };
 
 use crate::gpu_resources::types::gpu_type_macros::GpuUniformType;
+use crate::utils::buffer::{StagingBelt, UniformSlot};
 
+/// A model's slot of the shared `ModelUniforms` buffer, bound with its dynamic offset
 #[derive(Component, Debug)]
 pub struct ModelBindings {
-    pub bind_group: wgpu::BindGroup,
-    buffer: wgpu::Buffer,
+    slot: UniformSlot,
+    offset: u32,
     gpu_model: GpuModel,
 }
 
 impl ModelBindings {
-    pub fn new(world: &World, device: &wgpu::Device, transform: &mut Transform) -> Self {
-        let model_bind_group_layout = world.get_resource::<ModelUniformLayout>().unwrap();
+    pub fn new(world: &mut World, transform: &mut Transform) -> Self {
         let gpu_model = GpuModel::from_transform(transform);
 
-        let model_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Model Buffer"),
-            contents: &gpu_model.as_buffer(),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
+        world.resource_scope(|world, mut model_uniforms: Mut<ModelUniforms>| {
+            world.resource_scope(|world, mut belt: Mut<StagingBelt>| {
+                let device = &world.resource::<RenderResources>().device;
+                let slot = model_uniforms.allocate(
+                    device,
+                    world.resource::<ModelUniformLayout>(),
+                    &mut belt,
+                );
+                model_uniforms.write(device, &mut belt, slot, &gpu_model.as_buffer());
 
-        let model_bind_group = model_bind_group_layout.create_bind_group(device, &model_buffer);
-
-        Self {
-            bind_group: model_bind_group,
-            buffer: model_buffer,
-            gpu_model,
-        }
+                Self {
+                    slot,
+                    offset: model_uniforms.get_offset(slot),
+                    gpu_model,
+                }
+            })
+        })
     }
 
-    pub fn update(&mut self, queue: &Queue, transform: &mut Transform) {
+    /// Stages the model's uniform if its transform changed
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        belt: &mut StagingBelt,
+        model_uniforms: &ModelUniforms,
+        transform: &mut Transform,
+    ) {
         if self.gpu_model.update_model(transform) {
-            queue.write_buffer(&self.buffer, 0, &self.gpu_model.as_buffer());
+            model_uniforms.write(device, belt, self.slot, &self.gpu_model.as_buffer());
         }
     }
+
+    /// The dynamic offset to bind the `ModelUniforms` bind group with
+    pub fn get_offset(&self) -> u32 {
+        self.offset
+    }
+
+    pub fn get_slot(&self) -> UniformSlot {
+        self.slot
+    }
 }
@@ -0,0 +1,18 @@
+use bevy_ecs::system::Resource;
+
+use crate::simulation::cpu_backend::CpuSimulationBackend;
+
+/// Which backend advances the simulation drawn each frame.
+/// The cpu backend owns its own copy of the state, which is written back to the gpu when switching back.
+#[derive(Resource, Default)]
+pub enum ActiveSimulationBackend {
+    #[default]
+    Gpu,
+    Cpu(CpuSimulationBackend),
+}
+
+impl ActiveSimulationBackend {
+    pub fn is_gpu(&self) -> bool {
+        matches!(self, Self::Gpu)
+    }
+}
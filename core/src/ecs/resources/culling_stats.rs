@@ -0,0 +1,33 @@
+use bevy_ecs::system::Resource;
+
+use crate::gpu_resources::types::gpu_culling_counts::GpuCullingCounts;
+
+/// What became of the particles when the drawn instances were generated.
+/// Read back from the gpu, so it lags the drawn frame by a frame or two.
+#[derive(Debug, Default, Clone, Copy, Resource)]
+pub struct CullingStats {
+    /// Particles drawn as an instance
+    pub drawn: u32,
+    /// Particles outside the instance distance range
+    pub culled_by_range: u32,
+    /// Particles in range but outside the camera's view frustum
+    pub culled_by_frustum: u32,
+    /// Particles not culled that didn't fit their species' instance capacity
+    pub dropped: u32,
+}
+
+impl CullingStats {
+    pub fn from_gpu(counts: &GpuCullingCounts) -> Self {
+        Self {
+            drawn: counts.drawn,
+            culled_by_range: counts.culled_by_range,
+            culled_by_frustum: counts.culled_by_frustum,
+            dropped: counts.dropped,
+        }
+    }
+
+    /// Every particle counted, drawn or not
+    pub fn get_total(&self) -> u32 {
+        self.drawn + self.culled_by_range + self.culled_by_frustum + self.dropped
+    }
+}
@@ -0,0 +1,217 @@
+use std::{collections::VecDeque, fmt::Write};
+
+use bevy_ecs::system::Resource;
+
+/// Number of samples the rolling averages are taken over
+pub const DEFAULT_TIMING_WINDOW: usize = 60;
+
+/// The recent durations of one pass or schedule, in milliseconds
+#[derive(Debug, Clone)]
+pub struct TimingSeries {
+    label: &'static str,
+    samples: VecDeque<f32>,
+    sum: f32,
+}
+
+impl TimingSeries {
+    fn new(label: &'static str) -> Self {
+        Self {
+            label,
+            samples: VecDeque::new(),
+            sum: 0.0,
+        }
+    }
+
+    fn push(&mut self, milliseconds: f32, window: usize) {
+        self.samples.push_back(milliseconds);
+        self.sum += milliseconds;
+        while self.samples.len() > window {
+            self.sum -= self.samples.pop_front().unwrap();
+        }
+    }
+
+    pub fn get_label(&self) -> &'static str {
+        self.label
+    }
+
+    /// The most recent sample, zero before the first one arrives
+    pub fn get_latest(&self) -> f32 {
+        self.samples.back().copied().unwrap_or(0.0)
+    }
+
+    /// The average over the rolling window, zero before the first sample arrives
+    pub fn get_average(&self) -> f32 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        self.sum / self.samples.len() as f32
+    }
+}
+
+/// How long the passes of the render graph took on the gpu and the ecs schedules took on the cpu.
+/// Gpu timings arrive a few frames late, once their timestamp queries have been read back,
+/// and are only recorded when the adapter supports timestamp queries.
+#[derive(Debug, Resource)]
+pub struct FrameTimings {
+    gpu_supported: bool,
+    window: usize,
+    gpu_passes: Vec<TimingSeries>,
+    cpu_schedules: Vec<TimingSeries>,
+    // frames whose gpu timings have been read back
+    gpu_frame_count: u64,
+
+    log_interval: Option<f32>,
+    last_log_time: Option<f32>,
+}
+
+impl FrameTimings {
+    pub fn new(gpu_supported: bool) -> Self {
+        Self {
+            gpu_supported,
+            window: DEFAULT_TIMING_WINDOW,
+            gpu_passes: Vec::new(),
+            cpu_schedules: Vec::new(),
+            gpu_frame_count: 0,
+            log_interval: None,
+            last_log_time: None,
+        }
+    }
+
+    /// Whether the adapter supports timestamp queries, without them only cpu timings are recorded
+    pub fn is_gpu_supported(&self) -> bool {
+        self.gpu_supported
+    }
+
+    /// Sets the number of samples the averages are taken over, applies from the next sample on
+    pub fn set_window(&mut self, window: usize) {
+        self.window = window.max(1);
+    }
+
+    pub fn get_window(&self) -> usize {
+        self.window
+    }
+
+    /// Records the gpu durations of the passes of one frame, in the order they ran
+    pub fn record_gpu_frame(&mut self, passes: &[(&'static str, f32)]) {
+        self.record_gpu_passes(passes);
+        self.gpu_frame_count += 1;
+    }
+
+    /// Records the gpu durations of passes run outside the render graph, like the simulation steps
+    pub fn record_gpu_passes(&mut self, passes: &[(&'static str, f32)]) {
+        for &(label, milliseconds) in passes {
+            Self::record(&mut self.gpu_passes, label, milliseconds, self.window);
+        }
+    }
+
+    pub fn record_cpu_schedule(&mut self, label: &'static str, milliseconds: f32) {
+        Self::record(&mut self.cpu_schedules, label, milliseconds, self.window);
+    }
+
+    fn record(
+        series: &mut Vec<TimingSeries>,
+        label: &'static str,
+        milliseconds: f32,
+        window: usize,
+    ) {
+        match series.iter_mut().find(|timing| timing.label == label) {
+            Some(timing) => timing.push(milliseconds, window),
+            None => {
+                let mut timing = TimingSeries::new(label);
+                timing.push(milliseconds, window);
+                series.push(timing);
+            }
+        }
+    }
+
+    /// The gpu timings of every pass seen so far, in the order they first ran
+    pub fn get_gpu_passes(&self) -> &[TimingSeries] {
+        &self.gpu_passes
+    }
+
+    /// The cpu timings of every schedule seen so far, in the order they first ran
+    pub fn get_cpu_schedules(&self) -> &[TimingSeries] {
+        &self.cpu_schedules
+    }
+
+    pub fn get_gpu_pass(&self, label: &str) -> Option<&TimingSeries> {
+        self.gpu_passes.iter().find(|timing| timing.label == label)
+    }
+
+    pub fn get_cpu_schedule(&self, label: &str) -> Option<&TimingSeries> {
+        self.cpu_schedules
+            .iter()
+            .find(|timing| timing.label == label)
+    }
+
+    /// The summed average gpu time of all passes
+    pub fn get_gpu_total(&self) -> f32 {
+        self.gpu_passes.iter().map(TimingSeries::get_average).sum()
+    }
+
+    /// The summed average cpu time of all schedules
+    pub fn get_cpu_total(&self) -> f32 {
+        self.cpu_schedules
+            .iter()
+            .map(TimingSeries::get_average)
+            .sum()
+    }
+
+    pub fn get_gpu_frame_count(&self) -> u64 {
+        self.gpu_frame_count
+    }
+
+    /// `interval` is the minimum number of seconds between logged reports, `None` disables them
+    pub fn set_log_interval(&mut self, interval: Option<f32>) {
+        self.log_interval = interval;
+        self.last_log_time = None;
+    }
+
+    pub fn get_log_interval(&self) -> Option<f32> {
+        self.log_interval
+    }
+
+    /// Whether a report should be logged at `time`
+    pub fn is_log_due(&self, time: f32) -> bool {
+        match (self.log_interval, self.last_log_time) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(interval), Some(last_log_time)) => time - last_log_time >= interval,
+        }
+    }
+
+    pub fn mark_logged(&mut self, time: f32) {
+        self.last_log_time = Some(time);
+    }
+
+    /// A multi-line summary of the latest and average timings, for logs and overlays
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+
+        if self.gpu_supported {
+            let _ = writeln!(report, "gpu {:>7.3} ms", self.get_gpu_total());
+            for timing in &self.gpu_passes {
+                Self::write_line(&mut report, timing);
+            }
+        } else {
+            let _ = writeln!(report, "gpu timings unsupported by the adapter");
+        }
+
+        let _ = writeln!(report, "cpu {:>7.3} ms", self.get_cpu_total());
+        for timing in &self.cpu_schedules {
+            Self::write_line(&mut report, timing);
+        }
+
+        report
+    }
+
+    fn write_line(report: &mut String, timing: &TimingSeries) {
+        let _ = writeln!(
+            report,
+            "  {:<16} {:>7.3} ms (avg {:>7.3} ms)",
+            timing.label,
+            timing.get_latest(),
+            timing.get_average()
+        );
+    }
+}
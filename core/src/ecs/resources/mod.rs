@@ -1,6 +1,21 @@
+pub mod active_simulation_backend;
 pub mod apc_resources;
+pub mod culling_stats;
+pub mod frame_timings;
 pub mod http_resources;
 pub mod input;
+pub mod model_uniforms;
+pub mod morton_reorder;
+pub mod n_body_kernel_config;
 pub mod nbody_sim_resources;
+pub mod particle_attribute_schema;
+pub mod particle_readback;
+pub mod particle_species;
 pub mod screen_parameters;
+#[cfg(feature = "debug-shader-hot-reload")]
+pub mod shader_watcher;
+pub mod sim_clock;
+pub mod sim_snapshots;
+pub mod simulation_step_profiler;
 pub mod time;
+pub mod workgroup_tuning;
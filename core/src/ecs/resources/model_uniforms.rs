@@ -0,0 +1,69 @@
+use bevy_ecs::system::Resource;
+use encase::ShaderType;
+
+use crate::{
+    gpu_resources::{
+        layouts::model_uniform_layout::ModelUniformLayout, types::gpu_model::GpuModel,
+    },
+    utils::buffer::{StagingBelt, UniformPool, UniformSlot},
+};
+
+/// Models the pool has room for before it grows
+const INITIAL_MODEL_CAPACITY: usize = 64;
+
+/// The uniforms of every `ModelBindings`, slots of one buffer behind one bind group
+#[derive(Resource)]
+pub struct ModelUniforms {
+    pool: UniformPool,
+    bind_group: wgpu::BindGroup,
+}
+
+impl ModelUniforms {
+    pub fn new(device: &wgpu::Device, layout: &ModelUniformLayout) -> Self {
+        let pool = UniformPool::new(
+            device,
+            GpuModel::min_size().get(),
+            INITIAL_MODEL_CAPACITY,
+            "Model Uniform Buffer",
+        );
+        let bind_group = layout.create_bind_group(device, &pool);
+
+        Self { pool, bind_group }
+    }
+
+    /// Hands out the slot of a new model, the bind group is recreated if the buffer grows
+    pub fn allocate(
+        &mut self,
+        device: &wgpu::Device,
+        layout: &ModelUniformLayout,
+        belt: &mut StagingBelt,
+    ) -> UniformSlot {
+        let (slot, grown) = self.pool.allocate(device, belt);
+        if grown {
+            self.bind_group = layout.create_bind_group(device, &self.pool);
+        }
+        slot
+    }
+
+    pub fn free(&mut self, slot: UniformSlot) {
+        self.pool.free(slot);
+    }
+
+    pub fn write(
+        &self,
+        device: &wgpu::Device,
+        belt: &mut StagingBelt,
+        slot: UniformSlot,
+        data: &[u8],
+    ) {
+        self.pool.write(device, belt, slot, data);
+    }
+
+    pub fn get_offset(&self, slot: UniformSlot) -> u32 {
+        self.pool.get_offset(slot)
+    }
+
+    pub fn get_bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
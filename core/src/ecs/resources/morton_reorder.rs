@@ -0,0 +1,395 @@
+use bevy_ecs::{
+    system::Resource,
+    world::{Mut, World},
+};
+use log::error;
+use wgpu::BufferUsages;
+
+use crate::{
+    ecs::resources::{apc_resources::ApcQueue, nbody_sim_resources::NBodySimResources},
+    gpu_resources::{
+        layouts::nbody_simparams_uniform_layout::{
+            NBodyReorderBindings, NBodySimParamsUniformLayout,
+        },
+        pipelines::{
+            compute_task_registry::ComputeTaskRegistry,
+            morton_reorder_task::{MortonCodesTask, MortonGatherTask},
+        },
+        render_resources::RenderResources,
+    },
+    traits::apc_traits::ApcCallback,
+    utils::{
+        buffer::{Buffer, BufferBuilder},
+        radix_sort::GpuRadixSort,
+    },
+};
+
+/// Steps between reorders unless set otherwise
+pub const DEFAULT_REORDER_INTERVAL: u64 = 1000;
+
+/// Bits of the Morton codes, 10 per axis
+const MORTON_CODE_BITS: u32 = 30;
+
+/// The empty bounds in the order preserving bits cs_particle_bounds reduces them in,
+/// the largest value as the minimum and the smallest as the maximum
+const EMPTY_BOUNDS: [u32; 6] = [u32::MAX, u32::MAX, u32::MAX, 0, 0, 0];
+
+/// The buffers the reorder kernels bind, sized for the particle buffers
+struct ReorderBuffers {
+    morton_codes: Buffer<u32>,
+    permutation: Buffer<u32>,
+    bounds: Buffer<u32>,
+    // the attributes in the sorted order, copied back once gathered
+    attributes: Buffer<u32>,
+    bind_group: wgpu::BindGroup,
+    capacity: usize,
+    attribute_stride: usize,
+}
+
+impl ReorderBuffers {
+    fn new(
+        device: &wgpu::Device,
+        layout: &NBodySimParamsUniformLayout,
+        source_attributes: &Buffer<u32>,
+        capacity: usize,
+        attribute_stride: usize,
+    ) -> Self {
+        let sorted_usage = BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST;
+        let morton_codes = BufferBuilder::<u32>::new(device)
+            .label("Morton Code Buffer")
+            .size(capacity)
+            .usage(sorted_usage)
+            .build()
+            .unwrap();
+        let permutation = BufferBuilder::<u32>::new(device)
+            .label("Morton Permutation Buffer")
+            .size(capacity)
+            .usage(sorted_usage)
+            .build()
+            .unwrap();
+        let bounds = BufferBuilder::<u32>::new(device)
+            .label("Morton Bounds Buffer")
+            .usage(BufferUsages::STORAGE | BufferUsages::COPY_DST)
+            .contents(&EMPTY_BOUNDS)
+            .build()
+            .unwrap();
+        let attributes = BufferBuilder::<u32>::new(device)
+            .label("Morton Attribute Buffer")
+            .size(capacity * attribute_stride.max(1))
+            .usage(BufferUsages::STORAGE | BufferUsages::COPY_SRC)
+            .build()
+            .unwrap();
+
+        let bind_group = Self::create_bind_group(
+            device,
+            layout,
+            &morton_codes,
+            &permutation,
+            &bounds,
+            &attributes,
+            source_attributes,
+        );
+
+        Self {
+            morton_codes,
+            permutation,
+            bounds,
+            attributes,
+            bind_group,
+            capacity,
+            attribute_stride,
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &NBodySimParamsUniformLayout,
+        morton_codes: &Buffer<u32>,
+        permutation: &Buffer<u32>,
+        bounds: &Buffer<u32>,
+        attributes: &Buffer<u32>,
+        source_attributes: &Buffer<u32>,
+    ) -> wgpu::BindGroup {
+        layout
+            .create_reorder_bind_group(
+                device,
+                &NBodyReorderBindings {
+                    morton_codes,
+                    permutation,
+                    bounds,
+                    attributes,
+                    source_attributes,
+                },
+            )
+            .unwrap()
+    }
+
+    /// Binds the attribute buffer in use, it is reallocated along with the attribute schema
+    fn rebind_source_attributes(
+        &mut self,
+        device: &wgpu::Device,
+        layout: &NBodySimParamsUniformLayout,
+        source_attributes: &Buffer<u32>,
+    ) {
+        self.bind_group = Self::create_bind_group(
+            device,
+            layout,
+            &self.morton_codes,
+            &self.permutation,
+            &self.bounds,
+            &self.attributes,
+            source_attributes,
+        );
+    }
+}
+
+/// Periodically sorts the particles by the Morton code of their position, so particles close
+/// in space are close in memory again once they have mixed. Ids move with their particles,
+/// only the slots change.
+#[derive(Resource)]
+pub struct MortonReorder {
+    // steps between reorders, never when None
+    interval: Option<u64>,
+    last_reorder_step: u64,
+
+    sort: GpuRadixSort,
+    buffers: ReorderBuffers,
+
+    // the order is copied back so the cpu mirror of the ids can follow the particles
+    staging_buffer: Option<Buffer<u32>>,
+    // particles in the order being read back
+    in_flight: Option<usize>,
+}
+
+impl MortonReorder {
+    pub fn new(world: &World) -> Self {
+        let device = &world.resource::<RenderResources>().device;
+        let layout = world.resource::<NBodySimParamsUniformLayout>();
+        let sim = world.resource::<NBodySimResources>();
+
+        let capacity = sim.get_particle_capacity();
+        let attribute_stride = sim.get_attribute_schema().stride();
+
+        Self {
+            interval: Some(DEFAULT_REORDER_INTERVAL),
+            last_reorder_step: 0,
+            sort: GpuRadixSort::new(device, capacity).unwrap(),
+            buffers: ReorderBuffers::new(
+                device,
+                layout,
+                sim.get_attribute_buffer(),
+                capacity,
+                attribute_stride,
+            ),
+            staging_buffer: None,
+            in_flight: None,
+        }
+    }
+
+    /// Follows the particle buffers and the attribute schema when they change.
+    /// The bind group is rebuilt every time, it is cheap next to the sort.
+    fn ensure_buffers(
+        &mut self,
+        render_resources: &RenderResources,
+        layout: &NBodySimParamsUniformLayout,
+        sim: &NBodySimResources,
+    ) {
+        let device = &render_resources.device;
+        let capacity = sim.get_particle_capacity();
+        let attribute_stride = sim.get_attribute_schema().stride();
+        if capacity == self.buffers.capacity && attribute_stride == self.buffers.attribute_stride {
+            self.buffers
+                .rebind_source_attributes(device, layout, sim.get_attribute_buffer());
+            return;
+        }
+
+        self.buffers = ReorderBuffers::new(
+            device,
+            layout,
+            sim.get_attribute_buffer(),
+            capacity,
+            attribute_stride,
+        );
+    }
+
+    /// Reorders every `interval` steps, or never when `None`
+    pub fn set_interval(&mut self, interval: Option<u64>) {
+        self.interval = interval.filter(|interval| *interval > 0);
+    }
+
+    pub fn get_interval(&self) -> Option<u64> {
+        self.interval
+    }
+
+    /// Whether a reorder is due at the given step count, counting back from a rewind as well.
+    /// Never while the order of the last one is still being read back.
+    pub fn is_due(&self, step_count: u64) -> bool {
+        self.in_flight.is_none()
+            && self
+                .interval
+                .is_some_and(|interval| step_count.abs_diff(self.last_reorder_step) >= interval)
+    }
+
+    pub fn is_in_flight(&self) -> bool {
+        self.in_flight.is_some()
+    }
+
+    /// The staging buffer the order is copied to, reused until the particles outgrow it
+    fn ensure_staging_buffer(&mut self, device: &wgpu::Device, count: usize) {
+        let too_small = self
+            .staging_buffer
+            .as_ref()
+            .is_none_or(|buffer| buffer.length < count);
+        if too_small {
+            self.staging_buffer = Some(
+                BufferBuilder::<u32>::new(device)
+                    .label("Morton Permutation Readback Buffer")
+                    .size(self.buffers.capacity.max(count))
+                    .usage(BufferUsages::MAP_READ | BufferUsages::COPY_DST)
+                    .build()
+                    .unwrap(),
+            );
+        }
+    }
+
+    /// Reads the mapped order, `None` if mapping failed
+    fn take_permutation(&mut self, mapped: bool) -> Option<Vec<u32>> {
+        let count = self.in_flight.take()?;
+        let staging_buffer = self.staging_buffer.as_ref()?;
+        if !mapped {
+            return None;
+        }
+
+        let permutation = bytemuck::pod_collect_to_vec(
+            &staging_buffer.slice().get_mapped_range()[..count * std::mem::size_of::<u32>()],
+        );
+        staging_buffer.buffer.unmap();
+        Some(permutation)
+    }
+
+    pub fn get_bind_group(&self) -> &wgpu::BindGroup {
+        &self.buffers.bind_group
+    }
+}
+
+/// Sorts the particles by Morton code on the gpu, along with their attributes.
+/// The order is read back without stalling the frame, the cpu mirror of the ids follows once
+/// it arrives through the apc queue.
+pub fn reorder_particles(world: &mut World, step_count: u64) -> Result<(), String> {
+    world.resource_scope(|world, mut reorder: Mut<MortonReorder>| {
+        reorder.last_reorder_step = step_count;
+        reorder.ensure_buffers(
+            world.resource::<RenderResources>(),
+            world.resource::<NBodySimParamsUniformLayout>(),
+            world.resource::<NBodySimResources>(),
+        );
+    });
+
+    let count = world.resource::<NBodySimResources>().get_particle_count() as usize;
+    if count < 2 {
+        return Ok(());
+    }
+
+    // the reorder kernels are built for the particle layout in use
+    world.resource_scope(|world, mut registry: Mut<ComputeTaskRegistry>| {
+        registry.prepare(world);
+    });
+
+    // owned so the world stays free to lend the sort out
+    let render_resources = world.resource::<RenderResources>();
+    let (device, queue) = (
+        render_resources.device.clone(),
+        render_resources.queue.clone(),
+    );
+    world
+        .resource::<MortonReorder>()
+        .buffers
+        .bounds
+        .update(&queue, &EMPTY_BOUNDS, 0);
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Morton Reorder Encoder"),
+    });
+    {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Morton Code Compute Pass"),
+            timestamp_writes: None,
+        });
+        world.resource::<ComputeTaskRegistry>().dispatch_task(
+            world,
+            MortonCodesTask::LABEL,
+            &mut compute_pass,
+        )?;
+    }
+
+    world.resource_scope(|world, mut reorder: Mut<MortonReorder>| {
+        let reorder = &mut *reorder;
+        reorder.sort.sort(
+            world.resource::<RenderResources>(),
+            &mut encoder,
+            &reorder.buffers.morton_codes,
+            &reorder.buffers.permutation,
+            count,
+            MORTON_CODE_BITS,
+        )
+    })?;
+
+    {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Morton Gather Compute Pass"),
+            timestamp_writes: None,
+        });
+        world.resource::<ComputeTaskRegistry>().dispatch_task(
+            world,
+            MortonGatherTask::LABEL,
+            &mut compute_pass,
+        )?;
+    }
+
+    let sender = world.resource::<ApcQueue>().sender.clone();
+    world.resource_scope(|world, mut reorder: Mut<MortonReorder>| {
+        let reorder = &mut *reorder;
+        reorder.ensure_staging_buffer(&device, count);
+        let staging_buffer = reorder.staging_buffer.as_ref().unwrap();
+
+        reorder
+            .buffers
+            .permutation
+            .copy_to(&mut encoder, 0, staging_buffer, 0, count);
+        world
+            .resource::<NBodySimResources>()
+            .copy_attributes_from(&mut encoder, &reorder.buffers.attributes);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        reorder.in_flight = Some(count);
+
+        // the buffer can only be mapped once the copy has been submitted
+        staging_buffer
+            .slice()
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let mapped = result.is_ok();
+                let callback: ApcCallback = Box::new(move |world| complete_reorder(world, mapped));
+                let _ = sender.send(callback);
+            });
+    });
+
+    world.resource_mut::<NBodySimResources>().begin_reorder();
+
+    Ok(())
+}
+
+/// Moves the ids along with their particles once the order is read back
+fn complete_reorder(world: &mut World, mapped: bool) {
+    let permutation = world
+        .resource_mut::<MortonReorder>()
+        .take_permutation(mapped);
+
+    let mut n_body_sim_resources = world.resource_mut::<NBodySimResources>();
+    match permutation {
+        Some(permutation) => n_body_sim_resources.apply_reorder(&permutation),
+        None => {
+            error!("Failed to read back the Morton order, the particle ids no longer match");
+            n_body_sim_resources.abandon_reorder();
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use bevy_ecs::system::Resource;
+
+use crate::gpu_resources::shader_variants::ShaderDefines;
+
+/// How velocities and positions are advanced each step
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Integrator {
+    /// Moves with the old velocity, then updates it
+    Euler,
+    /// Updates the velocity, then moves with the new one
+    #[default]
+    SemiImplicitEuler,
+    /// Drifts half a step, kicks with the forces there, drifts the other half
+    Leapfrog,
+}
+
+/// How close encounters are kept from blowing up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SofteningKernel {
+    /// The softening is added to the squared distance of the force law
+    #[default]
+    Additive,
+    /// Plummer sphere, the direction is softened along with the magnitude
+    Plummer,
+    /// No softening, close encounters are only limited by the time step
+    None,
+}
+
+/// How the force between two bodies scales with their distance
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ForceLaw {
+    /// Newtonian gravity
+    #[default]
+    InverseSquare,
+    /// Gravity in two dimensions, falls off slower
+    InverseLinear,
+    /// Grows with distance, keeps the system bound
+    Spring,
+}
+
+/// Picks the variant of the n-body kernels the gpu simulation runs.
+/// Changes take effect on the next frame, each combination is compiled the first time it is used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Resource)]
+pub struct NBodyKernelConfig {
+    pub integrator: Integrator,
+    pub softening_kernel: SofteningKernel,
+    pub force_law: ForceLaw,
+    /// Whether the bodies are read through workgroup shared memory one tile at a time
+    pub tiled: bool,
+    /// Invocations per workgroup of the step kernel, also the tile size
+    pub workgroup_size: u32,
+}
+
+impl Default for NBodyKernelConfig {
+    fn default() -> Self {
+        Self {
+            integrator: Integrator::default(),
+            softening_kernel: SofteningKernel::default(),
+            force_law: ForceLaw::default(),
+            tiled: true,
+            workgroup_size: 64,
+        }
+    }
+}
+
+impl NBodyKernelConfig {
+    /// The `#define` values selecting this variant in n-body-sim-compute.wgsl
+    pub fn defines(&self) -> ShaderDefines {
+        let integrator = match self.integrator {
+            Integrator::Euler => 0,
+            Integrator::SemiImplicitEuler => 1,
+            Integrator::Leapfrog => 2,
+        };
+        let softening_kernel = match self.softening_kernel {
+            SofteningKernel::Additive => 0,
+            SofteningKernel::Plummer => 1,
+            SofteningKernel::None => 2,
+        };
+        let force_law = match self.force_law {
+            ForceLaw::InverseSquare => 0,
+            ForceLaw::InverseLinear => 1,
+            ForceLaw::Spring => 2,
+        };
+
+        vec![
+            ("INTEGRATOR", integrator.to_string()),
+            ("SOFTENING_KERNEL", softening_kernel.to_string()),
+            ("FORCE_LAW", force_law.to_string()),
+            ("TILED", (self.tiled as u32).to_string()),
+            ("WORKGROUP_SIZE", self.workgroup_size.to_string()),
+        ]
+    }
+}
@@ -1,58 +1,277 @@
+use std::collections::HashSet;
+
 use bevy_ecs::{system::Resource, world::World};
-use glam::{Vec3, Vec4};
+use bytemuck::Zeroable;
+use crossbeam::channel::Sender;
+use glam::{Mat4, Vec3};
 use wgpu::BufferUsages;
 
 use crate::{
-    ecs::components::{
-        materials::unlit_diffuse_material::UnlitDiffuseMaterial, mesh_filter::BasicMeshFilter,
+    ecs::resources::{
+        particle_attribute_schema::{ParticleAttributeSchema, ParticleAttributeValue},
+        particle_species::{MAX_LODS, MAX_SPECIES, ParticleSpecies},
     },
     gpu_resources::{
-        layouts::nbody_simparams_uniform_layout::NBodySimParamsUniformLayout,
+        layouts::nbody_simparams_uniform_layout::{
+            NBodyInstanceBindings, NBodyReorderSimBindings, NBodySimBindings,
+            NBodySimParamsUniformLayout,
+        },
+        particle_storage::{InstanceFormat, ParticleLayout, ParticleSlots},
+        pipelines::render_sprites_pipeline::{ParticleRenderMode, SPRITE_INDICES},
         render_resources::RenderResources,
         types::{
-            basic_vertex::BasicVertex, gpu_indirect_args::GpuIndirectArgs,
+            gpu_cull_params::GpuCullParams, gpu_culling_counts::GpuCullingCounts,
+            gpu_escape_record::GpuEscapeRecord, gpu_indirect_args::GpuIndirectArgs,
             gpu_particle::GpuParticle, gpu_particle_instance::GpuParticleInstance,
-            gpu_sim_params::GpuSimParams,
+            gpu_sim_params::GpuSimParams, gpu_species::GpuSpecies,
+            gpu_system_state::GpuSystemState,
         },
     },
-    utils::buffer::{Buffer, BufferBuilder},
+    traits::apc_traits::ApcCallback,
+    utils::buffer::{Buffer, BufferBuilder, DynamicBuffer, StagingBelt},
 };
 
+/// The smallest number of particles the gpu buffers are allocated for.
+/// Storage buffers can't be bound with a size of zero, so we always keep some room.
+const MIN_PARTICLE_CAPACITY: usize = 64;
+
+/// The most escapes recorded per step, any others are recorded on a later step.
+const ESCAPE_CAPACITY: usize = 256;
+
+/// Radius of the particle meshes at an instance size of 1 unless set otherwise
+pub const DEFAULT_BOUNDING_RADIUS: f32 = 1.0;
+
+/// The instances of every species and their draw arguments, generated together
+struct ParticleInstanceSet {
+    // one range of instances and one set of draw arguments per species
+    instance_buffer: Buffer<GpuParticleInstance>,
+    indirect_buffer: Buffer<GpuIndirectArgs>,
+    // what became of the particles when the instances were generated
+    culling_counts: Buffer<GpuCullingCounts>,
+    bind_group: wgpu::BindGroup,
+}
+
 #[derive(Resource)]
 pub struct NBodySimResources {
     sim_params: GpuSimParams,
 
-    particle_buffer_a: Buffer<GpuParticle>,
-    particle_buffer_b: Buffer<GpuParticle>,
+    // read_buffer always holds the current particle state, write_buffer receives the next step
+    particle_buffers: DynamicBuffer<GpuParticle>,
+    particle_layout: ParticleLayout,
     sim_params_buffer: Buffer<GpuSimParams>,
-    instance_buffer: Buffer<GpuParticleInstance>,
-    indirect_buffer: Buffer<GpuIndirectArgs>,
+    system_state_buffer: Buffer<GpuSystemState>,
+    escape_buffer: Buffer<GpuEscapeRecord>,
 
-    particle_mesh_filter: BasicMeshFilter,
-    particle_material: UnlitDiffuseMaterial,
+    // extra per-particle attributes, attribute_schema.stride() words per slot
+    attribute_schema: ParticleAttributeSchema,
+    attribute_buffer: Buffer<u32>,
+    attribute_bind_group: wgpu::BindGroup,
+
+    // the system state followed by the escape records, copied back to the cpu
+    escape_staging_buffer: Buffer,
+    escape_readback_in_flight: bool,
+
+    species: Vec<ParticleSpecies>,
+    species_buffer: Buffer<GpuSpecies>,
 
     // we need two bind groups for double buffering
-    bind_group_a: wgpu::BindGroup,
-    bind_group_b: wgpu::BindGroup,
+    // bind_group reads from the read buffer, swapped_bind_group is used once the buffers swap
+    bind_group: wgpu::BindGroup,
+    swapped_bind_group: wgpu::BindGroup,
+    // the particle buffers and params alone for the Morton reorder kernels, swapped the same way
+    reorder_bind_group: wgpu::BindGroup,
+    swapped_reorder_bind_group: wgpu::BindGroup,
+
+    // with two sets the instances are generated into one while the other is drawn,
+    // with one the draw waits for the generation
+    instance_sets: Vec<ParticleInstanceSet>,
+    drawn_instance_set: usize,
+    // the particle state changed since the instances were last generated
+    instances_stale: bool,
+    // the camera moved while it decides the culling or the levels of detail, the instances are
+    // generated into the drawn set so they are culled with the view they are drawn with
+    instances_view_dependent: bool,
+    instance_format: InstanceFormat,
+    // meshes or sprites, the draw arguments hold the index count of what is drawn
+    render_mode: ParticleRenderMode,
+    // instances each list's range holds, the particle capacity split between the lists unless
+    // limited
+    instance_capacity_limit: Option<usize>,
+    // whether the limit is raised once instances are dropped for lack of room
+    grow_instances: bool,
+    // the instance buffer is bound whole, every list's range has to fit in one binding
+    max_instance_buffer_size: u64,
+    // shared by the instance sets
+    cull_params: GpuCullParams,
+    cull_params_buffer: Buffer<GpuCullParams>,
+    /// The cull params for the sprite shaders, which size the quads by the bounding radius
+    sprite_bind_group: wgpu::BindGroup,
 
-    frame_count: u32,
+    // the culling counts of the drawn instance set, copied back to the cpu
+    culling_staging_buffer: Buffer<GpuCullingCounts>,
+    culling_readback_in_flight: bool,
+
+    // cpu mirror of the id stored in each slot of the particle buffers
+    particle_ids: Vec<u32>,
+    next_particle_id: u32,
+    // the particles were reordered on the gpu and the mirror waits for the order to be read back,
+    // bodies can't be looked up by id meanwhile
+    reorder_in_flight: bool,
 }
 
 impl NBodySimResources {
-    pub fn new(
-        world: &World,
-        particle_mesh_filter: BasicMeshFilter,
-        particle_material: UnlitDiffuseMaterial,
-    ) -> Self {
+    pub fn new(world: &World, particle_species: ParticleSpecies) -> Self {
         let num_particles = 10;
 
         let render_resources = world.get_resource::<RenderResources>().unwrap();
-        let (device, queue) = &render_resources.get_device_queue();
+        let device = &render_resources.device;
         let nbody_bind_group_layout = world.get_resource::<NBodySimParamsUniformLayout>().unwrap();
 
-        let sim_params = GpuSimParams::new(0.0, num_particles, 2.0);
+        let species = vec![particle_species];
+
+        let instance_capacity = (MIN_PARTICLE_CAPACITY / Self::list_count(&species)).max(1);
+        let mut sim_params = GpuSimParams::new(0.0, 0, 2.0);
+        sim_params.instance_capacity = instance_capacity as u32;
+
+        let particle_buffers = Self::create_particle_buffers(device, MIN_PARTICLE_CAPACITY);
+
+        let sim_params_buffer = BufferBuilder::<GpuSimParams>::new(device)
+            .label("Sim Params Buffer")
+            .usage(BufferUsages::UNIFORM | BufferUsages::COPY_DST)
+            .contents(&[sim_params])
+            .build()
+            .unwrap();
+
+        let species_buffer = BufferBuilder::<GpuSpecies>::new(device)
+            .label("Species Buffer")
+            .usage(BufferUsages::UNIFORM | BufferUsages::COPY_DST)
+            .contents(&Self::species_to_gpu(&species))
+            .build()
+            .unwrap();
+
+        let system_state_buffer = BufferBuilder::<GpuSystemState>::new(device)
+            .label("System State Buffer")
+            .size(1)
+            .usage(BufferUsages::STORAGE | BufferUsages::COPY_SRC)
+            .build()
+            .unwrap();
+
+        let escape_buffer = BufferBuilder::<GpuEscapeRecord>::new(device)
+            .label("Escape Buffer")
+            .size(ESCAPE_CAPACITY)
+            .usage(BufferUsages::STORAGE | BufferUsages::COPY_SRC)
+            .build()
+            .unwrap();
+
+        let attribute_schema = ParticleAttributeSchema::default();
+        let attribute_buffer =
+            Self::create_attribute_buffer(device, &attribute_schema, MIN_PARTICLE_CAPACITY);
+        let attribute_bind_group =
+            nbody_bind_group_layout.create_attribute_bind_group(device, &attribute_buffer);
+
+        let escape_staging_buffer = BufferBuilder::<u8>::new(device)
+            .label("Escape Staging Buffer")
+            .size((system_state_buffer.size + escape_buffer.size) as usize)
+            .usage(BufferUsages::MAP_READ | BufferUsages::COPY_DST)
+            .build()
+            .unwrap();
+
+        let mut cull_params = GpuCullParams::new(DEFAULT_BOUNDING_RADIUS);
+        cull_params.lod_count = Self::lod_count(&species) as u32;
+        let cull_params_buffer = BufferBuilder::<GpuCullParams>::new(device)
+            .label("Cull Params Buffer")
+            .usage(BufferUsages::UNIFORM | BufferUsages::COPY_DST)
+            .contents(&[cull_params])
+            .build()
+            .unwrap();
+        let sprite_bind_group = nbody_bind_group_layout
+            .create_sprite_bind_group(device, &cull_params_buffer)
+            .unwrap();
+
+        let culling_staging_buffer = BufferBuilder::<GpuCullingCounts>::new(device)
+            .label("Culling Staging Buffer")
+            .size(1)
+            .usage(BufferUsages::MAP_READ | BufferUsages::COPY_DST)
+            .build()
+            .unwrap();
+
+        let (bind_group, swapped_bind_group) = Self::create_bind_groups(
+            device,
+            nbody_bind_group_layout,
+            NBodySimBindings {
+                particles: &particle_buffers.read_buffer,
+                new_particles: &particle_buffers.write_buffer,
+                sim_params: &sim_params_buffer,
+                system_state: &system_state_buffer,
+                escapes: &escape_buffer,
+                particle_attributes: &attribute_buffer,
+                species: &species_buffer,
+            },
+        );
+        let (reorder_bind_group, swapped_reorder_bind_group) = Self::create_reorder_bind_groups(
+            device,
+            nbody_bind_group_layout,
+            NBodyReorderSimBindings {
+                particles: &particle_buffers.read_buffer,
+                new_particles: &particle_buffers.write_buffer,
+                sim_params: &sim_params_buffer,
+            },
+        );
+        let instance_sets = Self::create_instance_sets(
+            device,
+            nbody_bind_group_layout,
+            2,
+            instance_capacity,
+            &species,
+            ParticleRenderMode::default(),
+            &cull_params_buffer,
+        );
+
+        let mut resources = Self {
+            sim_params,
+            particle_buffers,
+            particle_layout: ParticleLayout::default(),
+            sim_params_buffer,
+            system_state_buffer,
+            escape_buffer,
 
-        // Create a buffer for the particle data
+            attribute_schema,
+            attribute_buffer,
+            attribute_bind_group,
+
+            escape_staging_buffer,
+            escape_readback_in_flight: false,
+
+            bind_group,
+            swapped_bind_group,
+            reorder_bind_group,
+            swapped_reorder_bind_group,
+
+            species,
+            species_buffer,
+
+            instance_sets,
+            drawn_instance_set: 0,
+            instances_stale: true,
+            instances_view_dependent: false,
+            instance_format: InstanceFormat::default(),
+            render_mode: ParticleRenderMode::default(),
+            instance_capacity_limit: None,
+            grow_instances: true,
+            max_instance_buffer_size: device.limits().max_storage_buffer_binding_size as u64,
+            cull_params,
+            cull_params_buffer,
+            sprite_bind_group,
+
+            culling_staging_buffer,
+            culling_readback_in_flight: false,
+
+            particle_ids: Vec::new(),
+            next_particle_id: 0,
+            reorder_in_flight: false,
+        };
+
+        // Create the initial particle data
         let min_mass = 0.1;
         let max_mass = 0.11;
         let min_velocity = 0.1;
@@ -60,10 +279,7 @@ impl NBodySimResources {
         let dimensions = 10.0;
         let mut rng = rand::thread_rng();
 
-        let first_big_particle = GpuParticle {
-            position: Vec4::new(0.0, 0.0, 0.0, 500.0),
-            velocity: Vec4::new(0.0, 0.0, 0.0, 0.0),
-        };
+        let first_big_particle = GpuParticle::new(Vec3::ZERO, 500.0, Vec3::ZERO);
 
         let random_partictes: Vec<GpuParticle> = (0..num_particles)
             .map(|i| {
@@ -82,116 +298,1427 @@ impl NBodySimResources {
             })
             .collect();
 
-        let particle_buffer_a = BufferBuilder::<GpuParticle>::new(device)
-            .label("Particle Buffer Read")
-            .usage(BufferUsages::STORAGE | BufferUsages::COPY_DST)
-            .queue(queue)
-            .contents(&random_partictes)
+        resources.add_bodies(render_resources, nbody_bind_group_layout, &random_partictes);
+
+        resources
+    }
+
+    fn create_particle_buffers(
+        device: &wgpu::Device,
+        capacity: usize,
+    ) -> DynamicBuffer<GpuParticle> {
+        DynamicBuffer::<GpuParticle>::with_capacity(
+            device,
+            capacity,
+            BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+            "Particle Buffer",
+        )
+    }
+
+    /// The particles of the read buffer from `first_slot` on
+    fn read_slots(&self, first_slot: usize) -> ParticleSlots {
+        ParticleSlots::new(
+            &self.particle_buffers.read_buffer,
+            self.particle_buffers.capacity(),
+            first_slot,
+        )
+    }
+
+    /// Creates an instance buffer of `capacity` instances, split into a range per instance list.
+    /// It is sized for full instances, packed ones use the start of each list's range.
+    fn create_instance_buffer(
+        device: &wgpu::Device,
+        capacity: usize,
+    ) -> Buffer<GpuParticleInstance> {
+        BufferBuilder::<GpuParticleInstance>::new(device)
+            .label("Instance Buffer")
+            .size(capacity)
+            .usage(BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::VERTEX)
             .build()
-            .unwrap();
+            .unwrap()
+    }
 
-        let particle_buffer_b = BufferBuilder::<GpuParticle>::new(device)
-            .label("Particle Buffer Write")
-            .size(num_particles as usize)
-            .usage(BufferUsages::STORAGE | BufferUsages::COPY_DST)
+    fn create_indirect_buffer(
+        device: &wgpu::Device,
+        species: &[ParticleSpecies],
+        render_mode: ParticleRenderMode,
+    ) -> Buffer<GpuIndirectArgs> {
+        BufferBuilder::<GpuIndirectArgs>::new(device)
+            .label("Indirect Buffer")
+            .usage(BufferUsages::STORAGE | BufferUsages::INDIRECT | BufferUsages::COPY_DST)
+            .contents(&Self::initial_indirect_args(species, render_mode))
             .build()
+            .unwrap()
+    }
+
+    /// Creates `count` instance sets, each with a range of `capacity` instances per instance list
+    fn create_instance_sets(
+        device: &wgpu::Device,
+        layout: &NBodySimParamsUniformLayout,
+        count: usize,
+        capacity: usize,
+        species: &[ParticleSpecies],
+        render_mode: ParticleRenderMode,
+        cull_params: &Buffer<GpuCullParams>,
+    ) -> Vec<ParticleInstanceSet> {
+        (0..count)
+            .map(|_| {
+                let instance_buffer =
+                    Self::create_instance_buffer(device, capacity * Self::list_count(species));
+                let indirect_buffer = Self::create_indirect_buffer(device, species, render_mode);
+                let culling_counts = BufferBuilder::<GpuCullingCounts>::new(device)
+                    .label("Culling Counts Buffer")
+                    .usage(BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST)
+                    .contents(&[GpuCullingCounts::zeroed()])
+                    .build()
+                    .unwrap();
+                let bind_group = layout
+                    .create_instance_bind_group(
+                        device,
+                        &NBodyInstanceBindings {
+                            instance_buffer: &instance_buffer,
+                            indirect_buffer: &indirect_buffer,
+                            culling_counts: &culling_counts,
+                            cull_params,
+                        },
+                    )
+                    .unwrap();
+
+                ParticleInstanceSet {
+                    instance_buffer,
+                    indirect_buffer,
+                    culling_counts,
+                    bind_group,
+                }
+            })
+            .collect()
+    }
+
+    /// Replaces the instance sets with empty ones sized for the current capacity and species.
+    /// The instance capacity in the sim params follows, uploading them is up to the caller.
+    fn recreate_instance_sets(
+        &mut self,
+        device: &wgpu::Device,
+        layout: &NBodySimParamsUniformLayout,
+        count: usize,
+    ) {
+        let capacity = self.get_instance_capacity();
+        self.instance_sets = Self::create_instance_sets(
+            device,
+            layout,
+            count,
+            capacity,
+            &self.species,
+            self.render_mode,
+            &self.cull_params_buffer,
+        );
+        self.sim_params.instance_capacity = capacity as u32;
+        self.drawn_instance_set = 0;
+        self.instances_stale = true;
+    }
+
+    /// Empty draw arguments for every instance list, see `get_instance_list`.
+    /// Sprites draw the same quad at every level of detail.
+    fn initial_indirect_args(
+        species: &[ParticleSpecies],
+        render_mode: ParticleRenderMode,
+    ) -> Vec<GpuIndirectArgs> {
+        let lod_count = Self::lod_count(species);
+        species
+            .iter()
+            .flat_map(|species| {
+                (0..lod_count).map(move |lod| {
+                    let index_count = match render_mode {
+                        ParticleRenderMode::Mesh => species.get_lod_mesh(lod).0.filter.index_count,
+                        ParticleRenderMode::Sprite => SPRITE_INDICES.len() as u32,
+                    };
+                    GpuIndirectArgs::new(index_count, 0)
+                })
+            })
+            .collect()
+    }
+
+    /// Levels of detail every species is drawn at, those of the species with the most
+    fn lod_count(species: &[ParticleSpecies]) -> usize {
+        species
+            .iter()
+            .map(ParticleSpecies::get_lod_count)
+            .max()
+            .unwrap_or(1)
+    }
+
+    /// Instance lists of the instance sets, one per species and level of detail
+    fn list_count(species: &[ParticleSpecies]) -> usize {
+        species.len() * Self::lod_count(species)
+    }
+
+    /// The species settings padded to the fixed size array the shader expects
+    fn species_to_gpu(species: &[ParticleSpecies]) -> Vec<GpuSpecies> {
+        let mut gpu_species: Vec<GpuSpecies> =
+            species.iter().map(ParticleSpecies::to_gpu).collect();
+        gpu_species.resize(MAX_SPECIES, GpuSpecies::zeroed());
+        gpu_species
+    }
+
+    fn create_attribute_buffer(
+        device: &wgpu::Device,
+        schema: &ParticleAttributeSchema,
+        capacity: usize,
+    ) -> Buffer<u32> {
+        // an empty schema still needs a non-empty buffer to bind
+        BufferBuilder::<u32>::new(device)
+            .label("Particle Attribute Buffer")
+            .size(capacity * schema.stride().max(1))
+            .usage(BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST)
+            .build()
+            .unwrap()
+    }
+
+    /// Creates the bind group for the given bindings and the one used once the particle buffers swap
+    fn create_bind_groups(
+        device: &wgpu::Device,
+        layout: &NBodySimParamsUniformLayout,
+        bindings: NBodySimBindings,
+    ) -> (wgpu::BindGroup, wgpu::BindGroup) {
+        let bind_group = layout.create_bind_group(device, &bindings).unwrap();
+        let swapped_bind_group = layout
+            .create_bind_group(
+                device,
+                &NBodySimBindings {
+                    particles: bindings.new_particles,
+                    new_particles: bindings.particles,
+                    ..bindings
+                },
+            )
             .unwrap();
 
-        let sim_params_buffer = BufferBuilder::<GpuSimParams>::new(device)
-            .label("Sim Params Buffer")
-            .usage(BufferUsages::UNIFORM | BufferUsages::COPY_DST)
-            .contents(&[sim_params])
+        (bind_group, swapped_bind_group)
+    }
+
+    /// Creates group 0 of the reorder kernels and the one used once the particle buffers swap
+    fn create_reorder_bind_groups(
+        device: &wgpu::Device,
+        layout: &NBodySimParamsUniformLayout,
+        bindings: NBodyReorderSimBindings,
+    ) -> (wgpu::BindGroup, wgpu::BindGroup) {
+        let bind_group = layout
+            .create_reorder_sim_bind_group(device, &bindings)
+            .unwrap();
+        let swapped_bind_group = layout
+            .create_reorder_sim_bind_group(
+                device,
+                &NBodyReorderSimBindings {
+                    particles: bindings.new_particles,
+                    new_particles: bindings.particles,
+                    ..bindings
+                },
+            )
+            .unwrap();
+
+        (bind_group, swapped_bind_group)
+    }
+
+    fn rebuild_bind_groups(&mut self, device: &wgpu::Device, layout: &NBodySimParamsUniformLayout) {
+        let (bind_group, swapped_bind_group) = Self::create_bind_groups(
+            device,
+            layout,
+            NBodySimBindings {
+                particles: &self.particle_buffers.read_buffer,
+                new_particles: &self.particle_buffers.write_buffer,
+                sim_params: &self.sim_params_buffer,
+                system_state: &self.system_state_buffer,
+                escapes: &self.escape_buffer,
+                particle_attributes: &self.attribute_buffer,
+                species: &self.species_buffer,
+            },
+        );
+        self.bind_group = bind_group;
+        self.swapped_bind_group = swapped_bind_group;
+        let (reorder_bind_group, swapped_reorder_bind_group) = Self::create_reorder_bind_groups(
+            device,
+            layout,
+            NBodyReorderSimBindings {
+                particles: &self.particle_buffers.read_buffer,
+                new_particles: &self.particle_buffers.write_buffer,
+                sim_params: &self.sim_params_buffer,
+            },
+        );
+        self.reorder_bind_group = reorder_bind_group;
+        self.swapped_reorder_bind_group = swapped_reorder_bind_group;
+        self.attribute_bind_group =
+            layout.create_attribute_bind_group(device, &self.attribute_buffer);
+    }
+
+    /// Makes sure the particle and instance buffers can hold `capacity` particles.
+    /// Growing reallocates the buffers and bind groups, the current particle state is copied over.
+    fn ensure_capacity(
+        &mut self,
+        render_resources: &RenderResources,
+        layout: &NBodySimParamsUniformLayout,
+        capacity: usize,
+    ) {
+        if capacity <= self.particle_buffers.capacity() {
+            return;
+        }
+
+        let (device, queue) = render_resources.get_device_queue();
+        let new_capacity = capacity.next_power_of_two().max(MIN_PARTICLE_CAPACITY);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Grow Particle Buffers Encoder"),
+        });
+        // the layout places the velocities by capacity, so the state is copied a range at a time
+        let particle_buffers = Self::create_particle_buffers(device, new_capacity);
+        self.particle_layout.copy(
+            &mut encoder,
+            self.read_slots(0),
+            ParticleSlots::new(&particle_buffers.read_buffer, new_capacity, 0),
+            self.particle_ids.len(),
+        );
+        self.particle_buffers = particle_buffers;
+
+        let attribute_buffer =
+            Self::create_attribute_buffer(device, &self.attribute_schema, new_capacity);
+        self.attribute_buffer.copy_to(
+            &mut encoder,
+            0,
+            &attribute_buffer,
+            0,
+            self.particle_ids.len() * self.attribute_schema.stride(),
+        );
+        self.attribute_buffer = attribute_buffer;
+
+        queue.submit(std::iter::once(encoder.finish()));
+
+        self.recreate_instance_sets(device, layout, self.instance_sets.len());
+        self.sim_params_buffer.update(queue, &[self.sim_params], 0);
+
+        self.rebuild_bind_groups(device, layout);
+    }
+
+    fn set_particle_count(&mut self, queue: &wgpu::Queue, count: usize) {
+        self.sim_params.num_particles = count as u32;
+        self.sim_params_buffer.update(queue, &[self.sim_params], 0);
+        self.instances_stale = true;
+    }
+
+    /// Appends the given bodies to the simulation, growing the gpu buffers if needed.
+    /// Each body is assigned a new persistent id, overwriting any id it already had.
+    /// Returns the ids assigned to the new bodies, in the same order.
+    pub fn add_bodies(
+        &mut self,
+        render_resources: &RenderResources,
+        layout: &NBodySimParamsUniformLayout,
+        bodies: &[GpuParticle],
+    ) -> Vec<u32> {
+        if bodies.is_empty() {
+            return Vec::new();
+        }
+
+        let first_slot = self.particle_ids.len();
+        let new_count = first_slot + bodies.len();
+        self.ensure_capacity(render_resources, layout, new_count);
+
+        let ids: Vec<u32> = (0..bodies.len() as u32)
+            .map(|i| self.next_particle_id + i)
+            .collect();
+        self.next_particle_id += bodies.len() as u32;
+        self.particle_ids.extend_from_slice(&ids);
+
+        let bodies: Vec<GpuParticle> = bodies
+            .iter()
+            .zip(&ids)
+            .map(|(body, id)| GpuParticle { id: *id, ..*body })
+            .collect();
+
+        // the write is queued after any copy submitted while growing
+        let queue = &render_resources.queue;
+        self.particle_layout
+            .write(queue, self.read_slots(first_slot), &bodies);
+
+        self.write_default_attributes(queue, first_slot, bodies.len());
+        self.set_particle_count(queue, new_count);
+
+        ids
+    }
+
+    /// Resets the attributes of `count` slots starting at `first_slot` to the schema defaults
+    fn write_default_attributes(&self, queue: &wgpu::Queue, first_slot: usize, count: usize) {
+        let stride = self.attribute_schema.stride();
+        if stride == 0 || count == 0 {
+            return;
+        }
+
+        let words = self.attribute_schema.default_words().repeat(count);
+        self.attribute_buffer
+            .update(queue, &words, first_slot * stride);
+    }
+
+    /// Removes the bodies with the given ids from the simulation.
+    /// The surviving particles are compacted on the gpu, keeping their order and state.
+    /// Returns the ids of the bodies that were removed, ids that are not in the simulation
+    /// (e.g. removed already) are left out. Nothing is removed while a reorder is in flight.
+    pub fn remove_bodies(
+        &mut self,
+        render_resources: &RenderResources,
+        ids: &[u32],
+    ) -> HashSet<u32> {
+        if self.reorder_in_flight {
+            return HashSet::new();
+        }
+
+        let ids: HashSet<u32> = ids.iter().copied().collect();
+        let keep: Vec<bool> = self
+            .particle_ids
+            .iter()
+            .map(|id| !ids.contains(id))
+            .collect();
+
+        let removed: HashSet<u32> = self
+            .particle_ids
+            .iter()
+            .zip(&keep)
+            .filter(|&(_, &kept)| !kept)
+            .map(|(&id, _)| id)
+            .collect();
+        if removed.is_empty() {
+            return removed;
+        }
+
+        let (device, queue) = render_resources.get_device_queue();
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Compact Particle Buffers Encoder"),
+        });
+
+        // the attributes are not double buffered, so their runs go through a scratch buffer
+        let stride = self.attribute_schema.stride();
+        let attribute_scratch_buffer = BufferBuilder::<u32>::new(device)
+            .label("Particle Attribute Scratch Buffer")
+            .size((keep.len() - removed.len()).max(1) * stride.max(1))
+            .usage(BufferUsages::COPY_SRC | BufferUsages::COPY_DST)
             .build()
             .unwrap();
 
-        let instance_buffer = BufferBuilder::<GpuParticleInstance>::new(device)
-            .label("Instance Buffer")
-            .size(num_particles as usize)
-            .usage(BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::VERTEX)
+        // copy each run of surviving particles into the write buffer, then make it the read buffer
+        let mut compacted = 0;
+        let mut slot = 0;
+        while slot < keep.len() {
+            if !keep[slot] {
+                slot += 1;
+                continue;
+            }
+
+            let run_start = slot;
+            while slot < keep.len() && keep[slot] {
+                slot += 1;
+            }
+
+            let run_length = slot - run_start;
+            self.particle_layout.copy(
+                &mut encoder,
+                self.read_slots(run_start),
+                ParticleSlots::new(
+                    &self.particle_buffers.write_buffer,
+                    self.particle_buffers.capacity(),
+                    compacted,
+                ),
+                run_length,
+            );
+            self.attribute_buffer.copy_to(
+                &mut encoder,
+                run_start * stride,
+                &attribute_scratch_buffer,
+                compacted * stride,
+                run_length * stride,
+            );
+            compacted += run_length;
+        }
+
+        attribute_scratch_buffer.copy_to(
+            &mut encoder,
+            0,
+            &self.attribute_buffer,
+            0,
+            compacted * stride,
+        );
+
+        queue.submit(std::iter::once(encoder.finish()));
+        self.swap_buffers();
+
+        let mut keep = keep.into_iter();
+        self.particle_ids.retain(|_| keep.next().unwrap_or(false));
+        self.set_particle_count(queue, self.particle_ids.len());
+
+        removed
+    }
+
+    /// Removes every body from the simulation. The gpu buffers keep their capacity.
+    pub fn clear_bodies(&mut self, queue: &wgpu::Queue) {
+        self.reorder_in_flight = false;
+        self.particle_ids.clear();
+        self.set_particle_count(queue, 0);
+    }
+
+    /// Replaces every body with the given ones, keeping the ids they carry.
+    /// Attributes are kept when the ids match the current bodies slot for slot,
+    /// otherwise every body is reset to the schema defaults.
+    pub fn set_bodies(
+        &mut self,
+        render_resources: &RenderResources,
+        layout: &NBodySimParamsUniformLayout,
+        bodies: &[GpuParticle],
+    ) {
+        let count = bodies.len();
+        self.ensure_capacity(render_resources, layout, count);
+
+        let ids: Vec<u32> = bodies.iter().map(|body| body.id).collect();
+        let queue = &render_resources.queue;
+        self.particle_layout
+            .write(queue, self.read_slots(0), bodies);
+        if self.reorder_in_flight || ids != self.particle_ids {
+            self.write_default_attributes(queue, 0, count);
+        }
+        self.reorder_in_flight = false;
+
+        // ids are never handed out twice
+        if let Some(max_id) = ids.iter().max() {
+            self.next_particle_id = self.next_particle_id.max(max_id + 1);
+        }
+        self.particle_ids = ids;
+        self.set_particle_count(queue, count);
+    }
+
+    /// Copies the current particles back to the cpu, waiting for the gpu to finish.
+    /// Only meant for tools and tests, this stalls the frame. The web can't wait on the gpu.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn read_particles_blocking(&self, render_resources: &RenderResources) -> Vec<GpuParticle> {
+        let count = self.particle_ids.len();
+        if count == 0 {
+            return Vec::new();
+        }
+
+        let (device, queue) = render_resources.get_device_queue();
+        let staging_buffer = BufferBuilder::<GpuParticle>::new(device)
+            .label("Particle Readback Buffer")
+            .size(count)
+            .usage(BufferUsages::MAP_READ | BufferUsages::COPY_DST)
             .build()
             .unwrap();
 
-        let indirect_buffer = BufferBuilder::<GpuIndirectArgs>::new(device)
-            .label("Indirect Buffer")
-            .usage(BufferUsages::STORAGE | BufferUsages::INDIRECT | BufferUsages::COPY_DST)
-            .queue(queue)
-            .contents(&[GpuIndirectArgs::new(
-                particle_mesh_filter.filter.index_count,
-                0,
-            )])
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Particle Readback Encoder"),
+        });
+        self.copy_particles_to(&mut encoder, &staging_buffer);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let (sender, receiver) = crossbeam::channel::bounded(1);
+        staging_buffer
+            .slice()
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        device.poll(wgpu::Maintain::Wait);
+
+        if !matches!(receiver.recv(), Ok(Ok(()))) {
+            return Vec::new();
+        }
+
+        let particles = self
+            .particle_layout
+            .read(&staging_buffer.slice().get_mapped_range(), count);
+        staging_buffer.buffer.unmap();
+
+        particles
+    }
+
+    /// The softening every body is simulated with, its species' override or the simulation's,
+    /// waiting for the gpu to finish. Only meant for handing the state to the cpu backend.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn read_softening_blocking(&self, render_resources: &RenderResources) -> Vec<f32> {
+        let count = self.particle_ids.len();
+        let species_of = |word: u32| (word as usize).min(self.species.len() - 1);
+        let species: Vec<usize> = match self.attribute_schema.index_of("species") {
+            Some(offset) if count > 0 => {
+                let stride = self.attribute_schema.stride();
+                self.read_attributes_blocking(render_resources, count)
+                    .chunks_exact(stride)
+                    .map(|words| species_of(words[offset]))
+                    .collect()
+            }
+            _ => vec![0; count],
+        };
+
+        species
+            .into_iter()
+            .map(|species| {
+                self.species[species]
+                    .softening
+                    .unwrap_or(self.sim_params.softening)
+            })
+            .collect()
+    }
+
+    /// Copies the attribute words of the first `count` slots back to the cpu, waiting for the gpu
+    #[cfg(not(target_arch = "wasm32"))]
+    fn read_attributes_blocking(
+        &self,
+        render_resources: &RenderResources,
+        count: usize,
+    ) -> Vec<u32> {
+        let words = count * self.attribute_schema.stride();
+        let (device, queue) = render_resources.get_device_queue();
+        let staging_buffer = BufferBuilder::<u32>::new(device)
+            .label("Attribute Readback Buffer")
+            .size(words.max(1))
+            .usage(BufferUsages::MAP_READ | BufferUsages::COPY_DST)
             .build()
             .unwrap();
 
-        let bind_group_a = nbody_bind_group_layout.create_bind_group(
-            device,
-            &particle_buffer_a,
-            &particle_buffer_b,
-            &sim_params_buffer,
-            &instance_buffer,
-            &indirect_buffer,
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Attribute Readback Encoder"),
+        });
+        self.attribute_buffer
+            .copy_to(&mut encoder, 0, &staging_buffer, 0, words);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let (sender, receiver) = crossbeam::channel::bounded(1);
+        staging_buffer
+            .slice()
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        device.poll(wgpu::Maintain::Wait);
+
+        if !matches!(receiver.recv(), Ok(Ok(()))) {
+            return Vec::new();
+        }
+
+        let attributes =
+            bytemuck::pod_collect_to_vec(&staging_buffer.slice().get_mapped_range()[..words * 4]);
+        staging_buffer.buffer.unmap();
+        attributes
+    }
+
+    /// Writes instances built on the cpu in place of the compute shader's, one list per species and
+    /// level of detail as numbered by `get_instance_list`. Missing lists are left empty.
+    /// They are drawn from this frame on. Lists longer than the instance capacity are truncated.
+    /// `culled` holds the particles the lists left out, the drawn and dropped counts are filled in.
+    pub fn upload_instances(
+        &mut self,
+        queue: &wgpu::Queue,
+        instance_lists: &[Vec<GpuParticleInstance>],
+        culled: GpuCullingCounts,
+    ) {
+        let capacity = self.sim_params.instance_capacity as usize;
+        let mut indirect_args = Self::initial_indirect_args(&self.species, self.render_mode);
+        let mut culling_counts = GpuCullingCounts {
+            drawn: 0,
+            dropped: 0,
+            ..culled
+        };
+        let instance_set = self.get_generated_instance_set();
+        let instance_size = self.instance_format.get_instance_size();
+
+        for (list, (instances, args)) in instance_lists
+            .iter()
+            .zip(indirect_args.iter_mut())
+            .enumerate()
+        {
+            let count = instances.len().min(capacity);
+            if count > 0 {
+                queue.write_buffer(
+                    &instance_set.instance_buffer.buffer,
+                    (list * capacity) as u64 * instance_size,
+                    &self.instance_format.encode(&instances[..count]),
+                );
+            }
+            args.instance_count = count as u32;
+            culling_counts.drawn += count as u32;
+            culling_counts.dropped += (instances.len() - count) as u32;
+        }
+
+        instance_set
+            .indirect_buffer
+            .update(queue, &indirect_args, 0);
+        instance_set
+            .culling_counts
+            .update(queue, &[culling_counts], 0);
+        self.present_instances();
+    }
+
+    pub fn get_sim_params(&self) -> &GpuSimParams {
+        &self.sim_params
+    }
+
+    /// Records a copy of the current particles into the given buffer, which must hold them all.
+    /// The copy is in the current particle layout laid out for the particle count,
+    /// `get_particle_layout().read` decodes it.
+    pub fn copy_particles_to(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        particles: &Buffer<GpuParticle>,
+    ) {
+        let count = self.particle_ids.len();
+        self.particle_layout.copy(
+            encoder,
+            self.read_slots(0),
+            ParticleSlots::new(particles, count, 0),
+            count,
         );
-        let bind_group_b = nbody_bind_group_layout.create_bind_group(
+    }
+
+    /// Records a copy of the current particles and their attributes into the given buffers,
+    /// the particles laid out as by `copy_particles_to`
+    pub fn copy_state_to(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        particles: &Buffer<GpuParticle>,
+        attributes: &Buffer<u32>,
+    ) {
+        let count = self.particle_ids.len();
+        self.copy_particles_to(encoder, particles);
+        self.attribute_buffer.copy_to(
+            encoder,
+            0,
+            attributes,
+            0,
+            count * self.attribute_schema.stride(),
+        );
+    }
+
+    /// Replaces the simulation state with one copied by `copy_state_to` in the current particle layout.
+    /// Without attributes every body is reset to the schema defaults.
+    /// Ids are never handed out twice, even if the restored bodies were removed since.
+    pub fn restore_state(
+        &mut self,
+        render_resources: &RenderResources,
+        layout: &NBodySimParamsUniformLayout,
+        particles: &Buffer<GpuParticle>,
+        attributes: Option<&Buffer<u32>>,
+        particle_ids: &[u32],
+    ) {
+        let count = particle_ids.len();
+        self.ensure_capacity(render_resources, layout, count);
+
+        let (device, queue) = render_resources.get_device_queue();
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Restore Particle Buffers Encoder"),
+        });
+        self.particle_layout.copy(
+            &mut encoder,
+            ParticleSlots::new(particles, count, 0),
+            self.read_slots(0),
+            count,
+        );
+        if let Some(attributes) = attributes {
+            attributes.copy_to(
+                &mut encoder,
+                0,
+                &self.attribute_buffer,
+                0,
+                count * self.attribute_schema.stride(),
+            );
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+
+        if attributes.is_none() {
+            self.write_default_attributes(queue, 0, count);
+        }
+
+        self.reorder_in_flight = false;
+        self.particle_ids = particle_ids.to_vec();
+        self.set_particle_count(queue, count);
+    }
+
+    /// Replaces the per-particle attribute schema.
+    /// The attribute buffer is reallocated and every existing particle is reset to the new defaults.
+    pub fn set_attribute_schema(
+        &mut self,
+        render_resources: &RenderResources,
+        layout: &NBodySimParamsUniformLayout,
+        schema: ParticleAttributeSchema,
+    ) {
+        let device = &render_resources.device;
+
+        self.attribute_schema = schema;
+        self.attribute_buffer = Self::create_attribute_buffer(
             device,
-            &particle_buffer_b,
-            &particle_buffer_a,
-            &sim_params_buffer,
-            &instance_buffer,
-            &indirect_buffer,
+            &self.attribute_schema,
+            self.particle_buffers.capacity(),
         );
+        self.write_default_attributes(&render_resources.queue, 0, self.particle_ids.len());
+        self.update_attribute_params(&render_resources.queue);
+        self.instances_stale = true;
 
-        Self {
-            sim_params,
-            particle_buffer_a,
-            particle_buffer_b,
-            sim_params_buffer,
-            instance_buffer,
-            indirect_buffer,
+        self.rebuild_bind_groups(device, layout);
+    }
+
+    /// Tells the simulation where to find the attributes it reads itself
+    fn update_attribute_params(&mut self, queue: &wgpu::Queue) {
+        self.sim_params.attribute_stride = self.attribute_schema.stride() as u32;
+        self.sim_params.species_offset = self
+            .attribute_schema
+            .index_of("species")
+            .map_or(GpuSimParams::NO_ATTRIBUTE, |offset| offset as u32);
+        self.sim_params_buffer.update(queue, &[self.sim_params], 0);
+    }
+
+    pub fn get_attribute_schema(&self) -> &ParticleAttributeSchema {
+        &self.attribute_schema
+    }
+
+    /// Sets an attribute of the body with the given id
+    pub fn set_attribute(
+        &self,
+        queue: &wgpu::Queue,
+        id: u32,
+        name: &str,
+        value: ParticleAttributeValue,
+    ) -> Result<(), String> {
+        let offset = self
+            .attribute_schema
+            .index_of(name)
+            .ok_or(format!("Unknown particle attribute '{}'", name))?;
+
+        let attribute = &self.attribute_schema.attributes()[offset];
+        if attribute.ty() != value.ty() {
+            return Err(format!(
+                "Particle attribute '{}' is {:?}, got {:?}",
+                name,
+                attribute.ty(),
+                value.ty()
+            ));
+        }
+
+        if self.reorder_in_flight {
+            return Err("The bodies are being reordered, try again next frame".to_string());
+        }
+
+        let slot = self
+            .particle_ids
+            .iter()
+            .position(|particle_id| *particle_id == id)
+            .ok_or(format!("No body with id {}", id))?;
+
+        self.attribute_buffer.update(
+            queue,
+            &[value.to_bits()],
+            slot * self.attribute_schema.stride() + offset,
+        );
+
+        Ok(())
+    }
+
+    /// Replaces the particle species, there must be between 1 and `MAX_SPECIES` of them.
+    /// Bodies whose `species` attribute is out of range are drawn as the last species.
+    pub fn set_species(
+        &mut self,
+        render_resources: &RenderResources,
+        layout: &NBodySimParamsUniformLayout,
+        species: Vec<ParticleSpecies>,
+    ) {
+        assert!(
+            (1..=MAX_SPECIES).contains(&species.len()),
+            "Expected between 1 and {} particle species, got {}",
+            MAX_SPECIES,
+            species.len()
+        );
+
+        let (device, queue) = render_resources.get_device_queue();
+
+        self.species = species;
+        self.species_buffer
+            .update(queue, &Self::species_to_gpu(&self.species), 0);
+
+        self.cull_params.lod_count = Self::lod_count(&self.species) as u32;
+        self.cull_params_buffer
+            .update(queue, &[self.cull_params], 0);
+        self.recreate_instance_sets(device, layout, self.instance_sets.len());
+
+        self.sim_params.species_count = self.species.len() as u32;
+        self.sim_params_buffer.update(queue, &[self.sim_params], 0);
+
+        self.rebuild_bind_groups(device, layout);
+    }
+
+    pub fn get_species(&self) -> &[ParticleSpecies] {
+        &self.species
+    }
+
+    /// Appends bodies of the given species, the attribute schema must have a `species` attribute.
+    /// Returns the ids assigned to the new bodies, in the same order.
+    pub fn add_bodies_of_species(
+        &mut self,
+        render_resources: &RenderResources,
+        layout: &NBodySimParamsUniformLayout,
+        species: u32,
+        bodies: &[GpuParticle],
+    ) -> Result<Vec<u32>, String> {
+        let offset = self
+            .attribute_schema
+            .index_of("species")
+            .ok_or("The particle attribute schema has no species attribute")?;
+
+        if species as usize >= self.species.len() {
+            return Err(format!("No particle species {}", species));
+        }
+
+        let first_slot = self.particle_ids.len();
+        let ids = self.add_bodies(render_resources, layout, bodies);
+
+        let mut words = self.attribute_schema.default_words();
+        words[offset] = species;
+        self.attribute_buffer.update(
+            &render_resources.queue,
+            &words.repeat(bodies.len()),
+            first_slot * self.attribute_schema.stride(),
+        );
+
+        Ok(ids)
+    }
+
+    /// Swaps the particle buffers so the state written by the last dispatch becomes the read state.
+    /// This should be called once after every dispatch of the simulation.
+    pub fn swap_buffers(&mut self) {
+        self.particle_buffers.swap();
+        std::mem::swap(&mut self.bind_group, &mut self.swapped_bind_group);
+        std::mem::swap(
+            &mut self.reorder_bind_group,
+            &mut self.swapped_reorder_bind_group,
+        );
+        self.instances_stale = true;
+    }
+
+    /// Records copying attributes gathered into `source` in the order of the particles in the
+    /// write buffer over the current ones, see `begin_reorder`
+    pub fn copy_attributes_from(&self, encoder: &mut wgpu::CommandEncoder, source: &Buffer<u32>) {
+        source.copy_to(
+            encoder,
+            0,
+            &self.attribute_buffer,
+            0,
+            self.particle_ids.len() * self.attribute_schema.stride(),
+        );
+    }
+
+    /// Takes the particles gathered into the write buffer as the current state.
+    /// The ids follow in `apply_reorder` once the order is read back.
+    pub fn begin_reorder(&mut self) {
+        self.swap_buffers();
+        self.reorder_in_flight = true;
+    }
+
+    /// Moves the ids to the slots the reorder moved their particles to.
+    /// Slot `i` now holds the particle that was in slot `permutation[i]`, bodies added since
+    /// stay after them. Ignored when the bodies were replaced since the reorder.
+    pub fn apply_reorder(&mut self, permutation: &[u32]) {
+        if !self.reorder_in_flight {
+            return;
+        }
+
+        self.reorder_in_flight = false;
+        self.particle_ids = permute_slots(&self.particle_ids, permutation, 1);
+    }
+
+    /// Gives up on the ids following a reorder whose order couldn't be read back.
+    /// They no longer match their slots until the bodies are replaced.
+    pub fn abandon_reorder(&mut self) {
+        self.reorder_in_flight = false;
+    }
+
+    pub fn is_reorder_in_flight(&self) -> bool {
+        self.reorder_in_flight
+    }
+
+    /// Switches how the particle buffers store the particles, converting the current state.
+    /// Only meant for tools and benchmarks, the conversion reads the particles back and stalls.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_particle_layout(
+        &mut self,
+        render_resources: &RenderResources,
+        layout: ParticleLayout,
+    ) {
+        if layout == self.particle_layout {
+            return;
+        }
+
+        let particles = self.read_particles_blocking(render_resources);
+        self.particle_layout = layout;
+        self.particle_layout
+            .write(&render_resources.queue, self.read_slots(0), &particles);
+        self.instances_stale = true;
+    }
+
+    pub fn get_particle_layout(&self) -> ParticleLayout {
+        self.particle_layout
+    }
+
+    /// Switches the format the instances are generated and drawn in.
+    /// The instances are generated again on the next frame, until then none are drawn.
+    pub fn set_instance_format(&mut self, queue: &wgpu::Queue, instance_format: InstanceFormat) {
+        if instance_format == self.instance_format {
+            return;
+        }
+
+        self.instance_format = instance_format;
+        self.instances_stale = true;
+
+        // the drawn instances are still in the old format
+        self.get_drawn_instance_set().indirect_buffer.update(
+            queue,
+            &Self::initial_indirect_args(&self.species, self.render_mode),
+            0,
+        );
+    }
+
+    pub fn get_instance_format(&self) -> InstanceFormat {
+        self.instance_format
+    }
+
+    /// Switches between drawing the instances as meshes and as sprites.
+    /// The instances are generated again on the next frame, until then none are drawn.
+    pub fn set_render_mode(&mut self, queue: &wgpu::Queue, render_mode: ParticleRenderMode) {
+        if render_mode == self.render_mode {
+            return;
+        }
+
+        self.render_mode = render_mode;
+        self.instances_stale = true;
+
+        // the drawn arguments still hold the index counts of the old mode
+        self.get_drawn_instance_set().indirect_buffer.update(
+            queue,
+            &Self::initial_indirect_args(&self.species, self.render_mode),
+            0,
+        );
+    }
+
+    pub fn get_render_mode(&self) -> ParticleRenderMode {
+        self.render_mode
+    }
+
+    /// Whether the particle state changed since the instances were last generated
+    pub fn are_instances_stale(&self) -> bool {
+        self.instances_stale
+    }
+
+    /// Has the instances generated again on the next frame
+    pub fn mark_instances_stale(&mut self) {
+        self.instances_stale = true;
+    }
+
+    /// Makes the instance set the instances were just generated into the one that is drawn.
+    /// This should be called once the frame that generated them has been recorded.
+    pub fn present_instances(&mut self) {
+        if !self.instances_view_dependent {
+            self.drawn_instance_set = (self.drawn_instance_set + 1) % self.instance_sets.len();
+        }
+        self.instances_stale = false;
+        self.instances_view_dependent = false;
+    }
+
+    /// With double buffering the instances are generated into one set while the frame draws the
+    /// ones generated the frame before, so the draw doesn't wait on the compute pass at the cost
+    /// of a frame of latency. The instances are generated again on the next frame.
+    /// Frames whose camera changed the culling or the levels of detail generate into the drawn
+    /// set and wait on the compute pass, see `stage_camera`.
+    pub fn set_double_buffered_instances(
+        &mut self,
+        device: &wgpu::Device,
+        layout: &NBodySimParamsUniformLayout,
+        double_buffered: bool,
+    ) {
+        let count = if double_buffered { 2 } else { 1 };
+        if count != self.instance_sets.len() {
+            self.recreate_instance_sets(device, layout, count);
+        }
+    }
+
+    pub fn is_double_buffered_instances(&self) -> bool {
+        self.instance_sets.len() > 1
+    }
+
+    /// Instances each species' range of the instance buffer holds
+    pub fn get_instance_capacity(&self) -> usize {
+        let capacity = self.particle_buffers.capacity();
+        let list_count = Self::list_count(&self.species);
+        let max_capacity = self.max_instance_buffer_size as usize
+            / (list_count * std::mem::size_of::<GpuParticleInstance>());
+
+        self.instance_capacity_limit
+            .unwrap_or(capacity / list_count)
+            .min(capacity)
+            .min(max_capacity)
+            .max(1)
+    }
+
+    /// Sets the instances each species and level of detail can draw, at most the particle
+    /// capacity and what fits in one storage binding.
+    /// Instances beyond the limit are dropped and counted, see `CullingStats`.
+    /// `None` splits the particle capacity between the instance lists, so the instance buffers
+    /// hold as many instances as there are particles. Lists holding more than their share drop
+    /// instances until `grow_instance_capacity` makes room.
+    pub fn set_instance_capacity_limit(
+        &mut self,
+        render_resources: &RenderResources,
+        layout: &NBodySimParamsUniformLayout,
+        limit: Option<usize>,
+    ) {
+        let capacity = self.get_instance_capacity();
+        self.instance_capacity_limit = limit;
+        if self.get_instance_capacity() == capacity {
+            return;
+        }
+
+        let (device, queue) = render_resources.get_device_queue();
+        self.recreate_instance_sets(device, layout, self.instance_sets.len());
+        self.sim_params_buffer.update(queue, &[self.sim_params], 0);
+    }
 
-            bind_group_a,
-            bind_group_b,
+    pub fn get_instance_capacity_limit(&self) -> Option<usize> {
+        self.instance_capacity_limit
+    }
+
+    /// Raises the instance capacity limit when instances are dropped, see `grow_instance_capacity`
+    pub fn set_grow_instances(&mut self, grow_instances: bool) {
+        self.grow_instances = grow_instances;
+    }
+
+    pub fn is_growing_instances(&self) -> bool {
+        self.grow_instances
+    }
+
+    /// Raises the instance capacity limit so the `dropped` instances would have fit, if growing
+    /// is enabled. No list dropped more than all of them, so the new limit is enough for any.
+    /// It stops at what fits in one storage binding. Returns whether the instance buffers grew.
+    pub fn grow_instance_capacity(
+        &mut self,
+        render_resources: &RenderResources,
+        layout: &NBodySimParamsUniformLayout,
+        dropped: u32,
+    ) -> bool {
+        if !self.grow_instances || dropped == 0 {
+            return false;
+        }
+
+        let capacity = self.get_instance_capacity();
+        let new_limit = (capacity + dropped as usize).next_power_of_two();
+        self.set_instance_capacity_limit(render_resources, layout, Some(new_limit));
+        self.get_instance_capacity() > capacity
+    }
+
+    /// Culls the instances outside the view frustum of the matrix given to `stage_camera`
+    pub fn set_frustum_culling(&mut self, queue: &wgpu::Queue, enabled: bool) {
+        self.cull_params.frustum_enabled = enabled as u32;
+        self.cull_params_buffer
+            .update(queue, &[self.cull_params], 0);
+        self.instances_stale = true;
+    }
+
+    pub fn is_frustum_culling(&self) -> bool {
+        self.cull_params.frustum_enabled != 0
+    }
+
+    /// Culls the particles whose distance from the origin is outside `min..=max`,
+    /// or culls none by distance when `None`
+    pub fn set_distance_band(&mut self, queue: &wgpu::Queue, band: Option<(f32, f32)>) {
+        if let Some((min_distance, max_distance)) = band {
+            self.sim_params.min_distance = min_distance;
+            self.sim_params.max_distance = max_distance;
+            self.sim_params_buffer.update(queue, &[self.sim_params], 0);
+        }
+        self.cull_params.range_enabled = band.is_some() as u32;
+        self.cull_params_buffer
+            .update(queue, &[self.cull_params], 0);
+        self.instances_stale = true;
+    }
+
+    pub fn get_distance_band(&self) -> Option<(f32, f32)> {
+        (self.cull_params.range_enabled != 0)
+            .then_some((self.sim_params.min_distance, self.sim_params.max_distance))
+    }
+
+    /// The radius of the particle meshes at an instance size of 1, the frustum culls by it and the
+    /// sprites are drawn at it
+    pub fn set_bounding_radius(&mut self, queue: &wgpu::Queue, bounding_radius: f32) {
+        self.cull_params.bounding_radius = bounding_radius;
+        self.cull_params_buffer
+            .update(queue, &[self.cull_params], 0);
+        self.instances_stale = true;
+    }
+
+    pub fn get_bounding_radius(&self) -> f32 {
+        self.cull_params.bounding_radius
+    }
+
+    /// Stages the camera's view-projection and pixels per unit at a clip space w of 1 with the
+    /// frame's other uploads, see `StagingBelt`. The instances are generated again when the camera
+    /// changed while it decides the culling or the levels of detail.
+    /// Those instances are generated into the set drawn this frame, even with double buffering,
+    /// as the ones generated for the next frame would be culled with a view a frame old and
+    /// particles entering the view would pop in a frame late.
+    pub fn stage_camera(
+        &mut self,
+        device: &wgpu::Device,
+        belt: &mut StagingBelt,
+        view_proj: Mat4,
+        screen_scale: f32,
+    ) {
+        if view_proj == self.cull_params.view_proj && screen_scale == self.cull_params.screen_scale
+        {
+            return;
+        }
+
+        self.cull_params.view_proj = view_proj;
+        self.cull_params.screen_scale = screen_scale;
+        self.cull_params_buffer
+            .stage(device, belt, &[self.cull_params], 0);
+        if self.is_frustum_culling() || self.get_lod_count() > 1 {
+            self.instances_stale = true;
+            self.instances_view_dependent = true;
+        }
+    }
+
+    /// Levels of detail every species is drawn at, see `ParticleSpecies::with_lods`
+    pub fn get_lod_count(&self) -> usize {
+        self.cull_params.lod_count as usize
+    }
+
+    /// The smallest diameter in pixels each level of detail but the coarsest is drawn at,
+    /// particles smaller on screen use the next level. Sizes past `MAX_LODS - 1` are ignored.
+    pub fn set_lod_screen_sizes(&mut self, queue: &wgpu::Queue, screen_sizes: &[f32]) {
+        let mut lod_screen_sizes = [0.0; MAX_LODS];
+        for (lod_screen_size, screen_size) in lod_screen_sizes
+            .iter_mut()
+            .zip(screen_sizes.iter().take(MAX_LODS - 1))
+        {
+            *lod_screen_size = *screen_size;
+        }
+
+        self.cull_params.lod_screen_sizes = lod_screen_sizes.into();
+        self.cull_params_buffer
+            .update(queue, &[self.cull_params], 0);
+        self.instances_stale = true;
+    }
+
+    pub fn get_lod_screen_sizes(&self) -> Vec<f32> {
+        self.cull_params.lod_screen_sizes.to_array()[..self.get_lod_count() - 1].to_vec()
+    }
+
+    pub fn get_cull_params(&self) -> &GpuCullParams {
+        &self.cull_params
+    }
+
+    fn get_drawn_instance_set(&self) -> &ParticleInstanceSet {
+        &self.instance_sets[self.drawn_instance_set]
+    }
+
+    // the set the drawn one swaps with, the same set when single buffered or when the instances
+    // depend on this frame's view
+    fn get_generated_instance_set(&self) -> &ParticleInstanceSet {
+        if self.instances_view_dependent {
+            return self.get_drawn_instance_set();
+        }
+        &self.instance_sets[(self.drawn_instance_set + 1) % self.instance_sets.len()]
+    }
+
+    /// Enables removal of unbound particles beyond the given distance from the center of mass,
+    /// or disables it when `None`
+    pub fn set_escape_radius(&mut self, queue: &wgpu::Queue, escape_radius: Option<f32>) {
+        self.sim_params.set_escape_radius(escape_radius);
+        self.sim_params_buffer.update(queue, &[self.sim_params], 0);
+    }
+
+    pub fn get_escape_radius(&self) -> Option<f32> {
+        self.sim_params.escape_radius()
+    }
+
+    /// Copies the escapes recorded by the last dispatch back to the cpu.
+    /// Once the copy is mapped, `on_mapped` is sent through the apc queue with whether mapping succeeded.
+    /// Does nothing while a previous readback is still in flight or escape detection is disabled.
+    pub fn request_escape_readback(
+        &mut self,
+        render_resources: &RenderResources,
+        sender: Sender<ApcCallback>,
+        on_mapped: fn(&mut World, bool),
+    ) {
+        if self.escape_readback_in_flight || self.get_escape_radius().is_none() {
+            return;
+        }
+
+        let (device, queue) = render_resources.get_device_queue();
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Escape Readback Encoder"),
+        });
+        encoder.copy_buffer_to_buffer(
+            &self.system_state_buffer.buffer,
+            0,
+            &self.escape_staging_buffer.buffer,
+            0,
+            self.system_state_buffer.size,
+        );
+        encoder.copy_buffer_to_buffer(
+            &self.escape_buffer.buffer,
+            0,
+            &self.escape_staging_buffer.buffer,
+            self.system_state_buffer.size,
+            self.escape_buffer.size,
+        );
+        queue.submit(std::iter::once(encoder.finish()));
 
-            particle_mesh_filter,
-            particle_material,
+        self.escape_readback_in_flight = true;
 
-            frame_count: 0,
+        // the buffer can only be mapped once the copy has been submitted
+        self.escape_staging_buffer
+            .slice()
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let mapped = result.is_ok();
+                let callback: ApcCallback = Box::new(move |world| on_mapped(world, mapped));
+                let _ = sender.send(callback);
+            });
+    }
+
+    /// Reads the mapped escape readback and returns the id and velocity of every escaped particle.
+    pub fn take_escape_readback(&mut self, mapped: bool) -> Vec<(u32, Vec3)> {
+        self.escape_readback_in_flight = false;
+        if !mapped {
+            return Vec::new();
+        }
+
+        let records: Vec<GpuEscapeRecord> = {
+            let data = self.escape_staging_buffer.slice().get_mapped_range();
+            let state_size = std::mem::size_of::<GpuSystemState>();
+            let system_state: GpuSystemState = bytemuck::pod_read_unaligned(&data[..state_size]);
+            let count = (system_state.escape_count as usize).min(self.escape_buffer.length);
+
+            data[state_size..]
+                .chunks_exact(std::mem::size_of::<GpuEscapeRecord>())
+                .take(count)
+                .map(bytemuck::pod_read_unaligned)
+                .collect()
+        };
+        self.escape_staging_buffer.buffer.unmap();
+
+        records
+            .iter()
+            .map(|record| (record.id, record.velocity))
+            .collect()
+    }
+
+    /// Copies the culling counts of the drawn instances back to the cpu, like the escape readback.
+    /// Does nothing while a previous readback is still in flight.
+    pub fn request_culling_readback(
+        &mut self,
+        render_resources: &RenderResources,
+        sender: Sender<ApcCallback>,
+        on_mapped: fn(&mut World, bool),
+    ) {
+        if self.culling_readback_in_flight {
+            return;
+        }
+
+        let (device, queue) = render_resources.get_device_queue();
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Culling Readback Encoder"),
+        });
+        self.get_drawn_instance_set().culling_counts.copy_to(
+            &mut encoder,
+            0,
+            &self.culling_staging_buffer,
+            0,
+            1,
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        self.culling_readback_in_flight = true;
+
+        self.culling_staging_buffer
+            .slice()
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let mapped = result.is_ok();
+                let callback: ApcCallback = Box::new(move |world| on_mapped(world, mapped));
+                let _ = sender.send(callback);
+            });
+    }
+
+    /// Reads the mapped culling readback, `None` if mapping failed
+    pub fn take_culling_readback(&mut self, mapped: bool) -> Option<GpuCullingCounts> {
+        self.culling_readback_in_flight = false;
+        if !mapped {
+            return None;
         }
+
+        let counts = bytemuck::pod_read_unaligned(
+            &self.culling_staging_buffer.slice().get_mapped_range()
+                [..std::mem::size_of::<GpuCullingCounts>()],
+        );
+        self.culling_staging_buffer.buffer.unmap();
+
+        Some(counts)
     }
 
     pub fn get_particle_count(&self) -> u32 {
         self.sim_params.num_particles
     }
 
-    pub fn get_vertex_buffer(&self) -> &Buffer<BasicVertex> {
-        &self.particle_mesh_filter.filter.vertex_buffer
+    pub fn get_particle_capacity(&self) -> usize {
+        self.particle_buffers.capacity()
     }
 
-    pub fn get_index_buffer(&self) -> &Buffer<u32> {
-        &self.particle_mesh_filter.filter.index_buffer
+    pub fn get_particle_ids(&self) -> &[u32] {
+        &self.particle_ids
     }
 
+    /// The instances drawn this frame
     pub fn get_instance_buffer(&self) -> &Buffer<GpuParticleInstance> {
-        &self.instance_buffer
+        &self.get_drawn_instance_set().instance_buffer
     }
 
-    pub fn get_indirect_buffer(&self) -> &Buffer<GpuIndirectArgs> {
-        &self.indirect_buffer
+    /// The instance list and draw arguments of a species at a level of detail
+    pub fn get_instance_list(&self, species: usize, lod: usize) -> usize {
+        species * self.get_lod_count() + lod
     }
 
-    pub fn get_material_bind_group(&self) -> &wgpu::BindGroup {
-        &self.particle_material.bind_group
+    /// The range of the drawn instance buffer holding the given instance list
+    pub fn get_instance_list_slice(&self, list: usize) -> wgpu::BufferSlice {
+        let range_size =
+            self.get_instance_capacity() as u64 * self.instance_format.get_instance_size();
+        let start = list as u64 * range_size;
+
+        self.get_instance_buffer()
+            .slice_range(start..start + range_size)
     }
 
-    pub fn set_frame_count(&mut self, frame_count: u32) {
-        self.frame_count = frame_count;
+    /// The draw arguments of the instances drawn this frame
+    pub fn get_indirect_buffer(&self) -> &Buffer<GpuIndirectArgs> {
+        &self.get_drawn_instance_set().indirect_buffer
     }
 
+    /// Clears the instance and culling counts of the set the instances are generated into
     pub fn reset_indirect_buffer(&mut self, queue: &wgpu::Queue) {
-        let new_idirect_args = &[GpuIndirectArgs::new(
-            self.particle_mesh_filter.filter.index_count,
-            0,
-        )];
+        let new_idirect_args = Self::initial_indirect_args(&self.species, self.render_mode);
+        let instance_set = self.get_generated_instance_set();
+
+        instance_set
+            .indirect_buffer
+            .update(queue, &new_idirect_args, 0);
+        instance_set
+            .culling_counts
+            .update(queue, &[GpuCullingCounts::zeroed()], 0);
+    }
+
+    /// Stages clearing the instance and culling counts with the frame's other uploads,
+    /// see `StagingBelt`
+    pub fn stage_indirect_reset(&self, device: &wgpu::Device, belt: &mut StagingBelt) {
+        let new_indirect_args = Self::initial_indirect_args(&self.species, self.render_mode);
+        let instance_set = self.get_generated_instance_set();
 
-        self.indirect_buffer.update(queue, new_idirect_args, 0);
+        instance_set
+            .indirect_buffer
+            .stage(device, belt, &new_indirect_args, 0);
+        instance_set
+            .culling_counts
+            .stage(device, belt, &[GpuCullingCounts::zeroed()], 0);
     }
 
     pub fn set_delta_time(&mut self, queue: &wgpu::Queue, delta_time: f32) {
@@ -199,11 +1726,105 @@ impl NBodySimResources {
         self.sim_params_buffer.update(queue, &[self.sim_params], 0);
     }
 
+    /// Sets the gravitational constant and the softening added to every squared distance
+    pub fn set_force_params(
+        &mut self,
+        queue: &wgpu::Queue,
+        gravitational_constant: f32,
+        softening: f32,
+    ) {
+        self.sim_params.gravitational_constant = gravitational_constant;
+        self.sim_params.softening = softening;
+        self.sim_params_buffer.update(queue, &[self.sim_params], 0);
+    }
+
     pub fn get_bind_group(&self) -> &wgpu::BindGroup {
-        if self.frame_count % 2 == 0 {
-            &self.bind_group_a
-        } else {
-            &self.bind_group_b
+        &self.bind_group
+    }
+
+    /// Group 0 of the Morton reorder kernels, reading the same particle buffer as `get_bind_group`
+    pub fn get_reorder_bind_group(&self) -> &wgpu::BindGroup {
+        &self.reorder_bind_group
+    }
+
+    /// The particle attributes the Morton reorder gathers from
+    pub fn get_attribute_buffer(&self) -> &Buffer<u32> {
+        &self.attribute_buffer
+    }
+
+    /// The bind group of the instance set the instances are generated into
+    pub fn get_instance_bind_group(&self) -> &wgpu::BindGroup {
+        &self.get_generated_instance_set().bind_group
+    }
+
+    pub fn get_attribute_bind_group(&self) -> &wgpu::BindGroup {
+        &self.attribute_bind_group
+    }
+
+    pub fn get_sprite_bind_group(&self) -> &wgpu::BindGroup {
+        &self.sprite_bind_group
+    }
+}
+
+/// Moves the values of every slot, `stride` values each, along with a reorder: slot `i` takes
+/// the values of slot `permutation[i]`. Slots past the permutation keep their values.
+fn permute_slots<T: Copy>(values: &[T], permutation: &[u32], stride: usize) -> Vec<T> {
+    let sorted = permutation.len() * stride;
+    permutation
+        .iter()
+        .flat_map(|&slot| &values[slot as usize * stride..(slot as usize + 1) * stride])
+        .chain(&values[sorted..])
+        .copied()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ATTRIBUTE_STRIDE: usize = 3;
+
+    // every attribute word names the body it belongs to
+    fn attributes_of(id: u32) -> [u32; ATTRIBUTE_STRIDE] {
+        [id * 10, id * 10 + 1, id * 10 + 2]
+    }
+
+    #[test]
+    fn reordered_ids_and_attributes_stay_with_their_particles() {
+        // ids out of slot order, like after earlier removals and reorders
+        let ids: Vec<u32> = (0..8).map(|slot| 100 + (slot * 5) % 8).collect();
+        let particles: Vec<GpuParticle> = ids
+            .iter()
+            .map(|&id| {
+                let mut particle = GpuParticle::new(Vec3::splat(id as f32), 1.0, Vec3::ZERO);
+                particle.id = id;
+                particle
+            })
+            .collect();
+        let attributes: Vec<u32> = ids.iter().flat_map(|&id| attributes_of(id)).collect();
+
+        // the sorted payload of the morton keys of the first 6 bodies,
+        // the last 2 were added after the reorder was dispatched
+        let keys = [7u32, 2, 9, 2, 0, 5];
+        let mut permutation: Vec<u32> = (0..keys.len() as u32).collect();
+        permutation.sort_by_key(|&slot| keys[slot as usize]);
+
+        // what cs_gather_particles leaves in the buffers
+        let particles = permute_slots(&particles, &permutation, 1);
+        let attributes = permute_slots(&attributes, &permutation, ATTRIBUTE_STRIDE);
+        // what apply_reorder leaves in the ids
+        let ids = permute_slots(&ids, &permutation, 1);
+
+        assert_eq!(ids.len(), particles.len());
+        for (slot, &id) in ids.iter().enumerate() {
+            assert_eq!(particles[slot].id, id, "slot {slot}");
+            assert_eq!(
+                attributes[slot * ATTRIBUTE_STRIDE..(slot + 1) * ATTRIBUTE_STRIDE],
+                attributes_of(id),
+                "slot {slot}"
+            );
         }
+        // sorted by key, equal keys keep their order, the late bodies stay last
+        assert_eq!(ids, [104, 105, 107, 101, 100, 102, 106, 103]);
     }
 }
@@ -0,0 +1,187 @@
+use bevy_ecs::{system::Resource, world::World};
+use crossbeam::channel::Sender;
+use wgpu::BufferUsages;
+
+use crate::{
+    ecs::resources::{nbody_sim_resources::NBodySimResources, sim_clock::SimClock},
+    gpu_resources::{
+        particle_storage::ParticleLayout, render_resources::RenderResources,
+        types::gpu_particle::GpuParticle,
+    },
+    traits::apc_traits::ApcCallback,
+    utils::buffer::{Buffer, BufferBuilder},
+};
+
+/// The latest particle state copied back from the gpu.
+/// Lags the simulation by however many frames the readback took.
+#[derive(Debug, Default, Resource)]
+pub struct ParticleSnapshot {
+    pub particles: Vec<GpuParticle>,
+    /// Simulated time and step of the state that was copied
+    pub sim_time: f32,
+    pub step_count: u64,
+    /// Increases with every completed readback, so readers can tell a new snapshot arrived
+    pub generation: u64,
+}
+
+/// Copies the particle state back to the cpu into `ParticleSnapshot` without stalling the frame.
+/// Readbacks run at most once every `interval` seconds, or once when `grab_now` is called.
+#[derive(Resource)]
+pub struct ParticleReadback {
+    interval: Option<f32>,
+    grab_requested: bool,
+    last_request_time: Option<f32>,
+
+    staging_buffer: Option<Buffer<GpuParticle>>,
+    in_flight: Option<InFlightReadback>,
+}
+
+/// What the state being copied was, filled in once the copy is mapped
+struct InFlightReadback {
+    count: usize,
+    particle_layout: ParticleLayout,
+    sim_time: f32,
+    step_count: u64,
+}
+
+impl Default for ParticleReadback {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl ParticleReadback {
+    /// `interval` is the minimum number of seconds between readbacks, `None` only reads back on request
+    pub fn new(interval: Option<f32>) -> Self {
+        Self {
+            interval,
+            grab_requested: false,
+            last_request_time: None,
+            staging_buffer: None,
+            in_flight: None,
+        }
+    }
+
+    pub fn set_interval(&mut self, interval: Option<f32>) {
+        self.interval = interval;
+    }
+
+    pub fn get_interval(&self) -> Option<f32> {
+        self.interval
+    }
+
+    /// Reads back once as soon as no other readback is in flight, ignoring the interval
+    pub fn grab_now(&mut self) {
+        self.grab_requested = true;
+    }
+
+    pub fn is_in_flight(&self) -> bool {
+        self.in_flight.is_some()
+    }
+
+    /// Whether a readback should start at `time`
+    pub fn is_due(&self, time: f32) -> bool {
+        if self.in_flight.is_some() {
+            return false;
+        }
+
+        if self.grab_requested {
+            return true;
+        }
+
+        match (self.interval, self.last_request_time) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(interval), Some(last_request_time)) => time - last_request_time >= interval,
+        }
+    }
+
+    /// Records that a readback started at `time`, clearing any pending grab
+    pub fn mark_requested(&mut self, time: f32) {
+        self.grab_requested = false;
+        self.last_request_time = Some(time);
+    }
+
+    /// Copies the current particles to the staging buffer.
+    /// Once the copy is mapped, `on_mapped` is sent through the apc queue with whether mapping succeeded.
+    pub fn request(
+        &mut self,
+        render_resources: &RenderResources,
+        n_body_sim_resources: &NBodySimResources,
+        time: f32,
+        sim_clock: &SimClock,
+        sender: Sender<ApcCallback>,
+        on_mapped: fn(&mut World, bool),
+    ) {
+        if self.in_flight.is_some() {
+            return;
+        }
+        self.mark_requested(time);
+
+        let count = n_body_sim_resources.get_particle_ids().len();
+        let (device, queue) = render_resources.get_device_queue();
+
+        // the staging buffer is reused until the simulation outgrows it
+        let too_small = self
+            .staging_buffer
+            .as_ref()
+            .is_none_or(|buffer| buffer.length < count.max(1));
+        if too_small {
+            self.staging_buffer = Some(
+                BufferBuilder::<GpuParticle>::new(device)
+                    .label("Particle Readback Staging Buffer")
+                    .size(n_body_sim_resources.get_particle_capacity())
+                    .usage(BufferUsages::MAP_READ | BufferUsages::COPY_DST)
+                    .build()
+                    .unwrap(),
+            );
+        }
+        let staging_buffer = self.staging_buffer.as_ref().unwrap();
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Particle Readback Encoder"),
+        });
+        n_body_sim_resources.copy_particles_to(&mut encoder, staging_buffer);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        self.in_flight = Some(InFlightReadback {
+            count,
+            particle_layout: n_body_sim_resources.get_particle_layout(),
+            sim_time: sim_clock.sim_time,
+            step_count: sim_clock.step_count,
+        });
+
+        // the buffer can only be mapped once the copy has been submitted
+        staging_buffer
+            .slice()
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let mapped = result.is_ok();
+                let callback: ApcCallback = Box::new(move |world| on_mapped(world, mapped));
+                let _ = sender.send(callback);
+            });
+    }
+
+    /// Reads the mapped staging buffer into the snapshot
+    pub fn complete(&mut self, mapped: bool, snapshot: &mut ParticleSnapshot) {
+        let Some(in_flight) = self.in_flight.take() else {
+            return;
+        };
+        let Some(staging_buffer) = &self.staging_buffer else {
+            return;
+        };
+
+        if !mapped {
+            return;
+        }
+
+        {
+            let data = staging_buffer.slice().get_mapped_range();
+            snapshot.particles = in_flight.particle_layout.read(&data, in_flight.count);
+        }
+        staging_buffer.buffer.unmap();
+
+        snapshot.sim_time = in_flight.sim_time;
+        snapshot.step_count = in_flight.step_count;
+        snapshot.generation += 1;
+    }
+}
@@ -0,0 +1,150 @@
+use glam::Vec4;
+
+use crate::{
+    ecs::components::{
+        materials::unlit_diffuse_material::UnlitDiffuseMaterial, mesh_filter::BasicMeshFilter,
+    },
+    gpu_resources::types::gpu_species::GpuSpecies,
+};
+
+/// The most species the simulation can render at once, matches MAX_SPECIES in the shader
+pub const MAX_SPECIES: usize = 8;
+/// The most levels of detail a species is drawn at, its own mesh included.
+/// Matches MAX_LODS in the shader.
+pub const MAX_LODS: usize = 4;
+
+/// A coarser mesh a species is drawn with once its particles cover fewer pixels on screen
+pub struct ParticleLod {
+    pub mesh_filter: BasicMeshFilter,
+    /// Turns the mesh to face the camera, for a quad standing in for the tiniest particles
+    pub billboard: bool,
+}
+
+impl ParticleLod {
+    pub fn new(mesh_filter: BasicMeshFilter) -> Self {
+        Self {
+            mesh_filter,
+            billboard: false,
+        }
+    }
+
+    /// A quad on the XY plane drawn facing the camera
+    pub fn billboard(mesh_filter: BasicMeshFilter) -> Self {
+        Self {
+            mesh_filter,
+            billboard: true,
+        }
+    }
+}
+
+/// How the instances of a species are colored
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpeciesColorRule {
+    /// Faster particles are redder
+    Velocity,
+    /// Every particle has the same color
+    Solid(Vec4),
+}
+
+/// How the instances of a species are sized
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpeciesSizeRule {
+    /// size = base + mass * scale
+    Mass { base: f32, scale: f32 },
+    /// Every particle has the same size
+    Fixed(f32),
+}
+
+impl Default for SpeciesSizeRule {
+    /// The sizing the instances had before there were species
+    fn default() -> Self {
+        Self::Mass {
+            base: 0.5,
+            scale: 0.00001,
+        }
+    }
+}
+
+/// A kind of particle with its own mesh, material and rules.
+/// The species of a body is read from the `species` particle attribute, bodies without one are species 0.
+pub struct ParticleSpecies {
+    pub mesh_filter: BasicMeshFilter,
+    /// Coarser meshes after `mesh_filter`, from the finest, see `with_lods`
+    pub lods: Vec<ParticleLod>,
+    pub material: UnlitDiffuseMaterial,
+    pub color_rule: SpeciesColorRule,
+    pub size_rule: SpeciesSizeRule,
+    /// Overrides the simulation softening for bodies of this species
+    pub softening: Option<f32>,
+}
+
+impl ParticleSpecies {
+    pub fn new(mesh_filter: BasicMeshFilter, material: UnlitDiffuseMaterial) -> Self {
+        Self {
+            mesh_filter,
+            lods: Vec::new(),
+            material,
+            color_rule: SpeciesColorRule::Velocity,
+            size_rule: SpeciesSizeRule::default(),
+            softening: None,
+        }
+    }
+
+    pub fn with_color_rule(mut self, color_rule: SpeciesColorRule) -> Self {
+        self.color_rule = color_rule;
+        self
+    }
+
+    pub fn with_size_rule(mut self, size_rule: SpeciesSizeRule) -> Self {
+        self.size_rule = size_rule;
+        self
+    }
+
+    pub fn with_softening(mut self, softening: f32) -> Self {
+        self.softening = Some(softening);
+        self
+    }
+
+    /// Coarser meshes drawn in turn as the particles get smaller on screen, from the finest.
+    /// At most `MAX_LODS - 1` are used, see `NBodySimResources::set_lod_screen_sizes`.
+    pub fn with_lods(mut self, lods: Vec<ParticleLod>) -> Self {
+        self.lods = lods;
+        self
+    }
+
+    /// The levels of detail the species has, its own mesh included
+    pub fn get_lod_count(&self) -> usize {
+        (self.lods.len() + 1).min(MAX_LODS)
+    }
+
+    /// The mesh of a level of detail and whether it is a billboard.
+    /// Levels past the species' coarsest reuse it, species can have fewer than others.
+    pub fn get_lod_mesh(&self, lod: usize) -> (&BasicMeshFilter, bool) {
+        if lod == 0 || self.lods.is_empty() {
+            return (&self.mesh_filter, false);
+        }
+
+        let lod = &self.lods[(lod - 1).min(self.get_lod_count() - 2)];
+        (&lod.mesh_filter, lod.billboard)
+    }
+
+    pub fn to_gpu(&self) -> GpuSpecies {
+        let (color_rule, color) = match self.color_rule {
+            SpeciesColorRule::Velocity => (0, Vec4::ONE),
+            SpeciesColorRule::Solid(color) => (1, color),
+        };
+
+        let (size_base, size_scale) = match self.size_rule {
+            SpeciesSizeRule::Mass { base, scale } => (base, scale),
+            SpeciesSizeRule::Fixed(size) => (size, 0.0),
+        };
+
+        GpuSpecies {
+            color,
+            color_rule,
+            size_base,
+            size_scale,
+            softening: self.softening.unwrap_or(-1.0),
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use bevy_ecs::system::Resource;
+
+/// Polls the modification times of the shader files, at most once every `poll_interval` seconds
+#[derive(Debug, Resource)]
+pub struct ShaderWatcher {
+    directory: PathBuf,
+    modified: HashMap<PathBuf, SystemTime>,
+    poll_interval: f32,
+    last_poll_time: f32,
+}
+
+impl ShaderWatcher {
+    pub fn new(directory: impl Into<PathBuf>, poll_interval: f32) -> Self {
+        let directory = directory.into();
+        let modified = scan_shader_files(&directory);
+
+        Self {
+            directory,
+            modified,
+            poll_interval,
+            last_poll_time: 0.0,
+        }
+    }
+
+    pub fn get_directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// The shader files created or modified since the last poll, empty if it isn't time to poll yet
+    pub fn poll_changes(&mut self, time: f32) -> Vec<PathBuf> {
+        if time - self.last_poll_time < self.poll_interval {
+            return Vec::new();
+        }
+        self.last_poll_time = time;
+
+        let modified = scan_shader_files(&self.directory);
+        let changed = modified
+            .iter()
+            .filter(|(path, time)| self.modified.get(*path) != Some(time))
+            .map(|(path, _)| path.clone())
+            .collect();
+
+        self.modified = modified;
+        changed
+    }
+}
+
+/// The modification time of every wgsl file under `directory`
+fn scan_shader_files(directory: &Path) -> HashMap<PathBuf, SystemTime> {
+    let mut modified = HashMap::new();
+    let mut directories = vec![directory.to_path_buf()];
+
+    while let Some(directory) = directories.pop() {
+        let Ok(entries) = std::fs::read_dir(&directory) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                directories.push(path);
+                continue;
+            }
+            if !path
+                .extension()
+                .is_some_and(|extension| extension == "wgsl")
+            {
+                continue;
+            }
+
+            if let Ok(time) = entry.metadata().and_then(|metadata| metadata.modified()) {
+                modified.insert(path, time);
+            }
+        }
+    }
+
+    modified
+}
@@ -0,0 +1,197 @@
+use bevy_ecs::system::Resource;
+
+/// Controls how simulation time advances relative to wall clock time.
+/// The simulation steps at most once per frame, while paused it only steps on request.
+#[derive(Debug, Resource)]
+pub struct SimClock {
+    paused: bool,
+    time_scale: f32,
+    reversed: bool,
+    // steps requested while paused that have not run yet
+    pending_steps: u32,
+    // a zero length step to rebuild the instances after the state changed while paused
+    refresh_pending: bool,
+
+    step_this_frame: bool,
+    stepped_last_frame: bool,
+    step_delta_time: f32,
+
+    /// Total simulated time, goes down while reversed
+    pub sim_time: f32,
+    /// Number of steps from the start, goes down while reversed so it keeps matching the
+    /// snapshots taken at the same simulated time
+    pub step_count: u64,
+}
+
+impl Default for SimClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimClock {
+    pub fn new() -> Self {
+        Self {
+            paused: false,
+            time_scale: 1.0,
+            reversed: false,
+            pending_steps: 0,
+            refresh_pending: false,
+
+            step_this_frame: false,
+            stepped_last_frame: false,
+            step_delta_time: 0.0,
+
+            sim_time: 0.0,
+            step_count: 0,
+        }
+    }
+
+    /// Decides whether the simulation steps this frame and by how much.
+    /// Should be called once per frame before the simulation bindings are updated.
+    pub fn advance(&mut self, delta_time: f32) {
+        self.stepped_last_frame = self.step_this_frame;
+
+        if self.paused && self.pending_steps == 0 && self.refresh_pending {
+            self.refresh_pending = false;
+            self.step_this_frame = true;
+            self.step_delta_time = 0.0;
+            return;
+        }
+        self.refresh_pending = false;
+
+        self.step_this_frame = if !self.paused {
+            true
+        } else if self.pending_steps > 0 {
+            self.pending_steps -= 1;
+            true
+        } else {
+            false
+        };
+
+        // there is nothing before the start to run back to
+        if self.step_this_frame && self.reversed && self.step_count == 0 {
+            self.paused = true;
+            self.pending_steps = 0;
+            self.step_this_frame = false;
+        }
+
+        if !self.step_this_frame {
+            self.step_delta_time = 0.0;
+            return;
+        }
+
+        let direction = if self.reversed { -1.0 } else { 1.0 };
+        self.step_delta_time = delta_time * self.time_scale * direction;
+        self.sim_time += self.step_delta_time;
+        self.step_count = if self.reversed {
+            self.step_count - 1
+        } else {
+            self.step_count + 1
+        };
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    /// Resumes running, dropping any steps still pending
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.pending_steps = 0;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        if self.paused {
+            self.resume();
+        } else {
+            self.pause();
+        }
+    }
+
+    /// Runs a zero length step next frame so a state restored while paused gets drawn
+    pub fn refresh(&mut self) {
+        self.refresh_pending = true;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Pauses the simulation and runs it for `frames` more steps, one per frame
+    pub fn step_frames(&mut self, frames: u32) {
+        self.paused = true;
+        self.pending_steps += frames;
+    }
+
+    /// Sets the ratio of simulated time to wall clock time, negative values are clamped to zero
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        self.time_scale = time_scale.max(0.0);
+    }
+
+    pub fn get_time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    /// Runs time backwards by negating the step delta time.
+    /// The clock pauses once it has run back to the first step.
+    pub fn set_reversed(&mut self, reversed: bool) {
+        self.reversed = reversed;
+    }
+
+    pub fn is_reversed(&self) -> bool {
+        self.reversed
+    }
+
+    /// Whether the simulation is dispatched this frame
+    pub fn should_step(&self) -> bool {
+        self.step_this_frame
+    }
+
+    /// Whether the simulation was dispatched on the previous frame
+    pub fn stepped_last_frame(&self) -> bool {
+        self.stepped_last_frame
+    }
+
+    /// The signed, scaled delta time of this frame's step, zero when not stepping
+    pub fn get_step_delta_time(&self) -> f32 {
+        self.step_delta_time
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reversing_stops_at_the_first_step() {
+        let mut clock = SimClock::new();
+        clock.advance(0.5);
+        clock.set_reversed(true);
+
+        clock.advance(0.5);
+        assert_eq!(clock.step_count, 0);
+        assert_eq!(clock.sim_time, 0.0);
+
+        clock.advance(0.5);
+        assert!(!clock.should_step());
+        assert!(clock.is_paused());
+        assert_eq!(clock.step_count, 0);
+        assert_eq!(clock.sim_time, 0.0);
+    }
+
+    #[test]
+    fn pending_steps_are_dropped_at_the_first_step() {
+        let mut clock = SimClock::new();
+        clock.set_reversed(true);
+        clock.step_frames(3);
+
+        clock.advance(0.5);
+        assert!(!clock.should_step());
+        assert_eq!(clock.get_step_delta_time(), 0.0);
+
+        clock.set_reversed(false);
+        clock.advance(0.5);
+        assert!(!clock.should_step());
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::VecDeque;
+
+use bevy_ecs::system::Resource;
+use wgpu::BufferUsages;
+
+use crate::{
+    ecs::resources::{nbody_sim_resources::NBodySimResources, sim_clock::SimClock},
+    gpu_resources::{
+        layouts::nbody_simparams_uniform_layout::NBodySimParamsUniformLayout,
+        particle_storage::ParticleLayout, render_resources::RenderResources,
+        types::gpu_particle::GpuParticle,
+    },
+    utils::buffer::{Buffer, BufferBuilder},
+};
+
+const DEFAULT_MEMORY_BUDGET: u64 = 256 * 1024 * 1024;
+const DEFAULT_CAPTURE_INTERVAL: u64 = 60;
+
+/// A copy of the simulation state taken on the gpu
+struct SimSnapshot {
+    sim_time: f32,
+    step_count: u64,
+    particle_ids: Vec<u32>,
+    attribute_stride: usize,
+    // the layout the particles were copied in
+    particle_layout: ParticleLayout,
+    particles: Buffer<GpuParticle>,
+    attributes: Buffer<u32>,
+}
+
+impl SimSnapshot {
+    fn size(&self) -> u64 {
+        self.particles.size + self.attributes.size
+    }
+}
+
+/// Ring of simulation snapshots used to rewind and scrub through time.
+/// A snapshot is copied on the gpu every `capture_interval` steps,
+/// the oldest snapshots are dropped to stay within the memory budget.
+#[derive(Resource)]
+pub struct SimSnapshots {
+    snapshots: VecDeque<SimSnapshot>,
+    memory_budget: u64,
+    capture_interval: u64,
+    last_capture_step: Option<u64>,
+}
+
+impl Default for SimSnapshots {
+    fn default() -> Self {
+        Self::new(DEFAULT_MEMORY_BUDGET, DEFAULT_CAPTURE_INTERVAL)
+    }
+}
+
+impl SimSnapshots {
+    /// `memory_budget` is in bytes, `capture_interval` in steps
+    pub fn new(memory_budget: u64, capture_interval: u64) -> Self {
+        Self {
+            snapshots: VecDeque::new(),
+            memory_budget,
+            capture_interval: capture_interval.max(1),
+            last_capture_step: None,
+        }
+    }
+
+    pub fn set_memory_budget(&mut self, memory_budget: u64) {
+        self.memory_budget = memory_budget;
+        self.evict(0);
+    }
+
+    pub fn set_capture_interval(&mut self, capture_interval: u64) {
+        self.capture_interval = capture_interval.max(1);
+    }
+
+    /// Bytes of gpu memory used by the stored snapshots
+    pub fn memory_used(&self) -> u64 {
+        self.snapshots.iter().map(SimSnapshot::size).sum()
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    /// The simulated time and step of every snapshot, oldest first
+    pub fn get_snapshot_times(&self) -> Vec<(f32, u64)> {
+        self.snapshots
+            .iter()
+            .map(|snapshot| (snapshot.sim_time, snapshot.step_count))
+            .collect()
+    }
+
+    /// Drops the oldest snapshots until `extra` more bytes fit in the budget
+    fn evict(&mut self, extra: u64) -> Option<SimSnapshot> {
+        let mut evicted = None;
+        while !self.snapshots.is_empty() && self.memory_used() + extra > self.memory_budget {
+            evicted = self.snapshots.pop_front();
+        }
+
+        evicted
+    }
+
+    /// Captures a snapshot if one is due at the clock's current step
+    pub fn capture_if_due(
+        &mut self,
+        render_resources: &RenderResources,
+        n_body_sim_resources: &NBodySimResources,
+        sim_clock: &SimClock,
+    ) {
+        let step_count = sim_clock.step_count;
+        if step_count % self.capture_interval != 0 || self.last_capture_step == Some(step_count) {
+            return;
+        }
+
+        self.capture(render_resources, n_body_sim_resources, sim_clock);
+    }
+
+    /// Copies the current simulation state into the ring on the gpu.
+    /// Skipped while a reorder is in flight, the ids don't match their slots until it lands.
+    /// Capturing forward drops the snapshots ahead, capturing while reversed keeps them.
+    pub fn capture(
+        &mut self,
+        render_resources: &RenderResources,
+        n_body_sim_resources: &NBodySimResources,
+        sim_clock: &SimClock,
+    ) {
+        if n_body_sim_resources.is_reorder_in_flight() {
+            return;
+        }
+
+        let (device, queue) = render_resources.get_device_queue();
+        let step_count = sim_clock.step_count;
+        self.last_capture_step = Some(step_count);
+
+        if sim_clock.is_reversed() {
+            // running backwards passes through the history, it stays ahead of us
+            if self
+                .snapshots
+                .iter()
+                .any(|snapshot| snapshot.step_count == step_count)
+            {
+                return;
+            }
+        } else {
+            // snapshots past this step belong to a timeline we rewound away from
+            self.snapshots
+                .retain(|snapshot| snapshot.step_count < step_count);
+        }
+
+        let particle_count = n_body_sim_resources.get_particle_ids().len().max(1);
+        let attribute_stride = n_body_sim_resources.get_attribute_schema().stride();
+        let attribute_count = particle_count * attribute_stride.max(1);
+
+        let size = (particle_count * std::mem::size_of::<GpuParticle>()
+            + attribute_count * std::mem::size_of::<u32>()) as u64;
+        if size > self.memory_budget {
+            return;
+        }
+
+        // reuse the buffers of an evicted snapshot when they are big enough
+        let evicted = self.evict(size).filter(|snapshot| {
+            snapshot.particles.length >= particle_count
+                && snapshot.attributes.length >= attribute_count
+        });
+        let (particles, attributes) = match evicted {
+            Some(snapshot) => (snapshot.particles, snapshot.attributes),
+            None => (
+                Self::create_buffer(device, particle_count, "Snapshot Particle Buffer"),
+                Self::create_buffer(device, attribute_count, "Snapshot Attribute Buffer"),
+            ),
+        };
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Snapshot Capture Encoder"),
+        });
+        n_body_sim_resources.copy_state_to(&mut encoder, &particles, &attributes);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        // captures made while reversed land before the history they passed through
+        let index = self
+            .snapshots
+            .partition_point(|snapshot| snapshot.step_count < step_count);
+        self.snapshots.insert(
+            index,
+            SimSnapshot {
+                sim_time: sim_clock.sim_time,
+                step_count,
+                particle_ids: n_body_sim_resources.get_particle_ids().to_vec(),
+                attribute_stride,
+                particle_layout: n_body_sim_resources.get_particle_layout(),
+                particles,
+                attributes,
+            },
+        );
+    }
+
+    fn create_buffer<T: bytemuck::Pod>(
+        device: &wgpu::Device,
+        size: usize,
+        label: &str,
+    ) -> Buffer<T> {
+        BufferBuilder::<T>::new(device)
+            .label(label)
+            .size(size)
+            .usage(BufferUsages::COPY_SRC | BufferUsages::COPY_DST)
+            .build()
+            .unwrap()
+    }
+
+    /// Restores the snapshot at `index`, oldest first, into the simulation and the clock.
+    /// Newer snapshots are kept so the timeline can be scrubbed forwards again.
+    pub fn restore(
+        &mut self,
+        index: usize,
+        render_resources: &RenderResources,
+        layout: &NBodySimParamsUniformLayout,
+        n_body_sim_resources: &mut NBodySimResources,
+        sim_clock: &mut SimClock,
+    ) -> Result<(), String> {
+        let snapshot = self
+            .snapshots
+            .get(index)
+            .ok_or(format!("No snapshot {}", index))?;
+
+        if snapshot.particle_layout != n_body_sim_resources.get_particle_layout() {
+            return Err(format!(
+                "Snapshot {} was captured with the {} particle layout, the simulation uses {}",
+                index,
+                snapshot.particle_layout.get_name(),
+                n_body_sim_resources.get_particle_layout().get_name()
+            ));
+        }
+
+        // attributes captured under a different schema can't be restored
+        let attributes = (snapshot.attribute_stride
+            == n_body_sim_resources.get_attribute_schema().stride())
+        .then_some(&snapshot.attributes);
+
+        n_body_sim_resources.restore_state(
+            render_resources,
+            layout,
+            &snapshot.particles,
+            attributes,
+            &snapshot.particle_ids,
+        );
+
+        sim_clock.sim_time = snapshot.sim_time;
+        sim_clock.step_count = snapshot.step_count;
+        sim_clock.refresh();
+        self.last_capture_step = Some(snapshot.step_count);
+
+        Ok(())
+    }
+
+    /// Restores the newest snapshot taken at or before `step_count`
+    pub fn rewind_to_step(
+        &mut self,
+        step_count: u64,
+        render_resources: &RenderResources,
+        layout: &NBodySimParamsUniformLayout,
+        n_body_sim_resources: &mut NBodySimResources,
+        sim_clock: &mut SimClock,
+    ) -> Result<(), String> {
+        let index = self
+            .snapshots
+            .iter()
+            .rposition(|snapshot| snapshot.step_count <= step_count)
+            .ok_or(format!("No snapshot at or before step {}", step_count))?;
+
+        self.restore(
+            index,
+            render_resources,
+            layout,
+            n_body_sim_resources,
+            sim_clock,
+        )
+    }
+}
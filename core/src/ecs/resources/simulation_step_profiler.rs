@@ -0,0 +1,62 @@
+use bevy_ecs::system::Resource;
+use crossbeam::channel::Sender;
+
+use crate::{
+    render::gpu_profiler::{GpuProfiler, MAX_PROFILED_PASSES},
+    traits::apc_traits::ApcCallback,
+};
+
+/// Times the gpu simulation steps. They are submitted ahead of the frame, outside the render
+/// graph, so its profiler never sees them. Every step is reported as its own pass.
+#[derive(Resource)]
+pub struct SimulationStepProfiler {
+    profiler: Option<GpuProfiler>,
+}
+
+impl SimulationStepProfiler {
+    pub const LABEL: &'static str = "Simulation Step";
+
+    /// Only times anything if the device was created with `Features::TIMESTAMP_QUERY`
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        Self {
+            profiler: GpuProfiler::new(device, queue).map(GpuProfiler::outside_graph),
+        }
+    }
+
+    /// Whether the device supports timing the steps
+    pub fn is_profiling(&self) -> bool {
+        self.profiler.is_some()
+    }
+
+    /// Starts reading back the steps of earlier frames and readies the queries for this frame's
+    pub fn begin_frame(&mut self, device: &wgpu::Device, sender: &Sender<ApcCallback>) {
+        if let Some(profiler) = &mut self.profiler {
+            profiler.begin_frame(device, sender);
+        }
+    }
+
+    /// The timestamp writes for the compute pass of the step at `step` this frame,
+    /// `None` when the steps go untimed
+    pub fn get_timestamp_writes(&self, step: u32) -> Option<wgpu::ComputePassTimestampWrites<'_>> {
+        let query_set = self.profiler.as_ref()?.get_query_set()?;
+        if step >= MAX_PROFILED_PASSES {
+            return None;
+        }
+
+        Some(wgpu::ComputePassTimestampWrites {
+            query_set,
+            beginning_of_pass_write_index: Some(step * 2),
+            end_of_pass_write_index: Some(step * 2 + 1),
+        })
+    }
+
+    /// Resolves the timestamps of the `steps` steps recorded this frame
+    pub fn end_frame(&mut self, encoder: &mut wgpu::CommandEncoder, steps: u32) {
+        let Some(profiler) = &mut self.profiler else {
+            return;
+        };
+
+        let passes = vec![Some(Self::LABEL); steps.min(MAX_PROFILED_PASSES) as usize];
+        profiler.end_frame(encoder, passes);
+    }
+}
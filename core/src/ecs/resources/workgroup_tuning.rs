@@ -0,0 +1,67 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use bevy_ecs::system::Resource;
+use serde::{Deserialize, Serialize};
+
+/// Where the tuned workgroup sizes are kept unless told otherwise
+pub const DEFAULT_TUNING_FILE: &str = "workgroup_sizes.toml";
+/// Steps timed per candidate size when benchmarking
+pub const DEFAULT_BENCHMARK_STEPS: u32 = 20;
+
+/// The fastest n-body workgroup size found for each adapter, stored as toml
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TunedWorkgroupSizes {
+    adapters: BTreeMap<String, u32>,
+}
+
+impl TunedWorkgroupSizes {
+    /// Reads the sizes from `path`, a missing file has no sizes yet
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+
+        toml::from_str(&content).map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let content = toml::to_string(self)
+            .map_err(|e| format!("Failed to serialize workgroup sizes: {}", e))?;
+        std::fs::write(path, content)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    pub fn get(&self, adapter_name: &str) -> Option<u32> {
+        self.adapters.get(adapter_name).copied()
+    }
+
+    pub fn set(&mut self, adapter_name: &str, workgroup_size: u32) {
+        self.adapters
+            .insert(adapter_name.to_string(), workgroup_size);
+    }
+}
+
+/// Which adapter the app runs on and where the sizes tuned for it are persisted.
+/// Benchmarking is only possible once this is inserted, F4 runs it in the interactive app.
+#[derive(Debug, Clone, Resource)]
+pub struct WorkgroupTuning {
+    pub adapter_name: String,
+    pub path: PathBuf,
+    /// Steps timed per candidate size
+    pub benchmark_steps: u32,
+}
+
+impl WorkgroupTuning {
+    pub fn new(adapter_name: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        Self {
+            adapter_name: adapter_name.into(),
+            path: path.into(),
+            benchmark_steps: DEFAULT_BENCHMARK_STEPS,
+        }
+    }
+}
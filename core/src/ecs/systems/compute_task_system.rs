@@ -0,0 +1,10 @@
+use bevy_ecs::world::{Mut, World};
+
+use crate::gpu_resources::pipelines::compute_task_registry::ComputeTaskRegistry;
+
+/// Switches the compute tasks to the shader variants they ask for before the frame is recorded
+pub fn prepare_compute_tasks_system(world: &mut World) {
+    world.resource_scope(|world, mut registry: Mut<ComputeTaskRegistry>| {
+        registry.prepare(world);
+    });
+}
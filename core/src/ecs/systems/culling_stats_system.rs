@@ -0,0 +1,67 @@
+use bevy_ecs::{
+    system::{Res, ResMut},
+    world::{Mut, World},
+};
+use log::warn;
+
+use crate::{
+    ecs::resources::{
+        apc_resources::ApcQueue, culling_stats::CullingStats,
+        nbody_sim_resources::NBodySimResources,
+    },
+    gpu_resources::{
+        layouts::nbody_simparams_uniform_layout::NBodySimParamsUniformLayout,
+        render_resources::RenderResources,
+    },
+};
+
+/// Copies the culling counts of the drawn instances back to the cpu without waiting on the gpu.
+/// The result is handled by `complete_culling_readback` once the copy is mapped.
+pub fn culling_stats_system(
+    render_resources: Res<RenderResources>,
+    apc_queue: Res<ApcQueue>,
+    mut n_body_sim_resources: ResMut<NBodySimResources>,
+) {
+    // drive any pending map callbacks without blocking
+    render_resources.device.poll(wgpu::Maintain::Poll);
+
+    n_body_sim_resources.request_culling_readback(
+        &render_resources,
+        apc_queue.sender.clone(),
+        complete_culling_readback,
+    );
+}
+
+/// Updates `CullingStats` and grows the instance buffers if instances were dropped
+fn complete_culling_readback(world: &mut World, mapped: bool) {
+    let Some(counts) = world
+        .resource_mut::<NBodySimResources>()
+        .take_culling_readback(mapped)
+    else {
+        return;
+    };
+
+    let stats = CullingStats::from_gpu(&counts);
+    let was_dropping = world.resource::<CullingStats>().dropped > 0;
+    world.insert_resource(stats);
+
+    if stats.dropped == 0 {
+        return;
+    }
+
+    world.resource_scope(|world, mut n_body_sim_resources: Mut<NBodySimResources>| {
+        let grown = n_body_sim_resources.grow_instance_capacity(
+            world.resource::<RenderResources>(),
+            world.resource::<NBodySimParamsUniformLayout>(),
+            stats.dropped,
+        );
+        // warned once when the drops start rather than on every readback
+        if !grown && !was_dropping {
+            warn!(
+                "{} particles were not drawn, the instance capacity of {} is too small",
+                stats.dropped,
+                n_body_sim_resources.get_instance_capacity()
+            );
+        }
+    });
+}
@@ -0,0 +1,67 @@
+use bevy_ecs::{
+    system::{Res, ResMut},
+    world::{Mut, World},
+};
+
+use crate::{
+    ecs::resources::{
+        apc_resources::ApcQueue, nbody_sim_resources::NBodySimResources, sim_clock::SimClock,
+        time::Time,
+    },
+    events::escape_event::{EscapeEvent, EscapeEvents},
+    gpu_resources::render_resources::RenderResources,
+};
+
+/// Copies the escapes found by the last step back to the cpu without waiting on the gpu.
+/// The result is handled by `complete_escape_readback` once the copy is mapped.
+pub fn escape_detection_system(
+    render_resources: Res<RenderResources>,
+    apc_queue: Res<ApcQueue>,
+    sim_clock: Res<SimClock>,
+    mut n_body_sim_resources: ResMut<NBodySimResources>,
+) {
+    // drive any pending map callbacks without blocking
+    render_resources.device.poll(wgpu::Maintain::Poll);
+
+    // the escapes on the gpu are only new if the last frame ran a step
+    if !sim_clock.stepped_last_frame() {
+        return;
+    }
+
+    n_body_sim_resources.request_escape_readback(
+        &render_resources,
+        apc_queue.sender.clone(),
+        complete_escape_readback,
+    );
+}
+
+/// Removes the escaped particles from the simulation and sends an `EscapeEvent` for each of them.
+/// A particle read back again before its removal took effect is only reported once.
+fn complete_escape_readback(world: &mut World, mapped: bool) {
+    let escaped = world
+        .resource_mut::<NBodySimResources>()
+        .take_escape_readback(mapped);
+
+    if escaped.is_empty() {
+        return;
+    }
+
+    let ids: Vec<u32> = escaped.iter().map(|(id, _)| *id).collect();
+    let mut removed =
+        world.resource_scope(|world, mut n_body_sim_resources: Mut<NBodySimResources>| {
+            let render_resources = world.resource::<RenderResources>();
+            n_body_sim_resources.remove_bodies(render_resources, &ids)
+        });
+
+    let time = world.resource::<Time>().total_time;
+    let mut escape_events = world.resource_mut::<EscapeEvents>();
+    for (id, velocity) in escaped {
+        if !removed.remove(&id) {
+            continue;
+        }
+
+        escape_events
+            .events
+            .send(EscapeEvent { id, velocity, time });
+    }
+}
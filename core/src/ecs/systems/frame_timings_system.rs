@@ -0,0 +1,32 @@
+use bevy_ecs::system::{Res, ResMut};
+use log::info;
+use winit::keyboard::KeyCode;
+
+use crate::ecs::resources::{frame_timings::FrameTimings, input::Input, time::Time};
+
+/// Seconds between logged reports once F3 turns them on
+const LOG_INTERVAL: f32 = 2.0;
+
+/// F3 toggles logging the frame timings, which are then reported every couple of seconds
+pub fn frame_timings_system(
+    input: Res<Input>,
+    time: Res<Time>,
+    mut frame_timings: ResMut<FrameTimings>,
+) {
+    let toggle = input
+        .keyboard
+        .get_key(KeyCode::F3)
+        .is_some_and(|key| key.was_pressed_this_frame());
+    if toggle {
+        let interval = match frame_timings.get_log_interval() {
+            Some(_) => None,
+            None => Some(LOG_INTERVAL),
+        };
+        frame_timings.set_log_interval(interval);
+    }
+
+    if frame_timings.is_log_due(time.total_time) {
+        frame_timings.mark_logged(time.total_time);
+        info!("Frame timings:\n{}", frame_timings.report());
+    }
+}
@@ -0,0 +1,61 @@
+use bevy_ecs::system::{Query, Res, ResMut};
+use log::info;
+use winit::keyboard::KeyCode;
+
+use crate::{
+    ecs::{
+        components::gpu_bindings::camera_bindings::CameraBindings,
+        resources::{
+            input::Input, nbody_sim_resources::NBodySimResources,
+            screen_parameters::ScreenParameters,
+        },
+    },
+    gpu_resources::render_resources::RenderResources,
+    utils::buffer::StagingBelt,
+};
+
+/// Stages the camera for the instance culling and level of detail selection, after the camera
+/// bindings are updated and before the instances are generated
+pub fn update_frustum_culling_system(
+    render_resources: Res<RenderResources>,
+    screen_parameters: Res<ScreenParameters>,
+    camera_query: Query<&CameraBindings>,
+    mut belt: ResMut<StagingBelt>,
+    mut n_body_sim_resources: ResMut<NBodySimResources>,
+) {
+    // TODO: Support multiple cameras
+    let Ok(camera_bindings) = camera_query.get_single() else {
+        return;
+    };
+
+    // a unit at a clip space w of 1 spans proj[1][1] of the two units of ndc the height covers
+    let screen_scale = camera_bindings.get_proj().y_axis.y * screen_parameters.height as f32 * 0.5;
+    n_body_sim_resources.stage_camera(
+        &render_resources.device,
+        &mut belt,
+        camera_bindings.get_view_proj(),
+        screen_scale,
+    );
+}
+
+/// F5 toggles frustum culling, to compare against drawing every particle
+pub fn toggle_frustum_culling_system(
+    input: Res<Input>,
+    render_resources: Res<RenderResources>,
+    mut n_body_sim_resources: ResMut<NBodySimResources>,
+) {
+    let toggle = input
+        .keyboard
+        .get_key(KeyCode::F5)
+        .is_some_and(|key| key.was_pressed_this_frame());
+    if !toggle {
+        return;
+    }
+
+    let enabled = !n_body_sim_resources.is_frustum_culling();
+    n_body_sim_resources.set_frustum_culling(&render_resources.queue, enabled);
+    info!(
+        "Frustum culling {}",
+        if enabled { "enabled" } else { "disabled" }
+    );
+}
@@ -1,5 +1,19 @@
+pub mod compute_task_system;
+pub mod culling_stats_system;
+pub mod escape_detection_system;
+pub mod frame_timings_system;
+pub mod frustum_culling_system;
+pub mod morton_reorder_system;
+pub mod particle_readback_system;
+pub mod particle_render_mode_system;
 pub mod rotate_transform_system;
+#[cfg(feature = "debug-shader-hot-reload")]
+pub mod shader_hot_reload_system;
+pub mod sim_clock_system;
+pub mod sim_snapshot_system;
+pub mod simulation_backend_system;
 pub mod update_camera_system;
 pub mod update_input_system;
 pub mod update_model_bindings_system;
 pub mod update_n_body_sim_system;
+pub mod workgroup_tuning_system;
@@ -0,0 +1,20 @@
+use bevy_ecs::world::World;
+use log::error;
+
+use crate::ecs::resources::{
+    morton_reorder::{MortonReorder, reorder_particles},
+    sim_clock::SimClock,
+};
+
+/// Sorts the particles by Morton code once the reorder interval has passed since the last sort.
+/// Runs before the frame is recorded, while the read buffer holds the current state.
+pub fn morton_reorder_system(world: &mut World) {
+    let step_count = world.resource::<SimClock>().step_count;
+    if !world.resource::<MortonReorder>().is_due(step_count) {
+        return;
+    }
+
+    if let Err(e) = reorder_particles(world, step_count) {
+        error!("Failed to reorder the particles: {}", e);
+    }
+}
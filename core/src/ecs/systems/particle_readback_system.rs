@@ -0,0 +1,73 @@
+use bevy_ecs::{
+    system::{Res, ResMut},
+    world::{Mut, World},
+};
+
+use crate::{
+    ecs::resources::{
+        active_simulation_backend::ActiveSimulationBackend,
+        apc_resources::ApcQueue,
+        nbody_sim_resources::NBodySimResources,
+        particle_readback::{ParticleReadback, ParticleSnapshot},
+        sim_clock::SimClock,
+        time::Time,
+    },
+    gpu_resources::render_resources::RenderResources,
+};
+
+/// Starts copying the gpu particle state back when a readback is due.
+/// Runs before the clock advances, so the copied state matches the clock's time and step.
+pub fn particle_readback_system(
+    render_resources: Res<RenderResources>,
+    apc_queue: Res<ApcQueue>,
+    time: Res<Time>,
+    sim_clock: Res<SimClock>,
+    n_body_sim_resources: Res<NBodySimResources>,
+    mut particle_readback: ResMut<ParticleReadback>,
+) {
+    // drive any pending map callbacks without blocking
+    render_resources.device.poll(wgpu::Maintain::Poll);
+
+    if !particle_readback.is_due(time.total_time) {
+        return;
+    }
+
+    particle_readback.request(
+        &render_resources,
+        &n_body_sim_resources,
+        time.total_time,
+        &sim_clock,
+        apc_queue.sender.clone(),
+        complete_particle_readback,
+    );
+}
+
+/// The cpu backend already has the state at hand, so the snapshot is filled in directly
+pub fn cpu_particle_readback_system(
+    time: Res<Time>,
+    sim_clock: Res<SimClock>,
+    active_backend: Res<ActiveSimulationBackend>,
+    mut particle_readback: ResMut<ParticleReadback>,
+    mut particle_snapshot: ResMut<ParticleSnapshot>,
+) {
+    let ActiveSimulationBackend::Cpu(backend) = active_backend.as_ref() else {
+        return;
+    };
+
+    if !particle_readback.is_due(time.total_time) {
+        return;
+    }
+
+    particle_readback.mark_requested(time.total_time);
+    particle_snapshot.particles = backend.get_particles().to_vec();
+    particle_snapshot.sim_time = sim_clock.sim_time;
+    particle_snapshot.step_count = sim_clock.step_count;
+    particle_snapshot.generation += 1;
+}
+
+fn complete_particle_readback(world: &mut World, mapped: bool) {
+    world.resource_scope(|world, mut particle_readback: Mut<ParticleReadback>| {
+        let mut particle_snapshot = world.resource_mut::<ParticleSnapshot>();
+        particle_readback.complete(mapped, &mut particle_snapshot);
+    });
+}
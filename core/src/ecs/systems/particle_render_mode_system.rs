@@ -0,0 +1,32 @@
+use bevy_ecs::system::{Res, ResMut};
+use log::info;
+use winit::keyboard::KeyCode;
+
+use crate::{
+    ecs::resources::{input::Input, nbody_sim_resources::NBodySimResources},
+    gpu_resources::{
+        pipelines::render_sprites_pipeline::ParticleRenderMode, render_resources::RenderResources,
+    },
+};
+
+/// F6 switches the particles between meshes and sprites
+pub fn toggle_particle_render_mode_system(
+    input: Res<Input>,
+    render_resources: Res<RenderResources>,
+    mut n_body_sim_resources: ResMut<NBodySimResources>,
+) {
+    let toggle = input
+        .keyboard
+        .get_key(KeyCode::F6)
+        .is_some_and(|key| key.was_pressed_this_frame());
+    if !toggle {
+        return;
+    }
+
+    let render_mode = match n_body_sim_resources.get_render_mode() {
+        ParticleRenderMode::Mesh => ParticleRenderMode::Sprite,
+        ParticleRenderMode::Sprite => ParticleRenderMode::Mesh,
+    };
+    n_body_sim_resources.set_render_mode(&render_resources.queue, render_mode);
+    info!("Drawing the particles as {:?}", render_mode);
+}
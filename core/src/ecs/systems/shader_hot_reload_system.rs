@@ -0,0 +1,134 @@
+use std::{borrow::Cow, path::PathBuf};
+
+use bevy_ecs::{
+    system::Resource,
+    world::{Mut, World},
+};
+use log::{error, info};
+
+use crate::{
+    ecs::resources::{shader_watcher::ShaderWatcher, time::Time},
+    gpu_resources::{
+        pipelines::{
+            compute_task_registry::ComputeTaskRegistry,
+            render_particles_pipeline::RenderParticlesPipeline,
+            render_sprites_pipeline::RenderSpritesPipeline,
+            unlit_diffuse_pipeline::UnlitDiffusePipeline,
+        },
+        render_resources::RenderResources,
+        shader_hot_reload::{compile_shader, shader_path},
+        shader_variants::create_checked,
+    },
+};
+
+/// Rebuilds the pipelines whose shaders changed on disk.
+/// A shader that fails to compile is logged and the pipeline built from the previous version is kept.
+pub fn shader_hot_reload_system(world: &mut World) {
+    let time = world.resource::<Time>().total_time;
+    let changed = world.resource_mut::<ShaderWatcher>().poll_changes(time);
+    if changed.is_empty() {
+        return;
+    }
+
+    for path in &changed {
+        info!("Shader changed: {}", path.display());
+    }
+
+    reload_render_pipeline(
+        world,
+        "render_particles.wgsl",
+        &changed,
+        |world,
+         [
+            vertex,
+            packed_vertex,
+            billboard_vertex,
+            packed_billboard_vertex,
+            fragment,
+        ]: [wgpu::ShaderModuleDescriptor; 5]| {
+            RenderParticlesPipeline::with_shaders(
+                world,
+                vertex,
+                packed_vertex,
+                billboard_vertex,
+                packed_billboard_vertex,
+                fragment,
+            )
+        },
+    );
+    reload_render_pipeline(
+        world,
+        "render_sprites.wgsl",
+        &changed,
+        |world, [vertex, packed_vertex, fragment]: [wgpu::ShaderModuleDescriptor; 3]| {
+            RenderSpritesPipeline::with_shaders(world, vertex, packed_vertex, fragment)
+        },
+    );
+    reload_render_pipeline(
+        world,
+        "unlit_diffuse.wgsl",
+        &changed,
+        |world, [vertex, fragment]: [wgpu::ShaderModuleDescriptor; 2]| {
+            UnlitDiffusePipeline::with_shaders(world, vertex, fragment)
+        },
+    );
+
+    // the kernels preprocess their source themselves, once per variant
+    world.resource_scope(|world, mut registry: Mut<ComputeTaskRegistry>| {
+        let device = &world.resource::<RenderResources>().device;
+
+        for source_file in registry.get_source_files() {
+            let path = shader_path(source_file);
+            if !changed.contains(&path) {
+                continue;
+            }
+
+            let source = match std::fs::read_to_string(&path) {
+                Ok(source) => source,
+                Err(e) => {
+                    error!("Failed to reload {}: {}", source_file, e);
+                    continue;
+                }
+            };
+
+            match registry.reload_source(device, source_file, source) {
+                Ok(count) => info!("Reloaded {} kernels from {}", count, source_file),
+                Err(e) => error!("Failed to reload {}:\n{}", source_file, e),
+            }
+        }
+    });
+}
+
+/// Rebuilds a render pipeline resource from a shader holding all its stages,
+/// `build` gets one module of the whole shader per stage it takes
+fn reload_render_pipeline<P: Resource, const STAGES: usize>(
+    world: &mut World,
+    file: &str,
+    changed: &[PathBuf],
+    build: fn(&World, [wgpu::ShaderModuleDescriptor; STAGES]) -> P,
+) {
+    let source = match compile_shader(file, changed) {
+        None => return,
+        Some(Ok(source)) => source,
+        Some(Err(e)) => {
+            error!("Failed to reload {}:\n{}", file, e);
+            return;
+        }
+    };
+
+    let descriptor = || wgpu::ShaderModuleDescriptor {
+        label: Some(file),
+        source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(&source)),
+    };
+    let device = world.resource::<RenderResources>().device.clone();
+
+    match create_checked(&device, || {
+        build(world, std::array::from_fn(|_| descriptor()))
+    }) {
+        Ok(pipeline) => {
+            world.insert_resource(pipeline);
+            info!("Reloaded {}", file);
+        }
+        Err(e) => error!("Failed to reload {}:\n{}", file, e),
+    }
+}
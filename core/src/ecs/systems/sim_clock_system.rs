@@ -0,0 +1,46 @@
+use bevy_ecs::system::{Res, ResMut};
+use winit::keyboard::KeyCode;
+
+use crate::ecs::resources::{input::Input, sim_clock::SimClock, time::Time};
+
+const MIN_TIME_SCALE: f32 = 1.0 / 64.0;
+const MAX_TIME_SCALE: f32 = 64.0;
+
+fn was_pressed(input: &Input, key: KeyCode) -> bool {
+    input
+        .keyboard
+        .get_key(key)
+        .is_some_and(|key| key.was_pressed_this_frame())
+}
+
+/// Keyboard controls for the simulation clock:
+/// space pauses/resumes, period steps a single frame, `[` and `]` halve and double the time scale,
+/// R reverses time
+pub fn sim_clock_input_system(input: Res<Input>, mut sim_clock: ResMut<SimClock>) {
+    if was_pressed(&input, KeyCode::Space) {
+        sim_clock.toggle_pause();
+    }
+
+    if was_pressed(&input, KeyCode::Period) {
+        sim_clock.step_frames(1);
+    }
+
+    if was_pressed(&input, KeyCode::BracketLeft) {
+        let time_scale = (sim_clock.get_time_scale() * 0.5).max(MIN_TIME_SCALE);
+        sim_clock.set_time_scale(time_scale);
+    }
+
+    if was_pressed(&input, KeyCode::BracketRight) {
+        let time_scale = (sim_clock.get_time_scale() * 2.0).min(MAX_TIME_SCALE);
+        sim_clock.set_time_scale(time_scale);
+    }
+
+    if was_pressed(&input, KeyCode::KeyR) {
+        let reversed = sim_clock.is_reversed();
+        sim_clock.set_reversed(!reversed);
+    }
+}
+
+pub fn advance_sim_clock_system(time: Res<Time>, mut sim_clock: ResMut<SimClock>) {
+    sim_clock.advance(time.delta_time);
+}
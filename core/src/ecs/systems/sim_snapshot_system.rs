@@ -0,0 +1,55 @@
+use bevy_ecs::system::{Res, ResMut};
+use log::warn;
+use winit::keyboard::KeyCode;
+
+use crate::{
+    ecs::resources::{
+        input::Input, nbody_sim_resources::NBodySimResources, sim_clock::SimClock,
+        sim_snapshots::SimSnapshots,
+    },
+    gpu_resources::{
+        layouts::nbody_simparams_uniform_layout::NBodySimParamsUniformLayout,
+        render_resources::RenderResources,
+    },
+};
+
+/// Copies the simulation state into the snapshot ring every few steps
+pub fn capture_sim_snapshot_system(
+    render_resources: Res<RenderResources>,
+    sim_clock: Res<SimClock>,
+    n_body_sim_resources: Res<NBodySimResources>,
+    mut sim_snapshots: ResMut<SimSnapshots>,
+) {
+    sim_snapshots.capture_if_due(&render_resources, &n_body_sim_resources, &sim_clock);
+}
+
+/// Backspace pauses and rewinds to the snapshot before the current step
+pub fn rewind_input_system(
+    input: Res<Input>,
+    render_resources: Res<RenderResources>,
+    layout: Res<NBodySimParamsUniformLayout>,
+    mut n_body_sim_resources: ResMut<NBodySimResources>,
+    mut sim_clock: ResMut<SimClock>,
+    mut sim_snapshots: ResMut<SimSnapshots>,
+) {
+    let rewind = input
+        .keyboard
+        .get_key(KeyCode::Backspace)
+        .is_some_and(|key| key.was_pressed_this_frame());
+
+    if !rewind {
+        return;
+    }
+
+    sim_clock.pause();
+    let step_count = sim_clock.step_count.saturating_sub(1);
+    if let Err(error) = sim_snapshots.rewind_to_step(
+        step_count,
+        &render_resources,
+        &layout,
+        &mut n_body_sim_resources,
+        &mut sim_clock,
+    ) {
+        warn!("Failed to rewind: {}", error);
+    }
+}
@@ -0,0 +1,134 @@
+use bevy_ecs::{
+    system::{Res, ResMut},
+    world::{Mut, World},
+};
+#[cfg(target_arch = "wasm32")]
+use log::warn;
+use winit::keyboard::KeyCode;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::simulation::cpu_backend::CpuSimulationBackend;
+use crate::{
+    ecs::resources::{
+        active_simulation_backend::ActiveSimulationBackend, input::Input,
+        n_body_kernel_config::NBodyKernelConfig, nbody_sim_resources::NBodySimResources,
+        sim_clock::SimClock,
+    },
+    gpu_resources::{
+        layouts::nbody_simparams_uniform_layout::NBodySimParamsUniformLayout,
+        render_resources::RenderResources,
+    },
+    simulation::gpu_backend::GpuSimulationBackend,
+    traits::simulation_traits::SimulationBackend,
+};
+
+/// Run condition for the systems that drive the gpu simulation
+pub fn gpu_backend_active(active_backend: Res<ActiveSimulationBackend>) -> bool {
+    active_backend.is_gpu()
+}
+
+/// Steps the gpu backend before the frame is recorded, the frame's compute pass then generates
+/// the instances from the state it leaves
+pub fn gpu_simulation_system(world: &mut World) {
+    let sim_clock = world.resource::<SimClock>();
+    if !sim_clock.should_step() {
+        return;
+    }
+
+    let delta_time = sim_clock.get_step_delta_time();
+    GpuSimulationBackend::new(world).advance(delta_time, 1);
+}
+
+/// Steps the cpu backend and uploads the instances it produced in place of the compute shader's,
+/// or only rebuilds them when the culling changed while paused.
+/// Every body is drawn as the first species, the cpu backend doesn't read particle attributes.
+pub fn cpu_simulation_system(
+    render_resources: Res<RenderResources>,
+    sim_clock: Res<SimClock>,
+    kernel_config: Res<NBodyKernelConfig>,
+    mut active_backend: ResMut<ActiveSimulationBackend>,
+    mut n_body_sim_resources: ResMut<NBodySimResources>,
+) {
+    let ActiveSimulationBackend::Cpu(backend) = active_backend.as_mut() else {
+        return;
+    };
+
+    // steps like the variant the gpu would run
+    backend.set_kernel_config(*kernel_config);
+
+    if sim_clock.should_step() {
+        backend.step(sim_clock.get_step_delta_time(), 1);
+    } else if !n_body_sim_resources.are_instances_stale() {
+        // the culling can change while paused, the camera moving for one
+        return;
+    }
+
+    let species = n_body_sim_resources.get_species()[0].to_gpu();
+    // the lists of the first species are the first ones
+    let (lod_instances, culled) = backend.build_instances(
+        &species,
+        n_body_sim_resources.get_sim_params(),
+        n_body_sim_resources.get_cull_params(),
+    );
+
+    n_body_sim_resources.upload_instances(&render_resources.queue, &lod_instances, culled);
+}
+
+/// B switches between the gpu and cpu simulation backends
+pub fn toggle_simulation_backend_system(world: &mut World) {
+    let toggle = world
+        .resource::<Input>()
+        .keyboard
+        .get_key(KeyCode::KeyB)
+        .is_some_and(|key| key.was_pressed_this_frame());
+
+    if !toggle {
+        return;
+    }
+
+    if world.resource::<ActiveSimulationBackend>().is_gpu() {
+        #[cfg(not(target_arch = "wasm32"))]
+        use_cpu_backend(world);
+        #[cfg(target_arch = "wasm32")]
+        warn!("The cpu backend needs the gpu state read back, which the web can't wait for");
+    } else {
+        use_gpu_backend(world);
+    }
+}
+
+/// Moves the simulation state from the gpu to a new cpu backend
+#[cfg(not(target_arch = "wasm32"))]
+pub fn use_cpu_backend(world: &mut World) {
+    let n_body_sim_resources = world.resource::<NBodySimResources>();
+    let particles =
+        n_body_sim_resources.read_particles_blocking(world.resource::<RenderResources>());
+
+    let particle_softening =
+        n_body_sim_resources.read_softening_blocking(world.resource::<RenderResources>());
+
+    let mut backend = CpuSimulationBackend::from_sim_params(n_body_sim_resources.get_sim_params());
+    backend.set_kernel_config(*world.resource::<NBodyKernelConfig>());
+    backend.upload_state(&particles);
+    backend.set_particle_softening(particle_softening);
+
+    world.insert_resource(ActiveSimulationBackend::Cpu(backend));
+}
+
+/// Writes the cpu backend's state back to the gpu and resumes simulating there
+pub fn use_gpu_backend(world: &mut World) {
+    let ActiveSimulationBackend::Cpu(mut backend) =
+        std::mem::take(world.resource_mut::<ActiveSimulationBackend>().as_mut())
+    else {
+        return;
+    };
+
+    let particles = backend.download_state();
+    world.resource_scope(|world, mut n_body_sim_resources: Mut<NBodySimResources>| {
+        let render_resources = world.resource::<RenderResources>();
+        let layout = world.resource::<NBodySimParamsUniformLayout>();
+        n_body_sim_resources.set_bodies(render_resources, layout, &particles);
+    });
+
+    // rebuild the instances even while paused
+    world.resource_mut::<SimClock>().refresh();
+}
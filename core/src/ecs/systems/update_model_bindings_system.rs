@@ -1,17 +1,23 @@
-use bevy_ecs::system::{Query, Res};
+use bevy_ecs::system::{Query, Res, ResMut};
 
 use crate::{
-    ecs::components::{gpu_bindings::model_bindings::ModelBindings, transform::Transform},
+    ecs::{
+        components::{gpu_bindings::model_bindings::ModelBindings, transform::Transform},
+        resources::model_uniforms::ModelUniforms,
+    },
     gpu_resources::render_resources::RenderResources,
+    utils::buffer::StagingBelt,
 };
 
 pub fn update_model_bindings_system(
     render_resources: Res<RenderResources>,
+    model_uniforms: Res<ModelUniforms>,
+    mut belt: ResMut<StagingBelt>,
     mut model_query: Query<(&mut Transform, &mut ModelBindings)>,
 ) {
-    let queue = &render_resources.queue;
+    let device = &render_resources.device;
 
     for (transform, mut bindings) in model_query.iter_mut() {
-        bindings.update(queue, transform.into_inner());
+        bindings.update(device, &mut belt, &model_uniforms, transform.into_inner());
     }
 }
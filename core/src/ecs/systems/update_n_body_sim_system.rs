@@ -1,18 +1,25 @@
-use bevy_ecs::system::{Query, Res, ResMut};
+use bevy_ecs::system::{Res, ResMut};
 
 use crate::{
-    ecs::resources::{
-        nbody_sim_resources::NBodySimResources, screen_parameters::ScreenParameters, time::Time,
-    },
-    gpu_resources::render_resources::RenderResources,
+    ecs::resources::nbody_sim_resources::NBodySimResources,
+    gpu_resources::render_resources::RenderResources, utils::buffer::StagingBelt,
 };
 
+/// Stages the frame's simulation uploads, they land before the frame's compute pass
 pub fn update_n_body_sim_bindings(
     render_resources: Res<RenderResources>,
-    time: Res<Time>,
-    mut n_body_sim_resources: ResMut<NBodySimResources>,
+    mut belt: ResMut<StagingBelt>,
+    n_body_sim_resources: Res<NBodySimResources>,
 ) {
-    n_body_sim_resources.set_frame_count(time.frame_count as u32);
-    n_body_sim_resources.set_delta_time(&render_resources.queue, time.delta_time);
-    n_body_sim_resources.reset_indirect_buffer(&render_resources.queue);
+    // while the state is unchanged the instances generated last are drawn again
+    if n_body_sim_resources.are_instances_stale() {
+        n_body_sim_resources.stage_indirect_reset(&render_resources.device, &mut belt);
+    }
+}
+
+/// Runs after the frame has been recorded, the instances it generated are drawn from now on
+pub fn present_n_body_sim_instances(mut n_body_sim_resources: ResMut<NBodySimResources>) {
+    if n_body_sim_resources.are_instances_stale() {
+        n_body_sim_resources.present_instances();
+    }
 }
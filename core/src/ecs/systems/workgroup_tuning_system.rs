@@ -0,0 +1,34 @@
+use bevy_ecs::world::World;
+use log::{error, info, warn};
+use winit::keyboard::KeyCode;
+
+use crate::{
+    ecs::resources::{
+        active_simulation_backend::ActiveSimulationBackend, input::Input,
+        workgroup_tuning::WorkgroupTuning,
+    },
+    simulation::workgroup_benchmark::tune_workgroup_size,
+};
+
+/// F4 benchmarks the n-body workgroup sizes on this adapter and switches to the fastest.
+/// The app stalls until the benchmark is done.
+pub fn workgroup_tuning_system(world: &mut World) {
+    let requested = world
+        .resource::<Input>()
+        .keyboard
+        .get_key(KeyCode::F4)
+        .is_some_and(|key| key.was_pressed_this_frame());
+    if !requested || !world.contains_resource::<WorkgroupTuning>() {
+        return;
+    }
+
+    if !world.resource::<ActiveSimulationBackend>().is_gpu() {
+        warn!("Workgroup sizes can only be tuned while the gpu backend is active");
+        return;
+    }
+
+    match tune_workgroup_size(world) {
+        Ok(workgroup_size) => info!("Switched to the fastest workgroup size {}", workgroup_size),
+        Err(e) => error!("Failed to tune the workgroup size: {}", e),
+    }
+}
@@ -0,0 +1,21 @@
+use bevy_ecs::{
+    event::{Event, Events},
+    system::Resource,
+};
+use glam::Vec3;
+
+/// Sent when an unbound particle leaves the escape radius and is removed from the simulation
+#[derive(Event, Debug)]
+pub struct EscapeEvent {
+    /// The id of the particle that escaped
+    pub id: u32,
+    /// The particle's velocity when it escaped
+    pub velocity: Vec3,
+    /// The time at which the escape was processed
+    pub time: f32,
+}
+
+#[derive(Resource)]
+pub struct EscapeEvents {
+    pub events: Events<EscapeEvent>,
+}
@@ -1,17 +1,28 @@
+pub mod escape_event;
 pub mod screen_resize_event;
 
 use bevy_ecs::{event::Events, system::ResMut, world::World};
+use escape_event::{EscapeEvent, EscapeEvents};
 use screen_resize_event::{ScreenResizeEvent, ScreenResizeEvents};
 
 pub fn init_events(world: &mut World) {
     let screen_resize_events = ScreenResizeEvents {
-        events: Events<ScreenResizeEvent>::default(),
+        events: Events::<ScreenResizeEvent>::default(),
+    };
+
+    let escape_events = EscapeEvents {
+        events: Events::<EscapeEvent>::default(),
     };
 
     world.insert_resource(screen_resize_events);
+    world.insert_resource(escape_events);
 }
 
 /// The update system for events... run after late update
-pub fn update_events_system(mut screen_resize_events: ResMut<ScreenResizeEvents>) {
+pub fn update_events_system(
+    mut screen_resize_events: ResMut<ScreenResizeEvents>,
+    mut escape_events: ResMut<EscapeEvents>,
+) {
     screen_resize_events.events.update();
+    escape_events.events.update();
 }
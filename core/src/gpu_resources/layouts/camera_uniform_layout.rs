@@ -1,29 +1,71 @@
 use bevy_ecs::system::Resource;
 
-const CAMERA_LAYOUT_DESCRIPTOR: wgpu::BindGroupLayoutDescriptor = wgpu::BindGroupLayoutDescriptor {
-    label: Some("camera_bind_group_layout"),
-    entries: &[wgpu::BindGroupLayoutEntry {
-        binding: 0,
-        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
-        ty: wgpu::BindingType::Buffer {
-            ty: wgpu::BufferBindingType::Uniform,
-            has_dynamic_offset: false,
-            min_binding_size: None,
-        },
-        count: None,
-    }],
+use crate::gpu_resources::{
+    reflection::ReflectedBindGroupLayout,
+    shaders::{render_particles, render_sprites, unlit_diffuse},
 };
 
+/// The bind group every render shader imports camera.wgsl into
+const CAMERA_GROUP: u32 = 0;
+
 #[derive(Resource)]
 pub struct CameraUniformLayout {
     pub layout: wgpu::BindGroupLayout,
+    /// The camera binding as declared by the render shaders
+    pub reflection: ReflectedBindGroupLayout,
 }
 
 impl CameraUniformLayout {
-    pub fn new(device: &wgpu::Device) -> Self {
-        let layout = device.create_bind_group_layout(&CAMERA_LAYOUT_DESCRIPTOR);
+    /// Builds the layout from the camera binding of every render shader, they share the bind group
+    pub fn new(device: &wgpu::Device) -> Result<Self, String> {
+        let reflection = ReflectedBindGroupLayout::from_shaders(
+            CAMERA_GROUP,
+            &[
+                (
+                    wgpu::ShaderStages::VERTEX,
+                    unlit_diffuse::naga::entry_points::vs_main::EXCLUSIVE_SOURCE,
+                ),
+                (
+                    wgpu::ShaderStages::FRAGMENT,
+                    unlit_diffuse::naga::entry_points::fs_main::EXCLUSIVE_SOURCE,
+                ),
+                (
+                    wgpu::ShaderStages::VERTEX,
+                    render_particles::naga::entry_points::vs_main::EXCLUSIVE_SOURCE,
+                ),
+                (
+                    wgpu::ShaderStages::VERTEX,
+                    render_particles::naga::entry_points::vs_packed::EXCLUSIVE_SOURCE,
+                ),
+                (
+                    wgpu::ShaderStages::VERTEX,
+                    render_particles::naga::entry_points::vs_billboard::EXCLUSIVE_SOURCE,
+                ),
+                (
+                    wgpu::ShaderStages::VERTEX,
+                    render_particles::naga::entry_points::vs_packed_billboard::EXCLUSIVE_SOURCE,
+                ),
+                (
+                    wgpu::ShaderStages::FRAGMENT,
+                    render_particles::naga::entry_points::fs_main::EXCLUSIVE_SOURCE,
+                ),
+                (
+                    wgpu::ShaderStages::VERTEX,
+                    render_sprites::naga::entry_points::vs_main::EXCLUSIVE_SOURCE,
+                ),
+                (
+                    wgpu::ShaderStages::VERTEX,
+                    render_sprites::naga::entry_points::vs_packed::EXCLUSIVE_SOURCE,
+                ),
+                (
+                    wgpu::ShaderStages::FRAGMENT,
+                    render_sprites::naga::entry_points::fs_main::EXCLUSIVE_SOURCE,
+                ),
+            ],
+        )?;
+        let layout = reflection.create_layout(device, "camera_bind_group_layout");
 
-        Self { layout }
+        Ok(Self { layout, reflection })
     }
 
     pub fn create_bind_group(
@@ -7,9 +7,15 @@ pub mod texture_uniform_layout;
 
 pub fn initialize_bind_group_layouts(world: &mut World, device: &wgpu::Device) {
     // Initialize camera uniform bind group layout and insert it into the world
-    world.insert_resource(camera_uniform_layout::CameraUniformLayout::new(device));
+    world.insert_resource(
+        camera_uniform_layout::CameraUniformLayout::new(device)
+            .expect("The render shaders declare the camera binding differently"),
+    );
 
-    world.insert_resource(model_uniform_layout::ModelUniformLayout::new(device));
+    world.insert_resource(
+        model_uniform_layout::ModelUniformLayout::new(device)
+            .expect("The mesh shaders declare an unsupported model binding"),
+    );
 
     // Initialize texture uniform bind group layout and insert it into the world
     world.insert_resource(texture_uniform_layout::TextureUniformLayout::<1>::new(
@@ -25,5 +31,8 @@ pub fn initialize_bind_group_layouts(world: &mut World, device: &wgpu::Device) {
         device,
     ));
 
-    world.insert_resource(nbody_simparams_uniform_layout::NBodySimParamsUniformLayout::new(device));
+    world.insert_resource(
+        nbody_simparams_uniform_layout::NBodySimParamsUniformLayout::new(device)
+            .expect("The n-body compute kernels declare an unsupported binding"),
+    );
 }
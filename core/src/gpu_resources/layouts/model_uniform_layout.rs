@@ -1,41 +1,59 @@
 use bevy_ecs::system::Resource;
 
-const MODEL_LAYOUT_DESCRIPTOR: wgpu::BindGroupLayoutDescriptor = wgpu::BindGroupLayoutDescriptor {
-    label: Some("model_bind_group_layout"),
-    entries: &[wgpu::BindGroupLayoutEntry {
-        binding: 0,
-        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
-        ty: wgpu::BindingType::Buffer {
-            ty: wgpu::BufferBindingType::Uniform,
-            has_dynamic_offset: false,
-            min_binding_size: None,
-        },
-        count: None,
-    }],
+use crate::{
+    gpu_resources::{reflection::ReflectedBindGroupLayout, shaders::unlit_diffuse},
+    utils::buffer::UniformPool,
 };
 
+/// The bind group unlit_diffuse.wgsl imports model.wgsl into
+const MODEL_GROUP: u32 = 1;
+
 #[derive(Resource)]
 pub struct ModelUniformLayout {
     pub layout: wgpu::BindGroupLayout,
+    /// The model binding as declared by the mesh shaders
+    pub reflection: ReflectedBindGroupLayout,
 }
 
 impl ModelUniformLayout {
-    pub fn new(device: &wgpu::Device) -> Self {
-        let layout = device.create_bind_group_layout(&MODEL_LAYOUT_DESCRIPTOR);
-        Self { layout }
+    /// Builds the layout from the model binding of the mesh shaders
+    pub fn new(device: &wgpu::Device) -> Result<Self, String> {
+        let mut reflection = ReflectedBindGroupLayout::from_shaders(
+            MODEL_GROUP,
+            &[
+                (
+                    wgpu::ShaderStages::VERTEX,
+                    unlit_diffuse::naga::entry_points::vs_main::EXCLUSIVE_SOURCE,
+                ),
+                (
+                    wgpu::ShaderStages::FRAGMENT,
+                    unlit_diffuse::naga::entry_points::fs_main::EXCLUSIVE_SOURCE,
+                ),
+            ],
+        )?;
+
+        // every model's uniform is a slot of one shared buffer, which the shader can't declare
+        for binding in &mut reflection.bindings {
+            if let wgpu::BindingType::Buffer {
+                has_dynamic_offset, ..
+            } = &mut binding.entry.ty
+            {
+                *has_dynamic_offset = true;
+            }
+        }
+        let layout = reflection.create_layout(device, "model_bind_group_layout");
+
+        Ok(Self { layout, reflection })
     }
 
-    pub fn create_bind_group(
-        &self,
-        device: &wgpu::Device,
-        buffer: &wgpu::Buffer,
-    ) -> wgpu::BindGroup {
+    /// Creates the bind group shared by every model, bound with the offset of the model's slot
+    pub fn create_bind_group(&self, device: &wgpu::Device, pool: &UniformPool) -> wgpu::BindGroup {
         device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("model_bind_group"),
             layout: &self.layout,
             entries: &[wgpu::BindGroupEntry {
                 binding: 0,
-                resource: buffer.as_entire_binding(),
+                resource: pool.binding(),
             }],
         })
     }
@@ -1,21 +1,44 @@
 use bevy_ecs::system::Resource;
 
 use crate::{
-    gpu_resources::types::{
-        gpu_indirect_args::GpuIndirectArgs, gpu_particle::GpuParticle,
-        gpu_particle_instance::GpuParticleInstance, gpu_sim_params::GpuSimParams,
+    gpu_resources::{
+        reflection::ReflectedBindGroupLayout,
+        shaders::{n_body_sim_compute, render_sprites},
+        types::{
+            gpu_cull_params::GpuCullParams, gpu_culling_counts::GpuCullingCounts,
+            gpu_escape_record::GpuEscapeRecord, gpu_indirect_args::GpuIndirectArgs,
+            gpu_particle::GpuParticle, gpu_particle_instance::GpuParticleInstance,
+            gpu_sim_params::GpuSimParams, gpu_species::GpuSpecies,
+            gpu_system_state::GpuSystemState,
+        },
     },
-    utils::buffer::Buffer,
+    utils::{bind_group::BindGroupBuilder, buffer::Buffer},
 };
 
-const NBODY_SIM_LAYOUT_DESCRIPTOR: wgpu::BindGroupLayoutDescriptor =
+/// The binding of the particle attribute words in the simulation compute bind group
+pub const PARTICLE_ATTRIBUTES_BINDING: u32 = 5;
+/// The binding of the particle attribute words in the render attribute bind group
+pub const PARTICLE_ATTRIBUTES_RENDER_BINDING: u32 = 0;
+
+/// The bind group the simulation kernels declare their resources in
+const NBODY_SIM_GROUP: u32 = 0;
+/// The bind group of the instance set the instances are generated into
+const NBODY_INSTANCE_GROUP: u32 = 1;
+/// The bind group of the Morton reorder buffers, no kernel uses it along with the instance set
+const NBODY_REORDER_GROUP: u32 = 1;
+/// The bind group of the cull params in the sprite shader, after the camera
+const NBODY_SPRITE_GROUP: u32 = 1;
+
+// The render shader's entry points don't reference `particle_attributes`, so it can't be
+// reflected from their sources and stays written out by hand.
+const PARTICLE_ATTRIBUTES_LAYOUT_DESCRIPTOR: wgpu::BindGroupLayoutDescriptor =
     wgpu::BindGroupLayoutDescriptor {
-        label: Some("N-Body Compute Bind Group Layout"),
+        label: Some("Particle Attributes Bind Group Layout"),
         entries: &[
-            // @binding(0) var<storage, read> particles: array<Particle>;
+            // @binding(0) var<storage, read> particle_attributes: array<u32>;
             wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::COMPUTE,
+                binding: PARTICLE_ATTRIBUTES_RENDER_BINDING,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
                 ty: wgpu::BindingType::Buffer {
                     ty: wgpu::BufferBindingType::Storage { read_only: true },
                     has_dynamic_offset: false,
@@ -23,99 +46,252 @@ const NBODY_SIM_LAYOUT_DESCRIPTOR: wgpu::BindGroupLayoutDescriptor =
                 },
                 count: None,
             },
-            // @binding(1) var<storage, read_write> newParticles: array<Particle>;
-            wgpu::BindGroupLayoutEntry {
-                binding: 1,
-                visibility: wgpu::ShaderStages::COMPUTE,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Storage { read_only: false },
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            },
-            // @binding(2) var<uniform> params: SimParams;
-            wgpu::BindGroupLayoutEntry {
-                binding: 2,
-                visibility: wgpu::ShaderStages::COMPUTE,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            },
-            // @binding(3) var<storage, read_write> instanceBuffer: array<Instance>;
-            wgpu::BindGroupLayoutEntry {
-                binding: 3,
-                visibility: wgpu::ShaderStages::COMPUTE,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Storage { read_only: false },
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            },
-            // @binding(4) var<storage, read_write> indirect_buffer: IndirectArgs;
-            wgpu::BindGroupLayoutEntry {
-                binding: 4,
-                visibility: wgpu::ShaderStages::COMPUTE,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Storage { read_only: false },
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            },
         ],
     };
 
+/// The buffers bound to the simulation compute bind group
+#[derive(Clone, Copy)]
+pub struct NBodySimBindings<'a> {
+    pub particles: &'a Buffer<GpuParticle>,
+    pub new_particles: &'a Buffer<GpuParticle>,
+    pub sim_params: &'a Buffer<GpuSimParams>,
+    pub system_state: &'a Buffer<GpuSystemState>,
+    pub escapes: &'a Buffer<GpuEscapeRecord>,
+    pub particle_attributes: &'a Buffer<u32>,
+    pub species: &'a Buffer<GpuSpecies>,
+}
+
+/// The buffers of one instance set, bound to the instance generation kernels
+#[derive(Clone, Copy)]
+pub struct NBodyInstanceBindings<'a> {
+    pub instance_buffer: &'a Buffer<GpuParticleInstance>,
+    pub indirect_buffer: &'a Buffer<GpuIndirectArgs>,
+    pub culling_counts: &'a Buffer<GpuCullingCounts>,
+    pub cull_params: &'a Buffer<GpuCullParams>,
+}
+
+/// The particle buffers and params the reorder kernels read, bound in place of the simulation
+/// bind group
+#[derive(Clone, Copy)]
+pub struct NBodyReorderSimBindings<'a> {
+    pub particles: &'a Buffer<GpuParticle>,
+    pub new_particles: &'a Buffer<GpuParticle>,
+    pub sim_params: &'a Buffer<GpuSimParams>,
+}
+
+/// The buffers of the Morton reorder, bound to the reorder kernels
+#[derive(Clone, Copy)]
+pub struct NBodyReorderBindings<'a> {
+    pub morton_codes: &'a Buffer<u32>,
+    pub permutation: &'a Buffer<u32>,
+    pub bounds: &'a Buffer<u32>,
+    pub attributes: &'a Buffer<u32>,
+    pub source_attributes: &'a Buffer<u32>,
+}
+
 #[derive(Resource)]
 pub struct NBodySimParamsUniformLayout {
     pub layout: wgpu::BindGroupLayout,
+    /// The simulation bindings as declared by the compute kernels
+    pub reflection: ReflectedBindGroupLayout,
+    pub instance_layout: wgpu::BindGroupLayout,
+    /// The instance set bindings as declared by the instance generation kernels
+    pub instance_reflection: ReflectedBindGroupLayout,
+    /// Group 0 of the reorder kernels, only the simulation bindings they read
+    pub reorder_sim_layout: wgpu::BindGroupLayout,
+    pub reorder_sim_reflection: ReflectedBindGroupLayout,
+    pub reorder_layout: wgpu::BindGroupLayout,
+    /// The Morton reorder bindings as declared by the reorder kernels
+    pub reorder_reflection: ReflectedBindGroupLayout,
+    /// Read only view of the particle attributes for the render passes
+    pub attribute_layout: wgpu::BindGroupLayout,
+    pub sprite_layout: wgpu::BindGroupLayout,
+    /// The cull params binding as declared by the sprite vertex shaders
+    pub sprite_reflection: ReflectedBindGroupLayout,
 }
 
 impl NBodySimParamsUniformLayout {
-    pub fn new(device: &wgpu::Device) -> Self {
-        let layout = device.create_bind_group_layout(&NBODY_SIM_LAYOUT_DESCRIPTOR);
+    /// Builds the simulation layout from the bindings the compute kernels declare
+    pub fn new(device: &wgpu::Device) -> Result<Self, String> {
+        let reflection = ReflectedBindGroupLayout::from_shaders(
+            NBODY_SIM_GROUP,
+            &[
+                (
+                    wgpu::ShaderStages::COMPUTE,
+                    n_body_sim_compute::naga::entry_points::cs_main::EXCLUSIVE_SOURCE,
+                ),
+                (
+                    wgpu::ShaderStages::COMPUTE,
+                    n_body_sim_compute::naga::entry_points::cs_center_of_mass::EXCLUSIVE_SOURCE,
+                ),
+                (
+                    wgpu::ShaderStages::COMPUTE,
+                    n_body_sim_compute::naga::entry_points::cs_generate_instances::EXCLUSIVE_SOURCE,
+                ),
+            ],
+        )?;
+        let layout = reflection.create_layout(device, "N-Body Compute Bind Group Layout");
+        let instance_reflection = ReflectedBindGroupLayout::from_shaders(
+            NBODY_INSTANCE_GROUP,
+            &[
+                (
+                    wgpu::ShaderStages::COMPUTE,
+                    n_body_sim_compute::naga::entry_points::cs_generate_instances::EXCLUSIVE_SOURCE,
+                ),
+                (
+                    wgpu::ShaderStages::COMPUTE,
+                    n_body_sim_compute::naga::entry_points::cs_clamp_instance_counts::EXCLUSIVE_SOURCE,
+                ),
+            ],
+        )?;
+        let instance_layout =
+            instance_reflection.create_layout(device, "N-Body Instance Bind Group Layout");
+        let reorder_shaders = [
+            (
+                wgpu::ShaderStages::COMPUTE,
+                n_body_sim_compute::naga::entry_points::cs_particle_bounds::EXCLUSIVE_SOURCE,
+            ),
+            (
+                wgpu::ShaderStages::COMPUTE,
+                n_body_sim_compute::naga::entry_points::cs_morton_codes::EXCLUSIVE_SOURCE,
+            ),
+            (
+                wgpu::ShaderStages::COMPUTE,
+                n_body_sim_compute::naga::entry_points::cs_gather_particles::EXCLUSIVE_SOURCE,
+            ),
+        ];
+        let reorder_sim_reflection =
+            ReflectedBindGroupLayout::from_shaders(NBODY_SIM_GROUP, &reorder_shaders)?;
+        let reorder_sim_layout = reorder_sim_reflection
+            .create_layout(device, "N-Body Reorder Compute Bind Group Layout");
+        let reorder_reflection =
+            ReflectedBindGroupLayout::from_shaders(NBODY_REORDER_GROUP, &reorder_shaders)?;
+        let reorder_layout =
+            reorder_reflection.create_layout(device, "N-Body Reorder Bind Group Layout");
+        let attribute_layout =
+            device.create_bind_group_layout(&PARTICLE_ATTRIBUTES_LAYOUT_DESCRIPTOR);
+        let sprite_reflection = ReflectedBindGroupLayout::from_shaders(
+            NBODY_SPRITE_GROUP,
+            &[
+                (
+                    wgpu::ShaderStages::VERTEX,
+                    render_sprites::naga::entry_points::vs_main::EXCLUSIVE_SOURCE,
+                ),
+                (
+                    wgpu::ShaderStages::VERTEX,
+                    render_sprites::naga::entry_points::vs_packed::EXCLUSIVE_SOURCE,
+                ),
+            ],
+        )?;
+        let sprite_layout =
+            sprite_reflection.create_layout(device, "N-Body Sprite Bind Group Layout");
 
-        Self { layout }
+        Ok(Self {
+            layout,
+            reflection,
+            instance_layout,
+            instance_reflection,
+            reorder_sim_layout,
+            reorder_sim_reflection,
+            reorder_layout,
+            reorder_reflection,
+            attribute_layout,
+            sprite_layout,
+            sprite_reflection,
+        })
     }
 
+    /// Creates the simulation bind group, failing if a buffer doesn't match what the kernels declare
     pub fn create_bind_group(
         &self,
         device: &wgpu::Device,
-        particles: &Buffer<GpuParticle>,
-        new_particles: &Buffer<GpuParticle>,
-        sim_params: &Buffer<GpuSimParams>,
-        instance_buffer: &Buffer<GpuParticleInstance>,
-        indirect_buffer: &Buffer<GpuIndirectArgs>,
+        bindings: &NBodySimBindings,
+    ) -> Result<wgpu::BindGroup, String> {
+        BindGroupBuilder::new(device, &self.layout, &self.reflection)
+            .label("N-Body Compute Bind Group")
+            .buffer(0, bindings.particles)
+            .buffer(1, bindings.new_particles)
+            .buffer(2, bindings.sim_params)
+            .buffer(3, bindings.system_state)
+            .buffer(4, bindings.escapes)
+            .buffer(PARTICLE_ATTRIBUTES_BINDING, bindings.particle_attributes)
+            .buffer(6, bindings.species)
+            .build()
+    }
+
+    /// Creates the bind group of one instance set, failing if a buffer doesn't match the kernel
+    pub fn create_instance_bind_group(
+        &self,
+        device: &wgpu::Device,
+        bindings: &NBodyInstanceBindings,
+    ) -> Result<wgpu::BindGroup, String> {
+        BindGroupBuilder::new(device, &self.instance_layout, &self.instance_reflection)
+            .label("N-Body Instance Bind Group")
+            .buffer(0, bindings.instance_buffer)
+            .buffer(1, bindings.indirect_buffer)
+            .buffer(2, bindings.culling_counts)
+            .buffer(3, bindings.cull_params)
+            .build()
+    }
+
+    /// Creates group 0 of the reorder kernels, failing if a buffer doesn't match
+    pub fn create_reorder_sim_bind_group(
+        &self,
+        device: &wgpu::Device,
+        bindings: &NBodyReorderSimBindings,
+    ) -> Result<wgpu::BindGroup, String> {
+        BindGroupBuilder::new(
+            device,
+            &self.reorder_sim_layout,
+            &self.reorder_sim_reflection,
+        )
+        .label("N-Body Reorder Compute Bind Group")
+        .buffer(0, bindings.particles)
+        .buffer(1, bindings.new_particles)
+        .buffer(2, bindings.sim_params)
+        .build()
+    }
+
+    /// Creates the bind group of the Morton reorder buffers, failing if a buffer doesn't match
+    pub fn create_reorder_bind_group(
+        &self,
+        device: &wgpu::Device,
+        bindings: &NBodyReorderBindings,
+    ) -> Result<wgpu::BindGroup, String> {
+        BindGroupBuilder::new(device, &self.reorder_layout, &self.reorder_reflection)
+            .label("N-Body Reorder Bind Group")
+            .buffer(0, bindings.morton_codes)
+            .buffer(1, bindings.permutation)
+            .buffer(2, bindings.bounds)
+            .buffer(3, bindings.attributes)
+            .buffer(4, bindings.source_attributes)
+            .build()
+    }
+
+    /// Creates the bind group of the sprite shaders, failing if the buffer doesn't match
+    pub fn create_sprite_bind_group(
+        &self,
+        device: &wgpu::Device,
+        cull_params: &Buffer<GpuCullParams>,
+    ) -> Result<wgpu::BindGroup, String> {
+        BindGroupBuilder::new(device, &self.sprite_layout, &self.sprite_reflection)
+            .label("N-Body Sprite Bind Group")
+            .buffer(0, cull_params)
+            .build()
+    }
+
+    pub fn create_attribute_bind_group(
+        &self,
+        device: &wgpu::Device,
+        attributes: &Buffer<u32>,
     ) -> wgpu::BindGroup {
         device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("camera_bind_group"),
-            layout: &self.layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: particles.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: new_particles.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: sim_params.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 3,
-                    resource: instance_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 4,
-                    resource: indirect_buffer.as_entire_binding(),
-                },
-            ],
+            label: Some("particle_attributes_bind_group"),
+            layout: &self.attribute_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: PARTICLE_ATTRIBUTES_RENDER_BINDING,
+                resource: attributes.as_entire_binding(),
+            }],
         })
     }
 }
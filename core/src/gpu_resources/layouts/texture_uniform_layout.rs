@@ -8,6 +8,8 @@ pub struct TextureUniformLayout<const N: usize> {
 }
 
 impl<const N: usize> TextureUniformLayout<N> {
+    // Written out by hand rather than reflected, the layouts with more than one pair are for
+    // materials no shader declares yet.
     pub fn new(device: &wgpu::Device) -> Self {
         // Generate entries dynamically based on N (number of texture-sampler pairs)
         let mut entries = Vec::with_capacity(N * 2);
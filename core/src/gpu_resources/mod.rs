@@ -2,9 +2,20 @@ use std::sync::Arc;
 
 use bevy_ecs::world::World;
 
+use crate::{
+    ecs::resources::model_uniforms::ModelUniforms,
+    utils::buffer::{STAGING_CHUNK_SIZE, StagingBelt},
+};
+
 pub mod layouts;
+pub mod particle_storage;
 pub mod pipelines;
+pub mod reflection;
 pub mod render_resources;
+#[cfg(feature = "debug-shader-hot-reload")]
+pub mod shader_hot_reload;
+pub mod shader_preprocessor;
+pub mod shader_variants;
 mod shaders;
 pub mod types;
 
@@ -19,5 +30,11 @@ pub fn initialize_gpu_resources(
     world.insert_resource(render_resources);
 
     layouts::initialize_bind_group_layouts(world, &device);
+    world.insert_resource(StagingBelt::new(STAGING_CHUNK_SIZE));
+    let model_uniforms = ModelUniforms::new(
+        &device,
+        world.resource::<layouts::model_uniform_layout::ModelUniformLayout>(),
+    );
+    world.insert_resource(model_uniforms);
     pipelines::initialize_pipelines(world);
 }
@@ -0,0 +1,225 @@
+use std::ops::Range;
+
+use crate::{
+    gpu_resources::types::{
+        gpu_packed_particle_instance::GpuPackedParticleInstance, gpu_particle::GpuParticle,
+        gpu_particle_instance::GpuParticleInstance,
+    },
+    utils::buffer::Buffer,
+};
+
+/// A particle is stored as two vec4 words, its position and mass then its velocity and id
+type ParticleWord = [u32; 4];
+
+const WORD_SIZE: u64 = std::mem::size_of::<ParticleWord>() as u64;
+
+/// How the particle buffers store their particles.
+/// Either way a particle takes two 16 byte words, on the cpu it is always a `GpuParticle`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ParticleLayout {
+    /// The two words of a particle are next to each other
+    #[default]
+    ArrayOfStructs,
+    /// Every position and mass comes first, the velocities and ids start at the buffer's capacity.
+    /// The force loop only reads positions, so it streams them without skipping the velocities.
+    StructOfArrays,
+}
+
+impl ParticleLayout {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "aos" => Ok(Self::ArrayOfStructs),
+            "soa" => Ok(Self::StructOfArrays),
+            _ => Err(format!(
+                "Unknown particle layout '{}', expected aos or soa",
+                name
+            )),
+        }
+    }
+
+    pub fn get_name(self) -> &'static str {
+        match self {
+            Self::ArrayOfStructs => "aos",
+            Self::StructOfArrays => "soa",
+        }
+    }
+
+    /// The `#define` value selecting this layout in n-body-sim-compute.wgsl
+    pub fn define_value(self) -> u32 {
+        match self {
+            Self::ArrayOfStructs => 0,
+            Self::StructOfArrays => 1,
+        }
+    }
+
+    /// The byte ranges holding the particles of `slots` in a buffer laid out for `capacity`
+    /// particles. The ranges of the same number of slots line up between any two buffers.
+    fn byte_ranges(self, slots: Range<usize>, capacity: usize) -> Vec<Range<u64>> {
+        let (start, end) = (slots.start as u64, slots.end as u64);
+        match self {
+            Self::ArrayOfStructs => vec![start * 2 * WORD_SIZE..end * 2 * WORD_SIZE],
+            Self::StructOfArrays => {
+                let velocities = capacity as u64;
+                vec![
+                    start * WORD_SIZE..end * WORD_SIZE,
+                    (velocities + start) * WORD_SIZE..(velocities + end) * WORD_SIZE,
+                ]
+            }
+        }
+    }
+
+    /// Records a copy of `count` particles between two buffers in this layout
+    pub fn copy(
+        self,
+        encoder: &mut wgpu::CommandEncoder,
+        source: ParticleSlots,
+        destination: ParticleSlots,
+        count: usize,
+    ) {
+        if count == 0 {
+            return;
+        }
+
+        let source_ranges = self.byte_ranges(source.slots(count), source.capacity);
+        let destination_ranges = self.byte_ranges(destination.slots(count), destination.capacity);
+        for (from, to) in source_ranges.into_iter().zip(destination_ranges) {
+            encoder.copy_buffer_to_buffer(
+                &source.buffer.buffer,
+                from.start,
+                &destination.buffer.buffer,
+                to.start,
+                from.end - from.start,
+            );
+        }
+    }
+
+    /// Writes the particles to the slots of the destination
+    pub fn write(self, queue: &wgpu::Queue, destination: ParticleSlots, particles: &[GpuParticle]) {
+        if particles.is_empty() {
+            return;
+        }
+
+        let ranges = self.byte_ranges(destination.slots(particles.len()), destination.capacity);
+        let words = self.encode(particles);
+        for (range, words) in ranges.into_iter().zip(words) {
+            queue.write_buffer(
+                &destination.buffer.buffer,
+                range.start,
+                bytemuck::cast_slice(&words),
+            );
+        }
+    }
+
+    /// The words of the particles, one list per range of `byte_ranges`
+    fn encode(self, particles: &[GpuParticle]) -> Vec<Vec<ParticleWord>> {
+        let words: &[[ParticleWord; 2]] = bytemuck::cast_slice(particles);
+        match self {
+            Self::ArrayOfStructs => vec![words.iter().flatten().copied().collect()],
+            Self::StructOfArrays => vec![
+                words.iter().map(|word| word[0]).collect(),
+                words.iter().map(|word| word[1]).collect(),
+            ],
+        }
+    }
+
+    /// Decodes `count` particles copied from slot 0 on into a buffer laid out for exactly `count`
+    pub fn read(self, data: &[u8], count: usize) -> Vec<GpuParticle> {
+        let size = count * std::mem::size_of::<GpuParticle>();
+        match self {
+            Self::ArrayOfStructs => bytemuck::pod_collect_to_vec(&data[..size]),
+            Self::StructOfArrays => {
+                let words: Vec<ParticleWord> = bytemuck::pod_collect_to_vec(&data[..size]);
+                let (positions, velocities) = words.split_at(count);
+                positions
+                    .iter()
+                    .zip(velocities)
+                    .map(|(position, velocity)| bytemuck::cast([*position, *velocity]))
+                    .collect()
+            }
+        }
+    }
+}
+
+/// The particles of a buffer from `first_slot` on, the buffer laid out for `capacity` particles.
+/// Buffers holding a copy of the particles, like snapshots and readbacks, are laid out for the
+/// number of particles copied.
+#[derive(Clone, Copy)]
+pub struct ParticleSlots<'a> {
+    pub buffer: &'a Buffer<GpuParticle>,
+    pub capacity: usize,
+    pub first_slot: usize,
+}
+
+impl<'a> ParticleSlots<'a> {
+    pub fn new(buffer: &'a Buffer<GpuParticle>, capacity: usize, first_slot: usize) -> Self {
+        Self {
+            buffer,
+            capacity,
+            first_slot,
+        }
+    }
+
+    fn slots(&self, count: usize) -> Range<usize> {
+        self.first_slot..self.first_slot + count
+    }
+}
+
+/// How the instances drawn by the particle renderer are stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum InstanceFormat {
+    /// `GpuParticleInstance`, full precision with the velocity and id, 64 bytes
+    #[default]
+    Full,
+    /// `GpuPackedParticleInstance`, half float position and size and an rgba8 color, 16 bytes
+    Packed,
+}
+
+impl InstanceFormat {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "full" => Ok(Self::Full),
+            "packed" => Ok(Self::Packed),
+            _ => Err(format!(
+                "Unknown instance format '{}', expected full or packed",
+                name
+            )),
+        }
+    }
+
+    pub fn get_name(self) -> &'static str {
+        match self {
+            Self::Full => "full",
+            Self::Packed => "packed",
+        }
+    }
+
+    /// The `#define` value selecting this format in n-body-sim-compute.wgsl
+    pub fn define_value(self) -> u32 {
+        match self {
+            Self::Full => 0,
+            Self::Packed => 1,
+        }
+    }
+
+    /// Bytes per instance, the stride of the instance vertex buffer
+    pub fn get_instance_size(self) -> u64 {
+        match self {
+            Self::Full => std::mem::size_of::<GpuParticleInstance>() as u64,
+            Self::Packed => std::mem::size_of::<GpuPackedParticleInstance>() as u64,
+        }
+    }
+
+    /// The bytes of the instances in this format
+    pub fn encode(self, instances: &[GpuParticleInstance]) -> Vec<u8> {
+        match self {
+            Self::Full => bytemuck::cast_slice(instances).to_vec(),
+            Self::Packed => {
+                let packed: Vec<GpuPackedParticleInstance> = instances
+                    .iter()
+                    .map(GpuPackedParticleInstance::from_instance)
+                    .collect();
+                bytemuck::cast_slice(&packed).to_vec()
+            }
+        }
+    }
+}
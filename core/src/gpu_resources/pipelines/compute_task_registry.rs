@@ -0,0 +1,504 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy_ecs::{system::Resource, world::World};
+use log::{info, warn};
+
+use crate::{
+    gpu_resources::{
+        reflection::ReflectedBindGroupLayout,
+        render_resources::RenderResources,
+        shader_variants::{ShaderDefines, create_checked, create_shader_variant},
+    },
+    traits::compute_task_traits::{ComputeKernel, ComputeTask},
+};
+
+struct RegisteredComputeTask {
+    order: i32,
+    task: Box<dyn ComputeTask>,
+    pipeline_layout: wgpu::PipelineLayout,
+    kernels: Vec<ComputeKernel>,
+    // every variant built so far, one pipeline per kernel in the same order
+    variants: HashMap<ShaderDefines, Vec<wgpu::ComputePipeline>>,
+    // the defines of the variant that is dispatched, always in variants
+    active_variant: ShaderDefines,
+    // variants that failed to build, not retried until their source changes
+    failed_variants: HashSet<ShaderDefines>,
+}
+
+impl RegisteredComputeTask {
+    fn get_pipelines(&self) -> &[wgpu::ComputePipeline] {
+        &self.variants[&self.active_variant]
+    }
+
+    #[cfg(feature = "debug-shader-hot-reload")]
+    fn uses_source_file(&self, source_file: &str) -> bool {
+        self.kernels
+            .iter()
+            .any(|kernel| kernel.source_file == source_file)
+    }
+}
+
+/// The compute tasks run in the frame's compute pass, sorted by their order.
+/// Tasks with the same order run in the order they were registered.
+///
+/// The pipelines of a task are built per set of `shader_defines`, `prepare` switches every task
+/// to the variant it currently asks for and keeps the variants built before around.
+#[derive(Resource, Default)]
+pub struct ComputeTaskRegistry {
+    tasks: Vec<RegisteredComputeTask>,
+    // sources reloaded from disk, used instead of the ones the kernels were compiled with
+    #[cfg(feature = "debug-shader-hot-reload")]
+    source_overrides: HashMap<&'static str, String>,
+}
+
+impl ComputeTaskRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds the pipelines of the task and gives it a slot in the compute pass
+    pub fn register(
+        &mut self,
+        world: &World,
+        order: i32,
+        task: impl ComputeTask + 'static,
+    ) -> Result<(), String> {
+        let label = task.label();
+        if self.contains(label) {
+            return Err(format!(
+                "A compute task named {} is already registered",
+                label
+            ));
+        }
+
+        let device = &world.resource::<RenderResources>().device;
+        check_limits(label, &task.bind_group_reflections(world), &device.limits())?;
+
+        let bind_group_layouts = task.bind_group_layouts(world);
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(&format!("{} Pipeline Layout", label)),
+            bind_group_layouts: &bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+
+        let kernels = task.kernels();
+        let defines = task.shader_defines(world);
+        let pipelines =
+            self.build_pipelines(device, label, &pipeline_layout, &kernels, &defines)?;
+
+        // insert after every task with a lower or equal order
+        let index = self
+            .tasks
+            .partition_point(|registered| registered.order <= order);
+        self.tasks.insert(
+            index,
+            RegisteredComputeTask {
+                order,
+                task: Box::new(task),
+                pipeline_layout,
+                kernels,
+                variants: HashMap::from([(defines.clone(), pipelines)]),
+                active_variant: defines,
+                failed_variants: HashSet::new(),
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Removes the task with the given label, returns whether it was registered
+    pub fn remove(&mut self, label: &str) -> bool {
+        let count = self.tasks.len();
+        self.tasks
+            .retain(|registered| registered.task.label() != label);
+        self.tasks.len() != count
+    }
+
+    pub fn contains(&self, label: &str) -> bool {
+        self.tasks
+            .iter()
+            .any(|registered| registered.task.label() == label)
+    }
+
+    /// The labels of the registered tasks in the order they run
+    pub fn get_labels(&self) -> Vec<&'static str> {
+        self.tasks
+            .iter()
+            .map(|registered| registered.task.label())
+            .collect()
+    }
+
+    /// The defines the pipelines of the task in use were built with
+    pub fn get_active_defines(&self, label: &str) -> Option<&ShaderDefines> {
+        self.tasks
+            .iter()
+            .find(|registered| registered.task.label() == label)
+            .map(|registered| &registered.active_variant)
+    }
+
+    /// Switches every task to the variant its `shader_defines` ask for, building it the first
+    /// time it is asked for. A variant that fails to build is logged once and the task keeps
+    /// running the variant it had.
+    pub fn prepare(&mut self, world: &World) {
+        let device = &world.resource::<RenderResources>().device;
+
+        let requested: Vec<(usize, ShaderDefines)> = self
+            .tasks
+            .iter()
+            .enumerate()
+            .map(|(index, registered)| (index, registered.task.shader_defines(world)))
+            .filter(|(index, defines)| {
+                let registered = &self.tasks[*index];
+                *defines != registered.active_variant
+                    && !registered.failed_variants.contains(defines)
+            })
+            .collect();
+
+        for (index, defines) in requested {
+            let registered = &self.tasks[index];
+            let label = registered.task.label();
+
+            if !registered.variants.contains_key(&defines) {
+                match self.build_pipelines(
+                    device,
+                    label,
+                    &registered.pipeline_layout,
+                    &registered.kernels,
+                    &defines,
+                ) {
+                    Ok(pipelines) => {
+                        info!("Built compute task {} variant {:?}", label, defines);
+                        self.tasks[index]
+                            .variants
+                            .insert(defines.clone(), pipelines);
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Failed to build compute task {} variant {:?}:\n{}",
+                            label, defines, e
+                        );
+                        self.tasks[index].failed_variants.insert(defines);
+                        continue;
+                    }
+                }
+            }
+
+            self.tasks[index].active_variant = defines;
+        }
+    }
+
+    /// The shader files the registered kernels are built from
+    #[cfg(feature = "debug-shader-hot-reload")]
+    pub fn get_source_files(&self) -> Vec<&'static str> {
+        let mut source_files: Vec<&'static str> = Vec::new();
+        for registered in &self.tasks {
+            for kernel in &registered.kernels {
+                if !source_files.contains(&kernel.source_file) {
+                    source_files.push(kernel.source_file);
+                }
+            }
+        }
+        source_files
+    }
+
+    /// Replaces the source of every kernel built from `source_file` and rebuilds the variants
+    /// in use, returns how many pipelines were rebuilt. The other variants are dropped and
+    /// built again when next asked for. If any fails to build the old source is kept.
+    #[cfg(feature = "debug-shader-hot-reload")]
+    pub fn reload_source(
+        &mut self,
+        device: &wgpu::Device,
+        source_file: &'static str,
+        source: String,
+    ) -> Result<usize, String> {
+        let previous = self.source_overrides.insert(source_file, source);
+
+        let mut rebuilt = Vec::new();
+        for (index, registered) in self.tasks.iter().enumerate() {
+            if !registered.uses_source_file(source_file) {
+                continue;
+            }
+
+            match self.build_pipelines(
+                device,
+                registered.task.label(),
+                &registered.pipeline_layout,
+                &registered.kernels,
+                &registered.active_variant,
+            ) {
+                Ok(pipelines) => rebuilt.push((index, pipelines)),
+                Err(e) => {
+                    match previous {
+                        Some(previous) => self.source_overrides.insert(source_file, previous),
+                        None => self.source_overrides.remove(source_file),
+                    };
+                    return Err(e);
+                }
+            }
+        }
+
+        let mut count = 0;
+        for (index, pipelines) in rebuilt {
+            let registered = &mut self.tasks[index];
+            count += pipelines.len();
+            registered.variants.clear();
+            registered.failed_variants.clear();
+            registered
+                .variants
+                .insert(registered.active_variant.clone(), pipelines);
+        }
+        Ok(count)
+    }
+
+    /// The source a kernel is built from, the reloaded one if its file changed on disk
+    fn get_kernel_source<'a>(&'a self, kernel: &ComputeKernel) -> &'a str {
+        #[cfg(feature = "debug-shader-hot-reload")]
+        let reloaded = self
+            .source_overrides
+            .get(kernel.source_file)
+            .map(String::as_str);
+        #[cfg(not(feature = "debug-shader-hot-reload"))]
+        let reloaded: Option<&str> = None;
+
+        reloaded.unwrap_or(kernel.source)
+    }
+
+    /// Builds the pipeline of every kernel for one set of defines.
+    /// Each source file is preprocessed and compiled once for all the kernels in it.
+    fn build_pipelines(
+        &self,
+        device: &wgpu::Device,
+        label: &str,
+        pipeline_layout: &wgpu::PipelineLayout,
+        kernels: &[ComputeKernel],
+        defines: &[(&'static str, String)],
+    ) -> Result<Vec<wgpu::ComputePipeline>, String> {
+        let mut modules: Vec<(&str, wgpu::ShaderModule)> = Vec::new();
+        let mut pipelines = Vec::with_capacity(kernels.len());
+
+        for kernel in kernels {
+            let module_index = match modules
+                .iter()
+                .position(|(source_file, _)| *source_file == kernel.source_file)
+            {
+                Some(module_index) => module_index,
+                None => {
+                    let module = create_shader_variant(
+                        device,
+                        kernel.source_file,
+                        self.get_kernel_source(kernel),
+                        defines,
+                    )?;
+                    modules.push((kernel.source_file, module));
+                    modules.len() - 1
+                }
+            };
+
+            let pipeline = create_checked(device, || {
+                device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some(&format!("{} {}", label, kernel.entry_point)),
+                    layout: Some(pipeline_layout),
+                    module: &modules[module_index].1,
+                    entry_point: kernel.entry_point,
+                    compilation_options: Default::default(),
+                })
+            })
+            .map_err(|e| format!("{} {}: {}", label, kernel.entry_point, e))?;
+            pipelines.push(pipeline);
+        }
+
+        Ok(pipelines)
+    }
+
+    /// Records every enabled task into the compute pass, in order
+    pub fn dispatch<'a>(&'a self, world: &'a World, compute_pass: &mut wgpu::ComputePass<'a>) {
+        for registered in &self.tasks {
+            if registered.task.is_enabled(world) {
+                Self::dispatch_registered(registered, world, compute_pass);
+            }
+        }
+    }
+
+    /// Records a single task into the compute pass whether or not it is enabled
+    pub fn dispatch_task<'a>(
+        &'a self,
+        world: &'a World,
+        label: &str,
+        compute_pass: &mut wgpu::ComputePass<'a>,
+    ) -> Result<(), String> {
+        let registered = self
+            .tasks
+            .iter()
+            .find(|registered| registered.task.label() == label)
+            .ok_or(format!("No compute task named {}", label))?;
+
+        Self::dispatch_registered(registered, world, compute_pass);
+        Ok(())
+    }
+
+    fn dispatch_registered<'a>(
+        registered: &'a RegisteredComputeTask,
+        world: &'a World,
+        compute_pass: &mut wgpu::ComputePass<'a>,
+    ) {
+        for (index, bind_group) in registered.task.bind_groups(world).into_iter().enumerate() {
+            compute_pass.set_bind_group(index as u32, bind_group, &[]);
+        }
+
+        for (kernel, pipeline) in registered.get_pipelines().iter().enumerate() {
+            let Some([x, y, z]) =
+                registered
+                    .task
+                    .dispatch_size(world, kernel, &registered.active_variant)
+            else {
+                continue;
+            };
+
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.dispatch_workgroups(x, y, z);
+        }
+    }
+}
+
+/// The buffers of the given binding type the bind groups make visible to `stage`
+fn buffers_per_stage(
+    reflections: &[&ReflectedBindGroupLayout],
+    stage: wgpu::ShaderStages,
+    is_counted: impl Fn(wgpu::BufferBindingType) -> bool,
+) -> u32 {
+    reflections
+        .iter()
+        .flat_map(|reflection| &reflection.bindings)
+        .filter(|binding| binding.entry.visibility.contains(stage))
+        .filter(|binding| match binding.entry.ty {
+            wgpu::BindingType::Buffer { ty, .. } => is_counted(ty),
+            _ => false,
+        })
+        .count() as u32
+}
+
+/// Fails if the bind groups of a task take more than the device was created with,
+/// creating its pipeline layout would fail instead
+fn check_limits(
+    label: &str,
+    reflections: &[&ReflectedBindGroupLayout],
+    limits: &wgpu::Limits,
+) -> Result<(), String> {
+    if reflections.len() as u32 > limits.max_bind_groups {
+        return Err(format!(
+            "Compute task {} uses {} bind groups, the limit is {}",
+            label,
+            reflections.len(),
+            limits.max_bind_groups
+        ));
+    }
+
+    let storage_buffers = buffers_per_stage(reflections, wgpu::ShaderStages::COMPUTE, |ty| {
+        matches!(ty, wgpu::BufferBindingType::Storage { .. })
+    });
+    if storage_buffers > limits.max_storage_buffers_per_shader_stage {
+        return Err(format!(
+            "Compute task {} binds {} storage buffers, the limit is {}",
+            label, storage_buffers, limits.max_storage_buffers_per_shader_stage
+        ));
+    }
+
+    let uniform_buffers = buffers_per_stage(reflections, wgpu::ShaderStages::COMPUTE, |ty| {
+        matches!(ty, wgpu::BufferBindingType::Uniform)
+    });
+    if uniform_buffers > limits.max_uniform_buffers_per_shader_stage {
+        return Err(format!(
+            "Compute task {} binds {} uniform buffers, the limit is {}",
+            label, uniform_buffers, limits.max_uniform_buffers_per_shader_stage
+        ));
+    }
+
+    Ok(())
+}
+
+/// Registers a compute task with the world's registry
+pub fn register_compute_task(
+    world: &mut World,
+    order: i32,
+    task: impl ComputeTask + 'static,
+) -> Result<(), String> {
+    world.resource_scope(
+        |world, mut registry: bevy_ecs::world::Mut<ComputeTaskRegistry>| {
+            registry.register(world, order, task)
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gpu_resources::shaders::n_body_sim_compute;
+
+    fn compute_reflection(group: u32, source: &str) -> ReflectedBindGroupLayout {
+        ReflectedBindGroupLayout::from_shaders(group, &[(wgpu::ShaderStages::COMPUTE, source)])
+            .unwrap()
+    }
+
+    #[test]
+    fn storage_buffers_over_the_limit_are_an_error() {
+        let source = "@group(0) @binding(0) var<storage, read> a: array<u32>;\n\
+                      @group(0) @binding(1) var<storage, read_write> b: array<u32>;\n\
+                      @group(0) @binding(2) var<uniform> c: vec4<f32>;\n\
+                      @group(1) @binding(0) var<storage, read_write> d: array<u32>;\n";
+        let group_0 = compute_reflection(0, source);
+        let group_1 = compute_reflection(1, source);
+        let limits = wgpu::Limits {
+            max_storage_buffers_per_shader_stage: 2,
+            ..Default::default()
+        };
+
+        assert!(check_limits("test", &[&group_0], &limits).is_ok());
+        assert_eq!(
+            check_limits("test", &[&group_0, &group_1], &limits).unwrap_err(),
+            "Compute task test binds 3 storage buffers, the limit is 2"
+        );
+    }
+
+    #[test]
+    fn buffers_of_other_stages_are_not_counted() {
+        let group = ReflectedBindGroupLayout::from_shaders(
+            0,
+            &[(
+                wgpu::ShaderStages::VERTEX,
+                "@group(0) @binding(0) var<storage, read> a: array<u32>;\n",
+            )],
+        )
+        .unwrap();
+        let limits = wgpu::Limits {
+            max_storage_buffers_per_shader_stage: 0,
+            ..Default::default()
+        };
+
+        assert!(check_limits("test", &[&group], &limits).is_ok());
+    }
+
+    // the reorder kernels read the particles without the rest of the simulation bind group
+    #[test]
+    fn reorder_kernels_fit_the_default_limits() {
+        use n_body_sim_compute::naga::entry_points;
+
+        let shaders = [
+            (
+                wgpu::ShaderStages::COMPUTE,
+                entry_points::cs_particle_bounds::EXCLUSIVE_SOURCE,
+            ),
+            (
+                wgpu::ShaderStages::COMPUTE,
+                entry_points::cs_morton_codes::EXCLUSIVE_SOURCE,
+            ),
+            (
+                wgpu::ShaderStages::COMPUTE,
+                entry_points::cs_gather_particles::EXCLUSIVE_SOURCE,
+            ),
+        ];
+        let group_0 = ReflectedBindGroupLayout::from_shaders(0, &shaders).unwrap();
+        let group_1 = ReflectedBindGroupLayout::from_shaders(1, &shaders).unwrap();
+
+        check_limits("reorder", &[&group_0, &group_1], &wgpu::Limits::default()).unwrap();
+    }
+}
@@ -1,16 +1,48 @@
 use bevy_ecs::world::World;
 
-pub mod n_body_sim_compute_pipeline;
+use crate::ecs::resources::n_body_kernel_config::NBodyKernelConfig;
+
+pub mod compute_task_registry;
+pub mod morton_reorder_task;
+pub mod n_body_sim_task;
+pub mod particle_instances_task;
 pub mod render_particles_pipeline;
+pub mod render_sprites_pipeline;
 pub mod unlit_diffuse_pipeline;
 
 pub fn initialize_pipelines(world: &mut World) {
     let unlit_diffuse_pipeline = unlit_diffuse_pipeline::UnlitDiffusePipeline::new(world);
-    let n_body_sim_compute_pipeline =
-        n_body_sim_compute_pipeline::NBodySimComputePipeline::new(world);
     let render_particles_pipeline = render_particles_pipeline::RenderParticlesPipeline::new(world);
+    let render_sprites_pipeline = render_sprites_pipeline::RenderSpritesPipeline::new(world);
 
     world.insert_resource(unlit_diffuse_pipeline);
-    world.insert_resource(n_body_sim_compute_pipeline);
     world.insert_resource(render_particles_pipeline);
+    world.insert_resource(render_sprites_pipeline);
+
+    world.init_resource::<NBodyKernelConfig>();
+    world.insert_resource(compute_task_registry::ComputeTaskRegistry::new());
+    compute_task_registry::register_compute_task(
+        world,
+        n_body_sim_task::NBodySimTask::ORDER,
+        n_body_sim_task::NBodySimTask,
+    )
+    .unwrap();
+    compute_task_registry::register_compute_task(
+        world,
+        particle_instances_task::ParticleInstancesTask::ORDER,
+        particle_instances_task::ParticleInstancesTask,
+    )
+    .unwrap();
+    compute_task_registry::register_compute_task(
+        world,
+        morton_reorder_task::MortonCodesTask::ORDER,
+        morton_reorder_task::MortonCodesTask,
+    )
+    .unwrap();
+    compute_task_registry::register_compute_task(
+        world,
+        morton_reorder_task::MortonGatherTask::ORDER,
+        morton_reorder_task::MortonGatherTask,
+    )
+    .unwrap();
 }
@@ -0,0 +1,148 @@
+use bevy_ecs::world::World;
+
+use crate::{
+    ecs::resources::{morton_reorder::MortonReorder, nbody_sim_resources::NBodySimResources},
+    gpu_resources::{
+        layouts::nbody_simparams_uniform_layout::NBodySimParamsUniformLayout,
+        reflection::ReflectedBindGroupLayout, shader_variants::ShaderDefines,
+    },
+    traits::compute_task_traits::{ComputeKernel, ComputeTask},
+};
+
+use super::{super::shaders::N_BODY_SIM_COMPUTE_SOURCE, n_body_sim_task::storage_defines};
+
+const SOURCE_FILE: &str = "n-body-sim-compute.wgsl";
+
+// the workgroup size the reorder kernels are written with
+const REORDER_WORKGROUP_SIZE: u32 = 64;
+
+fn reorder_kernel(entry_point: &'static str) -> ComputeKernel {
+    ComputeKernel {
+        entry_point,
+        source: N_BODY_SIM_COMPUTE_SOURCE,
+        source_file: SOURCE_FILE,
+    }
+}
+
+fn reorder_bind_group_layouts(world: &World) -> Vec<&wgpu::BindGroupLayout> {
+    let layout = world.resource::<NBodySimParamsUniformLayout>();
+    vec![&layout.reorder_sim_layout, &layout.reorder_layout]
+}
+
+fn reorder_bind_group_reflections(world: &World) -> Vec<&ReflectedBindGroupLayout> {
+    let layout = world.resource::<NBodySimParamsUniformLayout>();
+    vec![&layout.reorder_sim_reflection, &layout.reorder_reflection]
+}
+
+fn reorder_bind_groups(world: &World) -> Vec<&wgpu::BindGroup> {
+    vec![
+        world
+            .resource::<NBodySimResources>()
+            .get_reorder_bind_group(),
+        world.resource::<MortonReorder>().get_bind_group(),
+    ]
+}
+
+fn reorder_dispatch_size(world: &World) -> Option<[u32; 3]> {
+    let particle_count = world.resource::<NBodySimResources>().get_particle_count();
+    Some([particle_count.div_ceil(REORDER_WORKGROUP_SIZE), 1, 1])
+}
+
+/// Computes the Morton code of every particle within the bounds of all of them, to be sorted.
+/// Never part of the frame's compute pass, `reorder_particles` dispatches it.
+pub struct MortonCodesTask;
+
+impl MortonCodesTask {
+    pub const LABEL: &'static str = "MortonCodes";
+    pub const ORDER: i32 = 20;
+}
+
+impl ComputeTask for MortonCodesTask {
+    fn label(&self) -> &'static str {
+        Self::LABEL
+    }
+
+    fn kernels(&self) -> Vec<ComputeKernel> {
+        vec![
+            reorder_kernel("cs_particle_bounds"),
+            reorder_kernel("cs_morton_codes"),
+        ]
+    }
+
+    fn shader_defines(&self, world: &World) -> ShaderDefines {
+        storage_defines(world)
+    }
+
+    fn bind_group_layouts<'w>(&self, world: &'w World) -> Vec<&'w wgpu::BindGroupLayout> {
+        reorder_bind_group_layouts(world)
+    }
+
+    fn bind_group_reflections<'w>(&self, world: &'w World) -> Vec<&'w ReflectedBindGroupLayout> {
+        reorder_bind_group_reflections(world)
+    }
+
+    fn bind_groups<'w>(&self, world: &'w World) -> Vec<&'w wgpu::BindGroup> {
+        reorder_bind_groups(world)
+    }
+
+    fn dispatch_size(
+        &self,
+        world: &World,
+        _kernel: usize,
+        _defines: &[(&'static str, String)],
+    ) -> Option<[u32; 3]> {
+        reorder_dispatch_size(world)
+    }
+
+    fn is_enabled(&self, _world: &World) -> bool {
+        false
+    }
+}
+
+/// Moves the particles and their attributes into the sorted order, the particles into the
+/// write buffer. Never part of the frame's compute pass, `reorder_particles` dispatches it.
+pub struct MortonGatherTask;
+
+impl MortonGatherTask {
+    pub const LABEL: &'static str = "MortonGather";
+    pub const ORDER: i32 = 21;
+}
+
+impl ComputeTask for MortonGatherTask {
+    fn label(&self) -> &'static str {
+        Self::LABEL
+    }
+
+    fn kernels(&self) -> Vec<ComputeKernel> {
+        vec![reorder_kernel("cs_gather_particles")]
+    }
+
+    fn shader_defines(&self, world: &World) -> ShaderDefines {
+        storage_defines(world)
+    }
+
+    fn bind_group_layouts<'w>(&self, world: &'w World) -> Vec<&'w wgpu::BindGroupLayout> {
+        reorder_bind_group_layouts(world)
+    }
+
+    fn bind_group_reflections<'w>(&self, world: &'w World) -> Vec<&'w ReflectedBindGroupLayout> {
+        reorder_bind_group_reflections(world)
+    }
+
+    fn bind_groups<'w>(&self, world: &'w World) -> Vec<&'w wgpu::BindGroup> {
+        reorder_bind_groups(world)
+    }
+
+    fn dispatch_size(
+        &self,
+        world: &World,
+        _kernel: usize,
+        _defines: &[(&'static str, String)],
+    ) -> Option<[u32; 3]> {
+        reorder_dispatch_size(world)
+    }
+
+    fn is_enabled(&self, _world: &World) -> bool {
+        false
+    }
+}
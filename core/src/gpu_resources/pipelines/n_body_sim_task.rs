@@ -0,0 +1,127 @@
+use bevy_ecs::world::World;
+
+use crate::{
+    ecs::resources::{
+        n_body_kernel_config::NBodyKernelConfig, nbody_sim_resources::NBodySimResources,
+    },
+    gpu_resources::{
+        layouts::nbody_simparams_uniform_layout::NBodySimParamsUniformLayout,
+        reflection::ReflectedBindGroupLayout,
+        shader_variants::{ShaderDefines, get_define},
+    },
+    traits::compute_task_traits::{ComputeKernel, ComputeTask},
+};
+
+use super::super::shaders::N_BODY_SIM_COMPUTE_SOURCE;
+
+const SOURCE_FILE: &str = "n-body-sim-compute.wgsl";
+
+// the size the shader is written with, used when the defines leave it alone
+const DEFAULT_WORKGROUP_SIZE: u32 = 64;
+
+const CENTER_OF_MASS_KERNEL: usize = 0;
+const STEP_KERNEL: usize = 1;
+
+/// The invocations per workgroup of the step kernel built with `defines`, `None` if they
+/// don't set it
+pub fn workgroup_size_of(defines: &[(&'static str, String)]) -> Option<u32> {
+    get_define(defines, "WORKGROUP_SIZE").and_then(|value| value.parse().ok())
+}
+
+/// The `#define` values selecting the particle layout and instance format the buffers are in
+pub fn storage_defines(world: &World) -> ShaderDefines {
+    // the tasks are registered before the simulation resources, which start with the defaults
+    let (particle_layout, instance_format) =
+        world
+            .get_resource::<NBodySimResources>()
+            .map_or(Default::default(), |resources| {
+                (
+                    resources.get_particle_layout(),
+                    resources.get_instance_format(),
+                )
+            });
+
+    vec![
+        (
+            "PARTICLE_LAYOUT",
+            particle_layout.define_value().to_string(),
+        ),
+        (
+            "INSTANCE_FORMAT",
+            instance_format.define_value().to_string(),
+        ),
+    ]
+}
+
+/// Steps the n-body simulation. Never part of the frame's compute pass, `GpuSimulationBackend`
+/// dispatches it before the frame and the `ParticleInstancesTask` draws the state it leaves.
+pub struct NBodySimTask;
+
+impl NBodySimTask {
+    pub const LABEL: &'static str = "NBodySim";
+    pub const ORDER: i32 = 0;
+}
+
+impl ComputeTask for NBodySimTask {
+    fn label(&self) -> &'static str {
+        Self::LABEL
+    }
+
+    fn kernels(&self) -> Vec<ComputeKernel> {
+        vec![
+            ComputeKernel {
+                entry_point: "cs_center_of_mass",
+                source: N_BODY_SIM_COMPUTE_SOURCE,
+                source_file: SOURCE_FILE,
+            },
+            ComputeKernel {
+                entry_point: "cs_main",
+                source: N_BODY_SIM_COMPUTE_SOURCE,
+                source_file: SOURCE_FILE,
+            },
+        ]
+    }
+
+    /// The variant picked by the `NBodyKernelConfig` for the particle layout in use
+    fn shader_defines(&self, world: &World) -> ShaderDefines {
+        let mut defines = world.resource::<NBodyKernelConfig>().defines();
+        defines.extend(storage_defines(world));
+        defines
+    }
+
+    fn bind_group_layouts<'w>(&self, world: &'w World) -> Vec<&'w wgpu::BindGroupLayout> {
+        vec![&world.resource::<NBodySimParamsUniformLayout>().layout]
+    }
+
+    fn bind_group_reflections<'w>(&self, world: &'w World) -> Vec<&'w ReflectedBindGroupLayout> {
+        vec![&world.resource::<NBodySimParamsUniformLayout>().reflection]
+    }
+
+    fn bind_groups<'w>(&self, world: &'w World) -> Vec<&'w wgpu::BindGroup> {
+        vec![world.resource::<NBodySimResources>().get_bind_group()]
+    }
+
+    fn dispatch_size(
+        &self,
+        world: &World,
+        kernel: usize,
+        defines: &[(&'static str, String)],
+    ) -> Option<[u32; 3]> {
+        let nbody_sim_resources = world.resource::<NBodySimResources>();
+
+        match kernel {
+            // the center of mass is reduced by a single workgroup before the step
+            CENTER_OF_MASS_KERNEL => nbody_sim_resources.get_escape_radius().map(|_| [1, 1, 1]),
+            STEP_KERNEL => {
+                let particle_count = nbody_sim_resources.get_particle_count();
+                let workgroup_size = workgroup_size_of(defines).unwrap_or(DEFAULT_WORKGROUP_SIZE);
+                Some([particle_count.div_ceil(workgroup_size), 1, 1])
+            }
+            _ => None,
+        }
+    }
+
+    fn is_enabled(&self, _world: &World) -> bool {
+        false
+    }
+}
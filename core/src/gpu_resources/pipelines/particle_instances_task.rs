@@ -0,0 +1,110 @@
+use bevy_ecs::world::World;
+
+use crate::{
+    ecs::resources::{
+        active_simulation_backend::ActiveSimulationBackend, nbody_sim_resources::NBodySimResources,
+    },
+    gpu_resources::{
+        layouts::nbody_simparams_uniform_layout::NBodySimParamsUniformLayout,
+        reflection::ReflectedBindGroupLayout,
+        shader_variants::{ShaderDefines, get_define},
+    },
+    traits::compute_task_traits::{ComputeKernel, ComputeTask},
+};
+
+use super::{super::shaders::N_BODY_SIM_COMPUTE_SOURCE, n_body_sim_task::storage_defines};
+
+const SOURCE_FILE: &str = "n-body-sim-compute.wgsl";
+
+// the workgroup size cs_generate_instances is built with, through its define
+const INSTANCE_WORKGROUP_SIZE: u32 = 64;
+
+const GENERATE_KERNEL: usize = 0;
+const CLAMP_KERNEL: usize = 1;
+
+/// Generates the particle instances for rendering from the state after the frame's step.
+/// Runs at most once per frame and only when the state changed, so the simulation can step
+/// any number of times in between without paying for instances nobody draws.
+/// The instance counts are then clamped to the instance capacity for the draws.
+pub struct ParticleInstancesTask;
+
+impl ParticleInstancesTask {
+    pub const LABEL: &'static str = "ParticleInstances";
+    /// After the simulation step, whose output it reads
+    pub const ORDER: i32 = 10;
+}
+
+impl ComputeTask for ParticleInstancesTask {
+    fn label(&self) -> &'static str {
+        Self::LABEL
+    }
+
+    fn kernels(&self) -> Vec<ComputeKernel> {
+        vec![
+            ComputeKernel {
+                entry_point: "cs_generate_instances",
+                source: N_BODY_SIM_COMPUTE_SOURCE,
+                source_file: SOURCE_FILE,
+            },
+            ComputeKernel {
+                entry_point: "cs_clamp_instance_counts",
+                source: N_BODY_SIM_COMPUTE_SOURCE,
+                source_file: SOURCE_FILE,
+            },
+        ]
+    }
+
+    /// The variant reading the particle layout and writing the instance format in use
+    fn shader_defines(&self, world: &World) -> ShaderDefines {
+        let mut defines = storage_defines(world);
+        defines.push((
+            "INSTANCE_WORKGROUP_SIZE",
+            INSTANCE_WORKGROUP_SIZE.to_string(),
+        ));
+        defines
+    }
+
+    fn bind_group_layouts<'w>(&self, world: &'w World) -> Vec<&'w wgpu::BindGroupLayout> {
+        let layout = world.resource::<NBodySimParamsUniformLayout>();
+        vec![&layout.layout, &layout.instance_layout]
+    }
+
+    fn bind_group_reflections<'w>(&self, world: &'w World) -> Vec<&'w ReflectedBindGroupLayout> {
+        let layout = world.resource::<NBodySimParamsUniformLayout>();
+        vec![&layout.reflection, &layout.instance_reflection]
+    }
+
+    fn bind_groups<'w>(&self, world: &'w World) -> Vec<&'w wgpu::BindGroup> {
+        let nbody_sim_resources = world.resource::<NBodySimResources>();
+        vec![
+            nbody_sim_resources.get_bind_group(),
+            nbody_sim_resources.get_instance_bind_group(),
+        ]
+    }
+
+    fn dispatch_size(
+        &self,
+        world: &World,
+        kernel: usize,
+        defines: &[(&'static str, String)],
+    ) -> Option<[u32; 3]> {
+        match kernel {
+            GENERATE_KERNEL => {
+                let workgroup_size = get_define(defines, "INSTANCE_WORKGROUP_SIZE")
+                    .and_then(|value| value.parse().ok())
+                    .unwrap_or(INSTANCE_WORKGROUP_SIZE);
+                let particle_count = world.resource::<NBodySimResources>().get_particle_count();
+                Some([particle_count.div_ceil(workgroup_size), 1, 1])
+            }
+            // a single invocation clamps the counts of every species once they are all appended
+            CLAMP_KERNEL => Some([1, 1, 1]),
+            _ => None,
+        }
+    }
+
+    /// Skipped while the instances are up to date or built on the cpu
+    fn is_enabled(&self, world: &World) -> bool {
+        world.resource::<NBodySimResources>().are_instances_stale()
+            && world.resource::<ActiveSimulationBackend>().is_gpu()
+    }
+}
@@ -1,21 +1,63 @@
 use bevy_ecs::{system::Resource, world::World};
 
 use crate::gpu_resources::layouts::camera_uniform_layout::CameraUniformLayout;
+use crate::gpu_resources::layouts::nbody_simparams_uniform_layout::NBodySimParamsUniformLayout;
 use crate::gpu_resources::layouts::texture_uniform_layout::TextureUniformLayout;
+use crate::gpu_resources::particle_storage::InstanceFormat;
 use crate::gpu_resources::render_resources::RenderResources;
 use crate::gpu_resources::types::basic_vertex::BasicVertex;
+use crate::gpu_resources::types::gpu_packed_particle_instance::GpuPackedParticleInstance;
 use crate::gpu_resources::types::gpu_particle_instance::GpuParticleInstance;
 
 use super::super::shaders::render_particles::SHADER_DESCRIPTOR_FRAGMENT;
 use super::super::shaders::render_particles::SHADER_DESCRIPTOR_VERTEX;
+use super::super::shaders::render_particles::SHADER_DESCRIPTOR_VERTEX_BILLBOARD;
+use super::super::shaders::render_particles::SHADER_DESCRIPTOR_VERTEX_PACKED;
+use super::super::shaders::render_particles::SHADER_DESCRIPTOR_VERTEX_PACKED_BILLBOARD;
 
+/// Draws the particle instances, one pipeline per instance format for meshes and another for
+/// billboards facing the camera
 #[derive(Resource)]
 pub struct RenderParticlesPipeline {
     pub render_pipeline: wgpu::RenderPipeline,
+    pub packed_render_pipeline: wgpu::RenderPipeline,
+    pub billboard_render_pipeline: wgpu::RenderPipeline,
+    pub packed_billboard_render_pipeline: wgpu::RenderPipeline,
 }
 
 impl RenderParticlesPipeline {
     pub fn new(world: &World) -> Self {
+        Self::with_shaders(
+            world,
+            SHADER_DESCRIPTOR_VERTEX,
+            SHADER_DESCRIPTOR_VERTEX_PACKED,
+            SHADER_DESCRIPTOR_VERTEX_BILLBOARD,
+            SHADER_DESCRIPTOR_VERTEX_PACKED_BILLBOARD,
+            SHADER_DESCRIPTOR_FRAGMENT,
+        )
+    }
+
+    /// The pipeline whose vertex layout reads instances in the given format, turning the mesh to
+    /// face the camera for billboards
+    pub fn get_pipeline(&self, format: InstanceFormat, billboard: bool) -> &wgpu::RenderPipeline {
+        match (format, billboard) {
+            (InstanceFormat::Full, false) => &self.render_pipeline,
+            (InstanceFormat::Packed, false) => &self.packed_render_pipeline,
+            (InstanceFormat::Full, true) => &self.billboard_render_pipeline,
+            (InstanceFormat::Packed, true) => &self.packed_billboard_render_pipeline,
+        }
+    }
+
+    /// Builds the pipelines from the given vertex shaders, `vs_main` reading full instances,
+    /// `vs_packed` reading packed ones and their billboard versions, and the fragment shader
+    pub fn with_shaders(
+        world: &World,
+        vertex_shader: wgpu::ShaderModuleDescriptor,
+        packed_vertex_shader: wgpu::ShaderModuleDescriptor,
+        billboard_vertex_shader: wgpu::ShaderModuleDescriptor,
+        packed_billboard_vertex_shader: wgpu::ShaderModuleDescriptor,
+        fragment_shader: wgpu::ShaderModuleDescriptor,
+    ) -> Self {
         let render_resources = world.get_resource::<RenderResources>().unwrap();
         let device = &render_resources.device;
 
@@ -24,20 +66,32 @@ impl RenderParticlesPipeline {
             .unwrap()
             .layout;
         let camera_uniform_layout = &world.get_resource::<CameraUniformLayout>().unwrap().layout;
+        let particle_attribute_layout = &world
+            .get_resource::<NBodySimParamsUniformLayout>()
+            .unwrap()
+            .attribute_layout;
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("unlit_diffuse_pipeline_layout"),
-            bind_group_layouts: &[camera_uniform_layout, texture_uniform_layout],
+            bind_group_layouts: &[
+                camera_uniform_layout,
+                texture_uniform_layout,
+                particle_attribute_layout,
+            ],
             push_constant_ranges: &[],
         });
 
-        let vertex_shader_module = device.create_shader_module(SHADER_DESCRIPTOR_VERTEX);
-        let fragment_shader_module = device.create_shader_module(SHADER_DESCRIPTOR_FRAGMENT);
+        let vertex_shader_module = device.create_shader_module(vertex_shader);
+        let packed_vertex_shader_module = device.create_shader_module(packed_vertex_shader);
+        let billboard_vertex_shader_module = device.create_shader_module(billboard_vertex_shader);
+        let packed_billboard_vertex_shader_module =
+            device.create_shader_module(packed_billboard_vertex_shader);
+        let fragment_shader_module = device.create_shader_module(fragment_shader);
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("unlit_diffuse_pipeline"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
+        let render_pipeline = Self::create_pipeline(
+            render_resources,
+            &pipeline_layout,
+            wgpu::VertexState {
                 module: &vertex_shader_module,
                 entry_point: "vs_main",
                 buffers: &[
@@ -46,40 +100,104 @@ impl RenderParticlesPipeline {
                 ],
                 compilation_options: Default::default(),
             },
-            fragment: Some(wgpu::FragmentState {
-                module: &fragment_shader_module,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: render_resources.surface_format,
-                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
+            &fragment_shader_module,
+        );
+        let packed_render_pipeline = Self::create_pipeline(
+            render_resources,
+            &pipeline_layout,
+            wgpu::VertexState {
+                module: &packed_vertex_shader_module,
+                entry_point: "vs_packed",
+                buffers: &[
+                    BasicVertex::vertex_layout(),
+                    GpuPackedParticleInstance::instance_vertex_layout(),
+                ],
+                compilation_options: Default::default(),
+            },
+            &fragment_shader_module,
+        );
+
+        let billboard_render_pipeline = Self::create_pipeline(
+            render_resources,
+            &pipeline_layout,
+            wgpu::VertexState {
+                module: &billboard_vertex_shader_module,
+                entry_point: "vs_billboard",
+                buffers: &[
+                    BasicVertex::vertex_layout(),
+                    GpuParticleInstance::instance_layout(),
+                ],
                 compilation_options: Default::default(),
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: None,
-                unclipped_depth: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
             },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: wgpu::TextureFormat::Depth32Float,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
+            &fragment_shader_module,
+        );
+        let packed_billboard_render_pipeline = Self::create_pipeline(
+            render_resources,
+            &pipeline_layout,
+            wgpu::VertexState {
+                module: &packed_billboard_vertex_shader_module,
+                entry_point: "vs_packed_billboard",
+                buffers: &[
+                    BasicVertex::vertex_layout(),
+                    GpuPackedParticleInstance::instance_vertex_layout(),
+                ],
+                compilation_options: Default::default(),
             },
-            multiview: None,
-        });
+            &fragment_shader_module,
+        );
+
+        Self {
+            render_pipeline,
+            packed_render_pipeline,
+            billboard_render_pipeline,
+            packed_billboard_render_pipeline,
+        }
+    }
 
-        Self { render_pipeline }
+    fn create_pipeline(
+        render_resources: &RenderResources,
+        pipeline_layout: &wgpu::PipelineLayout,
+        vertex: wgpu::VertexState,
+        fragment_shader_module: &wgpu::ShaderModule,
+    ) -> wgpu::RenderPipeline {
+        render_resources
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("unlit_diffuse_pipeline"),
+                layout: Some(pipeline_layout),
+                vertex,
+                fragment: Some(wgpu::FragmentState {
+                    module: fragment_shader_module,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: render_resources.surface_format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: Default::default(),
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: None,
+                    unclipped_depth: false,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    conservative: false,
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: wgpu::TextureFormat::Depth32Float,
+                    depth_write_enabled: true,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            })
     }
 }
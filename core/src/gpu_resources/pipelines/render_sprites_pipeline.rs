@@ -0,0 +1,167 @@
+use bevy_ecs::{system::Resource, world::World};
+
+use crate::gpu_resources::layouts::camera_uniform_layout::CameraUniformLayout;
+use crate::gpu_resources::layouts::nbody_simparams_uniform_layout::NBodySimParamsUniformLayout;
+use crate::gpu_resources::particle_storage::InstanceFormat;
+use crate::gpu_resources::render_resources::RenderResources;
+use crate::gpu_resources::types::gpu_packed_particle_instance::GpuPackedParticleInstance;
+use crate::gpu_resources::types::gpu_particle_instance::GpuParticleInstance;
+use crate::utils::buffer::{Buffer, BufferBuilder};
+
+use super::super::shaders::render_sprites::SHADER_DESCRIPTOR_FRAGMENT;
+use super::super::shaders::render_sprites::SHADER_DESCRIPTOR_VERTEX;
+use super::super::shaders::render_sprites::SHADER_DESCRIPTOR_VERTEX_PACKED;
+
+/// How the particle renderer draws the instances
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ParticleRenderMode {
+    /// The species' meshes, picked per level of detail, with `RenderParticlesPipeline`
+    #[default]
+    Mesh,
+    /// A camera facing sprite per instance with `RenderSpritesPipeline`, for millions of particles
+    Sprite,
+}
+
+/// The indices of the two triangles of a sprite, the vertex shader places a corner per index
+pub const SPRITE_INDICES: [u32; 6] = [0, 1, 2, 2, 1, 3];
+
+/// Draws the particle instances as soft round sprites facing the camera, a quad per instance
+/// built in the vertex shader without a mesh. One pipeline per instance format.
+#[derive(Resource)]
+pub struct RenderSpritesPipeline {
+    pub render_pipeline: wgpu::RenderPipeline,
+    pub packed_render_pipeline: wgpu::RenderPipeline,
+    pub index_buffer: Buffer<u32>,
+}
+
+impl RenderSpritesPipeline {
+    pub fn new(world: &World) -> Self {
+        Self::with_shaders(
+            world,
+            SHADER_DESCRIPTOR_VERTEX,
+            SHADER_DESCRIPTOR_VERTEX_PACKED,
+            SHADER_DESCRIPTOR_FRAGMENT,
+        )
+    }
+
+    /// The pipeline whose vertex layout reads instances in the given format
+    pub fn get_pipeline(&self, format: InstanceFormat) -> &wgpu::RenderPipeline {
+        match format {
+            InstanceFormat::Full => &self.render_pipeline,
+            InstanceFormat::Packed => &self.packed_render_pipeline,
+        }
+    }
+
+    /// Builds the pipelines from the given vertex shaders, `vs_main` reading full instances and
+    /// `vs_packed` reading packed ones, and the fragment shader
+    pub fn with_shaders(
+        world: &World,
+        vertex_shader: wgpu::ShaderModuleDescriptor,
+        packed_vertex_shader: wgpu::ShaderModuleDescriptor,
+        fragment_shader: wgpu::ShaderModuleDescriptor,
+    ) -> Self {
+        let render_resources = world.get_resource::<RenderResources>().unwrap();
+        let device = &render_resources.device;
+
+        let camera_uniform_layout = &world.get_resource::<CameraUniformLayout>().unwrap().layout;
+        let sprite_layout = &world
+            .get_resource::<NBodySimParamsUniformLayout>()
+            .unwrap()
+            .sprite_layout;
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("render_sprites_pipeline_layout"),
+            bind_group_layouts: &[camera_uniform_layout, sprite_layout],
+            push_constant_ranges: &[],
+        });
+
+        let vertex_shader_module = device.create_shader_module(vertex_shader);
+        let packed_vertex_shader_module = device.create_shader_module(packed_vertex_shader);
+        let fragment_shader_module = device.create_shader_module(fragment_shader);
+
+        let render_pipeline = Self::create_pipeline(
+            render_resources,
+            &pipeline_layout,
+            wgpu::VertexState {
+                module: &vertex_shader_module,
+                entry_point: "vs_main",
+                buffers: &[GpuParticleInstance::instance_layout()],
+                compilation_options: Default::default(),
+            },
+            &fragment_shader_module,
+        );
+        let packed_render_pipeline = Self::create_pipeline(
+            render_resources,
+            &pipeline_layout,
+            wgpu::VertexState {
+                module: &packed_vertex_shader_module,
+                entry_point: "vs_packed",
+                buffers: &[GpuPackedParticleInstance::instance_vertex_layout()],
+                compilation_options: Default::default(),
+            },
+            &fragment_shader_module,
+        );
+
+        let index_buffer = BufferBuilder::<u32>::new(device)
+            .label("Sprite Index Buffer")
+            .usage(wgpu::BufferUsages::INDEX)
+            .contents(&SPRITE_INDICES)
+            .build()
+            .unwrap();
+
+        Self {
+            render_pipeline,
+            packed_render_pipeline,
+            index_buffer,
+        }
+    }
+
+    fn create_pipeline(
+        render_resources: &RenderResources,
+        pipeline_layout: &wgpu::PipelineLayout,
+        vertex: wgpu::VertexState,
+        fragment_shader_module: &wgpu::ShaderModule,
+    ) -> wgpu::RenderPipeline {
+        render_resources
+            .device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("render_sprites_pipeline"),
+                layout: Some(pipeline_layout),
+                vertex,
+                fragment: Some(wgpu::FragmentState {
+                    module: fragment_shader_module,
+                    entry_point: "fs_main",
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: render_resources.surface_format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: Default::default(),
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: None,
+                    unclipped_depth: false,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    conservative: false,
+                },
+                // the soft edges are blended over whatever is behind them, so the sprites test
+                // against the depth buffer without hiding each other
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: wgpu::TextureFormat::Depth32Float,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Less,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+            })
+    }
+}
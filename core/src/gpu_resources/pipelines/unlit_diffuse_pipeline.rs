@@ -16,6 +16,15 @@ pub struct UnlitDiffusePipeline {
 
 impl UnlitDiffusePipeline {
     pub fn new(world: &World) -> Self {
+        Self::with_shaders(world, SHADER_DESCRIPTOR_VERTEX, SHADER_DESCRIPTOR_FRAGMENT)
+    }
+
+    /// Builds the pipeline from the given vertex and fragment shaders
+    pub fn with_shaders(
+        world: &World,
+        vertex_shader: wgpu::ShaderModuleDescriptor,
+        fragment_shader: wgpu::ShaderModuleDescriptor,
+    ) -> Self {
         let render_resources = world.get_resource::<RenderResources>().unwrap();
         let device = &render_resources.device;
 
@@ -36,8 +45,8 @@ impl UnlitDiffusePipeline {
             push_constant_ranges: &[],
         });
 
-        let vertex_shader_module = device.create_shader_module(SHADER_DESCRIPTOR_VERTEX);
-        let fragment_shader_module = device.create_shader_module(SHADER_DESCRIPTOR_FRAGMENT);
+        let vertex_shader_module = device.create_shader_module(vertex_shader);
+        let fragment_shader_module = device.create_shader_module(fragment_shader);
 
         let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: Some("unlit_diffuse_pipeline"),
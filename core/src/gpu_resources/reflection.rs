@@ -0,0 +1,203 @@
+use naga::{
+    AddressSpace, ArraySize, ImageClass, ImageDimension, ScalarKind, StorageAccess, TypeInner,
+};
+
+/// How much memory a buffer binding needs, as declared by the shader
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReflectedSize {
+    /// A binding of a fixed number of bytes
+    Fixed(u32),
+    /// A runtime sized array, the buffer's element size must match the stride
+    RuntimeArray { stride: u32 },
+    /// Textures and samplers
+    Opaque,
+}
+
+/// A resource binding declared by a shader's global variables
+#[derive(Debug, Clone)]
+pub struct ReflectedBinding {
+    pub name: String,
+    pub entry: wgpu::BindGroupLayoutEntry,
+    pub size: ReflectedSize,
+}
+
+/// The bindings of one bind group, merged over every shader stage that uses it
+#[derive(Debug, Clone)]
+pub struct ReflectedBindGroupLayout {
+    pub group: u32,
+    /// Sorted by binding
+    pub bindings: Vec<ReflectedBinding>,
+}
+
+impl ReflectedBindGroupLayout {
+    /// Reflects the bindings of `group` from the given shader sources and the stage each runs in.
+    /// A binding declared differently by two shaders is an error.
+    pub fn from_shaders(
+        group: u32,
+        shaders: &[(wgpu::ShaderStages, &str)],
+    ) -> Result<Self, String> {
+        let mut bindings: Vec<ReflectedBinding> = Vec::new();
+
+        for (stage, source) in shaders {
+            let module = naga::front::wgsl::parse_str(source).map_err(|e| {
+                format!(
+                    "Failed to parse shader for reflection: {}",
+                    e.emit_to_string(source)
+                )
+            })?;
+
+            for (_, variable) in module.global_variables.iter() {
+                let Some(resource_binding) = &variable.binding else {
+                    continue;
+                };
+                if resource_binding.group != group {
+                    continue;
+                }
+
+                let name = variable.name.clone().unwrap_or_default();
+                let (ty, size) = reflect_binding_type(&module, variable.space, variable.ty)
+                    .map_err(|e| {
+                        format!("Binding {} ({}): {}", resource_binding.binding, name, e)
+                    })?;
+
+                match bindings
+                    .iter_mut()
+                    .find(|binding| binding.entry.binding == resource_binding.binding)
+                {
+                    Some(existing) => {
+                        if existing.entry.ty != ty || existing.size != size {
+                            return Err(format!(
+                                "Binding {} of group {} is declared as both {:?} and {:?}",
+                                resource_binding.binding, group, existing.entry.ty, ty
+                            ));
+                        }
+                        existing.entry.visibility |= *stage;
+                    }
+                    None => bindings.push(ReflectedBinding {
+                        name,
+                        entry: wgpu::BindGroupLayoutEntry {
+                            binding: resource_binding.binding,
+                            visibility: *stage,
+                            ty,
+                            count: None,
+                        },
+                        size,
+                    }),
+                }
+            }
+        }
+
+        bindings.sort_by_key(|binding| binding.entry.binding);
+
+        Ok(Self { group, bindings })
+    }
+
+    pub fn entries(&self) -> Vec<wgpu::BindGroupLayoutEntry> {
+        self.bindings.iter().map(|binding| binding.entry).collect()
+    }
+
+    pub fn get_binding(&self, binding: u32) -> Option<&ReflectedBinding> {
+        self.bindings
+            .iter()
+            .find(|reflected| reflected.entry.binding == binding)
+    }
+
+    pub fn create_layout(&self, device: &wgpu::Device, label: &str) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(label),
+            entries: &self.entries(),
+        })
+    }
+}
+
+fn reflect_binding_type(
+    module: &naga::Module,
+    space: AddressSpace,
+    ty: naga::Handle<naga::Type>,
+) -> Result<(wgpu::BindingType, ReflectedSize), String> {
+    let inner = &module.types[ty].inner;
+
+    let buffer_size = || match inner {
+        TypeInner::Array {
+            size: ArraySize::Dynamic,
+            stride,
+            ..
+        } => ReflectedSize::RuntimeArray { stride: *stride },
+        _ => ReflectedSize::Fixed(inner.size(module.to_ctx())),
+    };
+
+    match space {
+        AddressSpace::Uniform => Ok((
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            buffer_size(),
+        )),
+        AddressSpace::Storage { access } => Ok((
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage {
+                    read_only: !access.contains(StorageAccess::STORE),
+                },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            buffer_size(),
+        )),
+        AddressSpace::Handle => match inner {
+            TypeInner::Sampler { comparison } => Ok((
+                wgpu::BindingType::Sampler(if *comparison {
+                    wgpu::SamplerBindingType::Comparison
+                } else {
+                    wgpu::SamplerBindingType::Filtering
+                }),
+                ReflectedSize::Opaque,
+            )),
+            TypeInner::Image {
+                dim,
+                arrayed,
+                class,
+            } => {
+                let view_dimension = match (dim, arrayed) {
+                    (ImageDimension::D1, false) => wgpu::TextureViewDimension::D1,
+                    (ImageDimension::D2, false) => wgpu::TextureViewDimension::D2,
+                    (ImageDimension::D2, true) => wgpu::TextureViewDimension::D2Array,
+                    (ImageDimension::D3, false) => wgpu::TextureViewDimension::D3,
+                    (ImageDimension::Cube, false) => wgpu::TextureViewDimension::Cube,
+                    (ImageDimension::Cube, true) => wgpu::TextureViewDimension::CubeArray,
+                    _ => return Err(format!("Unsupported texture dimension {:?}", dim)),
+                };
+
+                let (sample_type, multisampled) = match class {
+                    ImageClass::Sampled { kind, multi } => {
+                        let sample_type = match kind {
+                            ScalarKind::Float => {
+                                wgpu::TextureSampleType::Float { filterable: true }
+                            }
+                            ScalarKind::Sint => wgpu::TextureSampleType::Sint,
+                            ScalarKind::Uint => wgpu::TextureSampleType::Uint,
+                            _ => return Err(format!("Unsupported texture sample kind {:?}", kind)),
+                        };
+                        (sample_type, *multi)
+                    }
+                    ImageClass::Depth { multi } => (wgpu::TextureSampleType::Depth, *multi),
+                    ImageClass::Storage { .. } => {
+                        return Err("Storage textures are not supported by reflection".to_string());
+                    }
+                };
+
+                Ok((
+                    wgpu::BindingType::Texture {
+                        sample_type,
+                        view_dimension,
+                        multisampled,
+                    },
+                    ReflectedSize::Opaque,
+                ))
+            }
+            other => Err(format!("Unsupported handle type {:?}", other)),
+        },
+        other => Err(format!("Unsupported address space {:?}", other)),
+    }
+}
@@ -0,0 +1,28 @@
+use std::path::{Path, PathBuf};
+
+use super::{shader_preprocessor::preprocess_shader, shader_variants::validate_wgsl};
+
+/// The shader sources the app was built from, watched for changes in development builds
+pub const SHADER_DIRECTORY: &str =
+    concat!(env!("CARGO_MANIFEST_DIR"), "/src/gpu_resources/shaders");
+
+pub fn shader_path(file: &str) -> PathBuf {
+    Path::new(SHADER_DIRECTORY).join(file)
+}
+
+/// Preprocesses a shader from the shader directory and validates it with naga, so a broken
+/// shader is reported with the line it fails on.
+/// Returns `None` if the shader doesn't import any of the `changed` files.
+pub fn compile_shader(file: &str, changed: &[PathBuf]) -> Option<Result<String, String>> {
+    let shader = match preprocess_shader(&shader_path(file)) {
+        Ok(shader) => shader,
+        // the imports aren't known, so the shader may well depend on what changed
+        Err(error) => return Some(Err(error)),
+    };
+
+    if !shader.files.iter().any(|path| changed.contains(path)) {
+        return None;
+    }
+
+    Some(validate_wgsl(file, &shader.source).map(|_| shader.source))
+}
@@ -0,0 +1,458 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+/// A shader with its imports inlined, ready to be compiled at runtime
+#[cfg(feature = "debug-shader-hot-reload")]
+#[derive(Debug, Clone)]
+pub struct PreprocessedShader {
+    pub source: String,
+    /// The shader file and every file it imports, directly or not
+    pub files: Vec<PathBuf>,
+}
+
+/// Runs the same `#define` / `#import` preprocessing the shaders get at compile time,
+/// on the files on disk.
+///
+/// - `#define NAME value` replaces `#NAME` in the rest of the file and in the files it imports
+/// - `#import path [as alias]` imports a file relative to the importing one, its items are
+///   used as `alias::item`, the alias defaults to the file name without extension
+/// - `@export` is dropped
+///
+/// Imported items are inlined with a prefix per import, a file imported twice with the same
+/// defines is only inlined once.
+#[cfg(feature = "debug-shader-hot-reload")]
+pub fn preprocess_shader(path: &Path) -> Result<PreprocessedShader, String> {
+    let mut preprocessor = Preprocessor::default();
+    let body = preprocessor.process_file(path, &BTreeMap::new(), None)?;
+    preprocessor.output.push_str(&body);
+
+    Ok(PreprocessedShader {
+        source: preprocessor.output,
+        files: preprocessor.files,
+    })
+}
+
+/// Preprocesses a shader that is already in memory, with `overrides` taking the place of the
+/// values its `#define`s give. The source can't `#import` since there is no file to resolve from.
+pub fn preprocess_source(
+    label: &str,
+    source: &str,
+    overrides: &[(&str, String)],
+) -> Result<String, String> {
+    let mut preprocessor = Preprocessor {
+        overrides: overrides
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect(),
+        ..Default::default()
+    };
+    let defines = preprocessor.overrides.clone();
+
+    preprocessor.process_source(label, source, None, &defines, None)
+}
+
+#[derive(Default)]
+struct Preprocessor {
+    /// Inlined imports, dependencies before the files that import them
+    output: String,
+    files: Vec<PathBuf>,
+    /// Prefix of every file inlined so far, keyed by path and the defines it saw
+    inlined: HashMap<(PathBuf, String), String>,
+    /// Values that win over the ones given by `#define`
+    overrides: BTreeMap<String, String>,
+}
+
+impl Preprocessor {
+    /// Preprocesses one file, inlining its imports into the output and returning its own body.
+    /// Items declared by the file are renamed with `prefix` when it is imported.
+    fn process_file(
+        &mut self,
+        path: &Path,
+        inherited_defines: &BTreeMap<String, String>,
+        prefix: Option<&str>,
+    ) -> Result<String, String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        if !self.files.iter().any(|file| file == path) {
+            self.files.push(path.to_path_buf());
+        }
+
+        let label = path.display().to_string();
+        let directory = path.parent().unwrap_or(Path::new(""));
+        self.process_source(&label, &source, Some(directory), inherited_defines, prefix)
+    }
+
+    /// Preprocesses the source of one file, `directory` is where its imports are resolved from
+    fn process_source(
+        &mut self,
+        label: &str,
+        source: &str,
+        directory: Option<&Path>,
+        inherited_defines: &BTreeMap<String, String>,
+        prefix: Option<&str>,
+    ) -> Result<String, String> {
+        let mut defines = inherited_defines.clone();
+        let mut namespaces: HashMap<String, String> = HashMap::new();
+        let mut body = String::new();
+
+        for (line_index, line) in source.lines().enumerate() {
+            let location = || format!("{}:{}", label, line_index + 1);
+            let trimmed = line.trim();
+
+            if let Some(define) = trimmed.strip_prefix("#define ") {
+                let mut parts = define.trim().splitn(2, char::is_whitespace);
+                let name = parts.next().unwrap_or_default().to_string();
+                let value = parts.next().unwrap_or_default().trim().to_string();
+                if name.is_empty() {
+                    return Err(format!("{}: #define without a name", location()));
+                }
+                let value = self.overrides.get(&name).cloned().unwrap_or(value);
+                defines.insert(name, value);
+            } else if let Some(import) = trimmed.strip_prefix("#import ") {
+                let mut parts = import.split_whitespace();
+                let relative_path = parts.next().unwrap_or_default();
+                let alias = match (parts.next(), parts.next()) {
+                    (None, _) => None,
+                    (Some("as"), Some(alias)) => Some(alias),
+                    _ => return Err(format!("{}: expected #import path [as alias]", location())),
+                };
+
+                let Some(directory) = directory else {
+                    return Err(format!(
+                        "{}: #import needs a shader loaded from a file",
+                        location()
+                    ));
+                };
+                let import_path = directory.join(relative_path);
+                let file_stem = import_path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .ok_or(format!(
+                        "{}: invalid import path {}",
+                        location(),
+                        relative_path
+                    ))?
+                    .to_string();
+                let namespace = alias.map(str::to_string).unwrap_or(file_stem.clone());
+
+                let import_prefix = self.import(&import_path, &file_stem, &defines)?;
+                namespaces.insert(namespace, import_prefix);
+            } else {
+                body.push_str(
+                    &substitute_defines(line, &defines)
+                        .map_err(|e| format!("{}: {}", location(), e))?,
+                );
+                body.push('\n');
+            }
+        }
+
+        rename_identifiers(&body, &namespaces, prefix).map_err(|e| format!("{}: {}", label, e))
+    }
+
+    /// Inlines an imported file unless it already was with the same defines, returns its prefix
+    fn import(
+        &mut self,
+        path: &Path,
+        file_stem: &str,
+        defines: &BTreeMap<String, String>,
+    ) -> Result<String, String> {
+        let key = (path.to_path_buf(), format!("{:?}", defines));
+        if let Some(prefix) = self.inlined.get(&key) {
+            return Ok(prefix.clone());
+        }
+
+        let prefix = format!(
+            "{}_{}_",
+            file_stem.replace(['-', '.'], "_"),
+            self.inlined.len()
+        );
+        // claim the prefix first so an import cycle doesn't recurse forever
+        self.inlined.insert(key, prefix.clone());
+
+        let body = self.process_file(path, defines, Some(&prefix))?;
+        self.output.push_str(&body);
+        self.output.push('\n');
+        Ok(prefix)
+    }
+}
+
+/// Replaces every `#NAME` outside of a line comment with the value of the define
+fn substitute_defines(line: &str, defines: &BTreeMap<String, String>) -> Result<String, String> {
+    let (code, comment) = line.split_at(line.find("//").unwrap_or(line.len()));
+    let mut result = String::with_capacity(line.len());
+    let mut rest = code;
+
+    while let Some(index) = rest.find('#') {
+        result.push_str(&rest[..index]);
+        let after = &rest[index + 1..];
+        let name_length = identifier_length(after);
+        if name_length == 0 {
+            result.push('#');
+            rest = after;
+            continue;
+        }
+
+        let name = &after[..name_length];
+        let value = defines
+            .get(name)
+            .ok_or(format!("#{} is not defined", name))?;
+        result.push_str(value);
+        rest = &after[name_length..];
+    }
+
+    result.push_str(rest);
+    result.push_str(comment);
+    Ok(result)
+}
+
+fn identifier_length(text: &str) -> usize {
+    text.char_indices()
+        .find(|&(index, c)| {
+            !(c == '_' || c.is_ascii_alphabetic() || (index > 0 && c.is_ascii_digit()))
+        })
+        .map(|(index, _)| index)
+        .unwrap_or(text.len())
+}
+
+/// Resolves `namespace::item` to the prefixed item and, for imported files, prefixes the
+/// items the file declares at the top level. Comments are copied as they are.
+fn rename_identifiers(
+    body: &str,
+    namespaces: &HashMap<String, String>,
+    prefix: Option<&str>,
+) -> Result<String, String> {
+    let declared = match prefix {
+        Some(_) => top_level_declarations(body),
+        None => Vec::new(),
+    };
+
+    let mut result = String::with_capacity(body.len());
+    let mut rest = body;
+    let mut previous = ' ';
+
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with("//") {
+            let end = rest.find('\n').unwrap_or(rest.len());
+            result.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+        if rest.starts_with("/*") {
+            let end = rest.find("*/").map(|end| end + 2).unwrap_or(rest.len());
+            result.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+        if rest.starts_with("@export") && identifier_length(&rest[1..]) == "export".len() {
+            rest = &rest["@export".len()..];
+            continue;
+        }
+
+        let length = identifier_length(rest);
+        if length == 0 {
+            result.push(c);
+            previous = c;
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+
+        let identifier = &rest[..length];
+        rest = &rest[length..];
+
+        if let Some(after) = rest.strip_prefix("::") {
+            let item_length = identifier_length(after);
+            if item_length > 0 {
+                let namespace_prefix = namespaces
+                    .get(identifier)
+                    .ok_or(format!("{} is not an imported namespace", identifier))?;
+                result.push_str(namespace_prefix);
+                result.push_str(&after[..item_length]);
+                rest = &after[item_length..];
+                previous = 'a';
+                continue;
+            }
+        }
+
+        // member accesses and number suffixes are never renamed
+        match prefix {
+            Some(prefix)
+                if previous != '.'
+                    && !previous.is_ascii_digit()
+                    && declared.iter().any(|name| name == identifier) =>
+            {
+                result.push_str(prefix);
+                result.push_str(identifier);
+            }
+            _ => result.push_str(identifier),
+        }
+        previous = 'a';
+    }
+
+    Ok(result)
+}
+
+/// The names of the functions, structs, globals, constants and aliases declared at the top level
+fn top_level_declarations(body: &str) -> Vec<String> {
+    let mut declarations = Vec::new();
+    let mut depth = 0i32;
+    let mut expect_name = false;
+    let mut rest = body;
+
+    while let Some(c) = rest.chars().next() {
+        if rest.starts_with("//") {
+            rest = &rest[rest.find('\n').unwrap_or(rest.len())..];
+            continue;
+        }
+        if rest.starts_with("/*") {
+            rest = &rest[rest.find("*/").map(|end| end + 2).unwrap_or(rest.len())..];
+            continue;
+        }
+
+        let length = identifier_length(rest);
+        if length == 0 {
+            match c {
+                '{' | '(' => depth += 1,
+                '}' | ')' => depth -= 1,
+                // skip the address space of `var<uniform>`
+                '<' if expect_name => {
+                    let end = rest.find('>').unwrap_or(rest.len() - 1);
+                    rest = &rest[end..];
+                }
+                _ => {}
+            }
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+
+        let identifier = &rest[..length];
+        rest = &rest[length..];
+        if depth != 0 {
+            continue;
+        }
+
+        if expect_name {
+            declarations.push(identifier.to_string());
+            expect_name = false;
+        } else {
+            expect_name = matches!(
+                identifier,
+                "fn" | "struct" | "var" | "const" | "override" | "alias"
+            );
+        }
+    }
+
+    declarations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defines_are_substituted_and_overridden() {
+        let source =
+            "#define SIZE 64\n#define MODE 0\nconst SIZE: u32 = #SIZE;\nconst MODE: u32 = #MODE;\n";
+        let output = preprocess_source("test", source, &[("MODE", "2".to_string())]).unwrap();
+
+        assert!(output.contains("const SIZE: u32 = 64;"), "{output}");
+        assert!(output.contains("const MODE: u32 = 2;"), "{output}");
+        assert!(!output.contains("#define"), "{output}");
+    }
+
+    #[test]
+    fn undefined_names_are_an_error_with_the_line() {
+        let error = preprocess_source("test.wgsl", "\nconst A = #MISSING;\n", &[]).unwrap_err();
+        assert_eq!(error, "test.wgsl:2: #MISSING is not defined");
+    }
+
+    #[test]
+    fn comments_are_left_alone() {
+        let source = "const A = 1; // see #NOT_A_DEFINE\n/* namespace::item */\n";
+        let output = preprocess_source("test", source, &[]).unwrap();
+        assert_eq!(output, source);
+    }
+
+    #[test]
+    fn export_is_dropped() {
+        let output = preprocess_source("test", "@export struct A { a: u32 }\n", &[]).unwrap();
+        assert_eq!(output, " struct A { a: u32 }\n");
+    }
+
+    #[test]
+    fn imports_need_a_file() {
+        let error = preprocess_source("test", "#import lib.wgsl\n", &[]).unwrap_err();
+        assert!(
+            error.contains("#import needs a shader loaded from a file"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn only_top_level_declarations_are_collected() {
+        let body = "struct S { field: u32 }\nvar<uniform> camera: S;\nfn f(x: f32) -> f32 { let y = x; return y; }\nconst C = 1u;\n";
+        assert_eq!(top_level_declarations(body), ["S", "camera", "f", "C"]);
+    }
+
+    #[test]
+    fn imported_items_are_prefixed_but_members_are_not() {
+        let body = "struct S { value: f32 }\nfn f(s: S) -> f32 { return s.value * 2.0f; }\n";
+        let output = rename_identifiers(body, &HashMap::new(), Some("lib_0_")).unwrap();
+        assert_eq!(
+            output,
+            "struct lib_0_S { value: f32 }\nfn lib_0_f(s: lib_0_S) -> f32 { return s.value * 2.0f; }\n"
+        );
+    }
+
+    #[test]
+    fn namespaced_items_resolve_to_their_prefix() {
+        let namespaces = HashMap::from([("lib".to_string(), "lib_0_".to_string())]);
+        let output = rename_identifiers("let x = lib::f(1.0);", &namespaces, None).unwrap();
+        assert_eq!(output, "let x = lib_0_f(1.0);");
+
+        let error = rename_identifiers("other::f()", &namespaces, None).unwrap_err();
+        assert_eq!(error, "other is not an imported namespace");
+    }
+
+    #[cfg(feature = "debug-shader-hot-reload")]
+    #[test]
+    fn files_are_imported_once_with_the_importers_defines() {
+        let directory =
+            std::env::temp_dir().join(format!("shader_preprocessor_test_{}", std::process::id()));
+        std::fs::create_dir_all(directory.join("include")).unwrap();
+        std::fs::write(
+            directory.join("include/lib.wgsl"),
+            "fn scale(v: f32) -> f32 { return v * #SCALE; }\n",
+        )
+        .unwrap();
+        std::fs::write(
+            directory.join("main.wgsl"),
+            "#define SCALE 2.0\n#import include/lib.wgsl\n#import include/lib.wgsl as again\nfn main() -> f32 { return lib::scale(1.0) + again::scale(2.0); }\n",
+        )
+        .unwrap();
+
+        let shader = preprocess_shader(&directory.join("main.wgsl")).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(
+            shader.source.matches("fn lib_0_scale").count(),
+            1,
+            "{}",
+            shader.source
+        );
+        assert!(
+            shader.source.contains("return v * 2.0;"),
+            "{}",
+            shader.source
+        );
+        assert!(
+            shader
+                .source
+                .contains("return lib_0_scale(1.0) + lib_0_scale(2.0);"),
+            "{}",
+            shader.source
+        );
+        assert_eq!(shader.files.len(), 2);
+    }
+}
@@ -0,0 +1,59 @@
+use super::shader_preprocessor::preprocess_source;
+
+/// Values for the `#define` switches of a shader, picking one variant of it.
+/// Defines left out keep the value the shader gives them.
+pub type ShaderDefines = Vec<(&'static str, String)>;
+
+/// The value the defines give `name`, `None` if they leave it to the shader
+pub fn get_define<'a>(defines: &'a [(&'static str, String)], name: &str) -> Option<&'a str> {
+    defines
+        .iter()
+        .find(|(define, _)| *define == name)
+        .map(|(_, value)| value.as_str())
+}
+
+/// Preprocesses `source` with the given defines and builds the shader module for it.
+/// The variant is validated with naga first, so a broken combination of switches is
+/// reported with the line it fails on instead of taking the app down.
+pub fn create_shader_variant(
+    device: &wgpu::Device,
+    label: &str,
+    source: &str,
+    defines: &[(&'static str, String)],
+) -> Result<wgpu::ShaderModule, String> {
+    let source = preprocess_source(label, source, defines)?;
+    validate_wgsl(label, &source)?;
+
+    create_checked(device, || {
+        device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(label),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        })
+    })
+}
+
+/// Parses and validates preprocessed WGSL, errors point at the line in `source`
+pub fn validate_wgsl(label: &str, source: &str) -> Result<(), String> {
+    let module = naga::front::wgsl::parse_str(source)
+        .map_err(|e| e.emit_to_string_with_path(source, label))?;
+    naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    )
+    .validate(&module)
+    .map_err(|e| e.emit_to_string_with_path(source, label))?;
+
+    Ok(())
+}
+
+/// Runs `create` with wgpu validation errors captured instead of raised, so a pipeline
+/// that doesn't match its layout fails here rather than taking the app down
+pub fn create_checked<T>(device: &wgpu::Device, create: impl FnOnce() -> T) -> Result<T, String> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let value = create();
+
+    match futures::executor::block_on(device.pop_error_scope()) {
+        Some(error) => Err(error.to_string()),
+        None => Ok(value),
+    }
+}
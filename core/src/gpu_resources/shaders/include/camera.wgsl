@@ -7,3 +7,18 @@ var<uniform> camera: camera_h::CameraUniform;
 fn to_clip(pos: vec3<f32>) -> vec4<f32> {
     return camera.view_proj * vec4<f32>(pos, 1.0);
 }
+
+// The camera's right axis in world space, the first row of the view matrix
+fn right() -> vec3<f32> {
+    return vec3<f32>(camera.view[0].x, camera.view[1].x, camera.view[2].x);
+}
+
+// The camera's up axis in world space, the second row of the view matrix
+fn up() -> vec3<f32> {
+    return vec3<f32>(camera.view[0].y, camera.view[1].y, camera.view[2].y);
+}
+
+// The clip space extent of one unit at a w of 1 along x and y, the projection's diagonal
+fn proj_scale() -> vec2<f32> {
+    return vec2<f32>(camera.proj[0].x, camera.proj[1].y);
+}
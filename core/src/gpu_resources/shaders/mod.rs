@@ -1,6 +1,4 @@
-use crate::{
-    include_wgsl_shader, include_wgsl_shader_compute, include_wgsl_shader_vertex_fragment,
-};
+use crate::{include_wgsl_shader, include_wgsl_shader_vertex_fragment};
 mod shader_macros;
 
 include_wgsl_shader!(r#"include/basic_vertex.wgsl"#, basic_vertex);
@@ -8,11 +6,38 @@ include_wgsl_shader!(r#"include/camera_h.wgsl"#, gpu_camera);
 include_wgsl_shader!(r#"include/model_h.wgsl"#, gpu_model);
 
 include_wgsl_shader_vertex_fragment!(r#"unlit_diffuse.wgsl"#, unlit_diffuse);
-include_wgsl_shader_vertex_fragment!(r#"render_particles.wgsl"#, render_particles);
+include_wgsl_shader!(
+    r#"render_particles.wgsl"#,
+    render_particles,
+    vs_main as SHADER_DESCRIPTOR_VERTEX,
+    vs_packed as SHADER_DESCRIPTOR_VERTEX_PACKED,
+    vs_billboard as SHADER_DESCRIPTOR_VERTEX_BILLBOARD,
+    vs_packed_billboard as SHADER_DESCRIPTOR_VERTEX_PACKED_BILLBOARD,
+    fs_main as SHADER_DESCRIPTOR_FRAGMENT
+);
+include_wgsl_shader!(
+    r#"render_sprites.wgsl"#,
+    render_sprites,
+    vs_main as SHADER_DESCRIPTOR_VERTEX,
+    vs_packed as SHADER_DESCRIPTOR_VERTEX_PACKED,
+    fs_main as SHADER_DESCRIPTOR_FRAGMENT
+);
+
+include_wgsl_shader!(
+    r#"n-body-sim-compute.wgsl"#,
+    n_body_sim_compute,
+    cs_main as SHADER_DESCRIPTOR_COMPUTE,
+    cs_center_of_mass as SHADER_DESCRIPTOR_CENTER_OF_MASS,
+    cs_generate_instances as SHADER_DESCRIPTOR_GENERATE_INSTANCES
+);
 
-include_wgsl_shader_compute!(r#"n-body-sim-compute.wgsl"#, n_body_sim_compute);
+/// The n-body kernels before preprocessing, variants are built from it at runtime
+pub const N_BODY_SIM_COMPUTE_SOURCE: &str = include_str!("n-body-sim-compute.wgsl");
 
-include_wgsl_shader_compute!(
-    r#"n-body-sim-compute-workgroup.wgsl"#,
-    n_body_sim_compute_workgroup
+include_wgsl_shader!(
+    r#"radix_sort.wgsl"#,
+    radix_sort,
+    cs_radix_count as SHADER_DESCRIPTOR_COUNT,
+    cs_radix_scan as SHADER_DESCRIPTOR_SCAN,
+    cs_radix_scatter as SHADER_DESCRIPTOR_SCATTER
 );
@@ -1,14 +1,39 @@
-// Input binding for the particle data
+// Variant switches, the values here build the default variant and are overridden at runtime
+// by the NBodyKernelConfig, see the constants below for the values each one takes
+#define INTEGRATOR 1
+#define SOFTENING_KERNEL 0
+#define FORCE_LAW 0
+#define TILED 1
+#define WORKGROUP_SIZE 64
+#define INSTANCE_WORKGROUP_SIZE 64
+#define PARTICLE_LAYOUT 0
+#define INSTANCE_FORMAT 0
+
+// A particle as the cpu sees it, the buffers store it as two words laid out by PARTICLE_LAYOUT
 @export struct Particle {
     position: vec4<f32>,  // xyz = position, w = mass
-    velocity: vec4<f32>,  // xyz = velocity, w = unused
+    velocity: vec3<f32>,  // xyz = velocity
+    id: u32,              // Persistent id, stable across buffer swaps and compaction
 }
 
 // Instance data for rendering
-struct Instance {
+@export struct Instance {
     position: vec4<f32>,  // xyz = position, w = size/scale
     color: vec4<f32>,     // rgba color
-    velocity: vec4<f32>,  // For visual effects like trails/rotation
+    velocity: vec3<f32>,  // For visual effects like trails/rotation
+    id: u32,              // The id of the particle this instance was made from
+    index: u32,           // The slot of the particle, for looking up its attributes
+    _0: u32,              // Padding
+    _1: u32,              // Padding
+    _2: u32,              // Padding
+}
+
+// The packed instance format, the vertex fetch widens the halves and bytes back to floats
+@export struct PackedInstance {
+    position_xy: u32,      // pack2x16float of the position's x and y
+    position_z_size: u32,  // pack2x16float of the position's z and the size
+    color: u32,            // pack4x8unorm of the rgba color
+    index: u32,            // The slot of the particle, for looking up its attributes
 }
 
 // Parameters for the simulation
@@ -17,12 +42,42 @@ struct Instance {
     num_particles: u32,
     gravitational_constant: f32,
     softening: f32,       // To avoid numerical instability when particles get too close
-    min_distance: f32,     // Threshold for instance inclusion
-    max_distance: f32,     // Upper bound for instance inclusion
-    _0: u32,               // Padding
-    _1: u32,               // Padding
+    min_distance: f32,    // Threshold for instance inclusion, from the origin when range culling is on
+    max_distance: f32,    // Upper bound for instance inclusion
+    escape_radius: f32,   // Unbound particles beyond this distance from the center of mass escape
+    escape_enabled: u32,  // Non-zero to enable escape detection
+    attribute_stride: u32,  // Words per particle in particle_attributes
+    species_offset: u32,    // Word offset of the species attribute, NO_ATTRIBUTE if there is none
+    species_count: u32,     // Number of species in use
+    instance_capacity: u32, // Instances each instance list can hold
+}
+
+// Per-species simulation and rendering settings
+@export struct Species {
+    color: vec4<f32>,     // rgba color for COLOR_RULE_SOLID
+    color_rule: u32,      // How instances of this species are colored
+    size_base: f32,       // Instance size = size_base + mass * size_scale
+    size_scale: f32,
+    softening: f32,       // Overrides params.softening for this species when not negative
+}
+
+// The system wide state needed for escape detection, computed once per step
+@export struct SystemState {
+    center_of_mass: vec4<f32>,           // xyz = position, w = total mass
+    center_of_mass_velocity: vec4<f32>,  // xyz = velocity, w = unused
+    escape_count: atomic<u32>,           // for atomic append into escapes
+    _0: u32,                             // Padding
+    _1: u32,                             // Padding
+    _2: u32,                             // Padding
+}
+
+// A particle that was found to be unbound beyond the escape radius
+@export struct EscapeRecord {
+    velocity: vec3<f32>,  // xyz = velocity
+    id: u32,              // The id of the escaped particle
 }
 
+// One set of draw arguments per species
 @export struct IndirectArgs {
     index_count: u32,
     instance_count: atomic<u32>, // for atomic append
@@ -31,100 +86,623 @@ struct Instance {
     first_instance: u32,
 }
 
-// Input and output bindings
-@group(0) @binding(0) var<storage, read> particles: array<Particle>;
-@group(0) @binding(1) var<storage, read_write> new_particles: array<Particle>;
+// What became of the particles when the instances were generated, summed over the species
+@export struct CullingCounts {
+    drawn: u32,                    // Instances the draws read, after clamping
+    culled_by_range: atomic<u32>,  // Outside [min_distance, max_distance]
+    dropped: u32,                  // Not culled but beyond their list's instance capacity
+    culled_by_frustum: atomic<u32>,  // In range but outside the camera's view frustum
+}
+
+// How the instances are culled and which level of detail they are drawn at,
+// updated whenever the camera moves
+@export struct CullParams {
+    view_proj: mat4x4<f32>,  // The view-projection of the camera the instances are drawn with
+    bounding_radius: f32,    // Radius of the particle meshes at an instance size of 1
+    frustum_enabled: u32,    // Non-zero to cull the instances outside the view frustum
+    range_enabled: u32,      // Non-zero to cull the particles outside [min_distance, max_distance]
+    lod_count: u32,          // Levels of detail of every species, between 1 and MAX_LODS
+    lod_screen_sizes: vec4<f32>,  // Smallest diameter in pixels drawn at each level but the last
+    screen_scale: f32,       // Pixels per unit at a clip space w of 1, half the viewport height times proj[1][1]
+    _0: u32,                 // Padding
+    _1: u32,                 // Padding
+    _2: u32,                 // Padding
+}
+
+const MAX_SPECIES = 8u;
+const MAX_LODS = 4u;
+const NO_ATTRIBUTE = 0xffffffffu;
+
+const COLOR_RULE_VELOCITY = 0u;
+const COLOR_RULE_SOLID = 1u;
+
+// Input and output bindings, two words per particle, see load_particle
+@group(0) @binding(0) var<storage, read> particles: array<vec4<u32>>;
+@group(0) @binding(1) var<storage, read_write> new_particles: array<vec4<u32>>;
 @group(0) @binding(2) var<uniform> params: SimParams;
-@group(0) @binding(3) var<storage, read_write> instance_buffer: array<Instance>;
-@group(0) @binding(4) var<storage, read_write> indirect_buffer: IndirectArgs;
+@group(0) @binding(3) var<storage, read_write> system_state: SystemState;
+@group(0) @binding(4) var<storage, read_write> escapes: array<EscapeRecord>;
+// Extra per-particle attributes declared by the ParticleAttributeSchema, interleaved per slot
+@group(0) @binding(5) var<storage, read_write> particle_attributes: array<u32>;
+@group(0) @binding(6) var<uniform> species: array<Species, MAX_SPECIES>;
 
-// @compute @workgroup_size(64)
-@compute @workgroup_size(8,8,1)
-fn cs_main(@builtin(global_invocation_id) global_id: vec3<u32>) {
-    let index = global_id.x;
+// The instance set the instances are generated into, double buffered so the set generated
+// the frame before can be drawn meanwhile. Words per instance depend on INSTANCE_FORMAT.
+// There is one instance list and one set of draw arguments per species and level of detail,
+// see instance_list.
+@group(1) @binding(0) var<storage, read_write> instance_buffer: array<vec4<u32>>;
+@group(1) @binding(1) var<storage, read_write> indirect_buffer: array<IndirectArgs>;
+// Cleared along with the instance counts, read back to the cpu once the instances are generated
+@group(1) @binding(2) var<storage, read_write> culling_counts: CullingCounts;
+// Shared by the instance sets
+@group(1) @binding(3) var<uniform> cull_params: CullParams;
 
-    // Guard against out-of-bounds work
-    if (index >= params.num_particles) {
+const PARTICLE_LAYOUT_AOS = 0u;  // the position word and velocity word of a particle are adjacent
+const PARTICLE_LAYOUT_SOA = 1u;  // every position word, then every velocity word from the capacity on
+
+const INSTANCE_FORMAT_FULL = 0u;    // Instance, four words
+const INSTANCE_FORMAT_PACKED = 1u;  // PackedInstance, one word
+
+const PARTICLE_LAYOUT: u32 = #PARTICLE_LAYOUT;
+const INSTANCE_FORMAT: u32 = #INSTANCE_FORMAT;
+
+// Particles the buffers have room for, the bind groups always bind the whole buffers
+fn particle_capacity() -> u32 {
+    return arrayLength(&particles) / 2u;
+}
+
+// The word holding the position (xyz) and mass (w) of the particle in the given slot
+fn position_word(index: u32) -> u32 {
+    if (PARTICLE_LAYOUT == PARTICLE_LAYOUT_SOA) {
+        return index;
+    }
+    return index * 2u;
+}
+
+// The word holding the velocity (xyz) and id (w) of the particle in the given slot
+fn velocity_word(index: u32) -> u32 {
+    if (PARTICLE_LAYOUT == PARTICLE_LAYOUT_SOA) {
+        return particle_capacity() + index;
+    }
+    return index * 2u + 1u;
+}
+
+fn load_position(index: u32) -> vec4<f32> {
+    return bitcast<vec4<f32>>(particles[position_word(index)]);
+}
+
+fn load_particle(index: u32) -> Particle {
+    let velocity = particles[velocity_word(index)];
+
+    var particle: Particle;
+    particle.position = load_position(index);
+    particle.velocity = bitcast<vec3<f32>>(velocity.xyz);
+    particle.id = velocity.w;
+    return particle;
+}
+
+fn store_particle(index: u32, particle: Particle) {
+    new_particles[position_word(index)] = bitcast<vec4<u32>>(particle.position);
+    new_particles[velocity_word(index)] = vec4<u32>(bitcast<vec3<u32>>(particle.velocity), particle.id);
+}
+
+// Writes the instance to the given slot of the instance buffer in the INSTANCE_FORMAT
+fn store_instance(slot: u32, instance: Instance) {
+    if (INSTANCE_FORMAT == INSTANCE_FORMAT_PACKED) {
+        instance_buffer[slot] = vec4<u32>(
+            pack2x16float(instance.position.xy),
+            pack2x16float(instance.position.zw),
+            pack4x8unorm(instance.color),
+            instance.index,
+        );
         return;
     }
 
-    // Load the current particle
-    let current_particle = particles[index];
-    var new_particle = current_particle;
+    let word = slot * 4u;
+    instance_buffer[word] = bitcast<vec4<u32>>(instance.position);
+    instance_buffer[word + 1u] = bitcast<vec4<u32>>(instance.color);
+    instance_buffer[word + 2u] = vec4<u32>(bitcast<vec3<u32>>(instance.velocity), instance.id);
+    instance_buffer[word + 3u] = vec4<u32>(instance.index, 0u, 0u, 0u);
+}
 
-    // Apply N-body gravitational force
-    var total_force = vec3<f32>(0.0, 0.0, 0.0);
+// The species of the particle in the given slot, species 0 when there is no species attribute
+fn species_of(index: u32) -> u32 {
+    if (params.species_offset == NO_ATTRIBUTE) {
+        return 0u;
+    }
+
+    let species_index = particle_attributes[index * params.attribute_stride + params.species_offset];
+    return min(species_index, params.species_count - 1u);
+}
+
+// Size of the single workgroup that reduces the center of mass
+const REDUCTION_SIZE = 256u;
+
+var<workgroup> shared_mass_position: array<vec4<f32>, REDUCTION_SIZE>;
+var<workgroup> shared_momentum: array<vec4<f32>, REDUCTION_SIZE>;
+
+// Reduces the center of mass of the whole system in a single workgroup
+// and resets the escape counter for this step
+@compute @workgroup_size(REDUCTION_SIZE)
+fn cs_center_of_mass(@builtin(local_invocation_id) local_id: vec3<u32>) {
+    let local_index = local_id.x;
+
+    // Each thread accumulates a strided slice of the particles
+    var mass_position = vec4<f32>(0.0, 0.0, 0.0, 0.0);
+    var momentum = vec4<f32>(0.0, 0.0, 0.0, 0.0);
+    for (var i = local_index; i < params.num_particles; i = i + REDUCTION_SIZE) {
+        let particle = load_particle(i);
+        let mass = particle.position.w;
+        mass_position = mass_position + vec4<f32>(particle.position.xyz * mass, mass);
+        momentum = momentum + vec4<f32>(particle.velocity.xyz * mass, 0.0);
+    }
+
+    shared_mass_position[local_index] = mass_position;
+    shared_momentum[local_index] = momentum;
+    workgroupBarrier();
+
+    // Tree reduction in shared memory
+    for (var stride = REDUCTION_SIZE / 2u; stride > 0u; stride = stride / 2u) {
+        if (local_index < stride) {
+            shared_mass_position[local_index] = shared_mass_position[local_index] + shared_mass_position[local_index + stride];
+            shared_momentum[local_index] = shared_momentum[local_index] + shared_momentum[local_index + stride];
+        }
+        workgroupBarrier();
+    }
+
+    if (local_index == 0u) {
+        let total = shared_mass_position[0];
+        let total_mass = max(total.w, 1e-20);
+
+        system_state.center_of_mass = vec4<f32>(total.xyz / total_mass, total.w);
+        system_state.center_of_mass_velocity = vec4<f32>(shared_momentum[0].xyz / total_mass, 0.0);
+        atomicStore(&system_state.escape_count, 0u);
+    }
+}
+
+// Records the particle if it is unbound (positive specific energy relative to the center of mass)
+// and further than the escape radius from the center of mass
+fn check_escape(particle: Particle) {
+    let offset = particle.position.xyz - system_state.center_of_mass.xyz;
+    let distance = length(offset);
+
+    if (distance <= params.escape_radius) {
+        return;
+    }
+
+    // Treat the rest of the system as a point mass at the center of mass
+    let relative_velocity = particle.velocity.xyz - system_state.center_of_mass_velocity.xyz;
+    let remaining_mass = max(system_state.center_of_mass.w - particle.position.w, 0.0);
+    let kinetic_energy = 0.5 * dot(relative_velocity, relative_velocity);
+    let potential_energy = -params.gravitational_constant * remaining_mass / sqrt(distance * distance + params.softening);
+
+    if (kinetic_energy + potential_energy > 0.0) {
+        let escape_index = atomicAdd(&system_state.escape_count, 1u);
+
+        // Ensure we don't overflow the escape buffer, the rest are picked up on a later step
+        if (escape_index < arrayLength(&escapes)) {
+            var record: EscapeRecord;
+            record.velocity = particle.velocity;
+            record.id = particle.id;
+            escapes[escape_index] = record;
+        }
+    }
+}
+
+const INTEGRATOR_EULER = 0u;                 // x += v dt, then v += a dt
+const INTEGRATOR_SEMI_IMPLICIT_EULER = 1u;   // v += a dt, then x += v dt
+const INTEGRATOR_LEAPFROG = 2u;              // drift half a step, kick, drift half a step
+
+const SOFTENING_ADDITIVE = 0u;  // |a| = law(r^2 + softening) along the unit direction
+const SOFTENING_PLUMMER = 1u;   // a = law(r^2 + softening) along diff / sqrt(r^2 + softening)
+const SOFTENING_NONE = 2u;      // |a| = law(r^2)
+
+const FORCE_INVERSE_SQUARE = 0u;  // gravity
+const FORCE_INVERSE_LINEAR = 1u;  // gravity in two dimensions
+const FORCE_SPRING = 2u;          // grows with distance, keeps the system bound
+
+const INTEGRATOR: u32 = #INTEGRATOR;
+const SOFTENING_KERNEL: u32 = #SOFTENING_KERNEL;
+const FORCE_LAW: u32 = #FORCE_LAW;
+const TILED: u32 = #TILED;
+// Fixed per pipeline, naga only accepts const expressions in @workgroup_size so the size is
+// picked with the define rather than an override. The dispatch size is derived from the same value.
+const WORKGROUP_SIZE: u32 = #WORKGROUP_SIZE;
+
+// One tile of bodies (xyz = position, w = mass) shared by the workgroup
+var<workgroup> shared_bodies: array<vec4<f32>, WORKGROUP_SIZE>;
+
+// How far the bodies drift before the forces are evaluated
+fn drift_time() -> f32 {
+    if (INTEGRATOR == INTEGRATOR_LEAPFROG) {
+        return 0.5 * params.delta_time;
+    }
+    return 0.0;
+}
+
+// The particle in the given slot as a body at the position the forces are evaluated at.
+// Only the leapfrog drift needs the velocity, the others read nothing but the position word.
+fn body_of(index: u32) -> vec4<f32> {
+    let body = load_position(index);
+    if (INTEGRATOR == INTEGRATOR_LEAPFROG) {
+        let velocity = bitcast<vec3<f32>>(particles[velocity_word(index)].xyz);
+        return vec4<f32>(body.xyz + velocity * drift_time(), body.w);
+    }
+    return body;
+}
+
+// Acceleration of a particle at `position` towards `body`
+fn pair_acceleration(position: vec3<f32>, body: vec4<f32>, softening: f32) -> vec3<f32> {
+    let diff = body.xyz - position;
+    let dist_sqr = dot(diff, diff);
+
+    // coincident bodies have no direction to pull in
+    if (dist_sqr == 0.0) {
+        return vec3<f32>(0.0, 0.0, 0.0);
+    }
+
+    var softened_sqr = dist_sqr + softening;
+    if (SOFTENING_KERNEL == SOFTENING_NONE) {
+        softened_sqr = dist_sqr;
+    }
+
+    var magnitude: f32;
+    if (FORCE_LAW == FORCE_INVERSE_LINEAR) {
+        magnitude = inverseSqrt(softened_sqr);
+    } else if (FORCE_LAW == FORCE_SPRING) {
+        magnitude = sqrt(softened_sqr);
+    } else {
+        magnitude = 1.0 / softened_sqr;
+    }
+    magnitude = magnitude * params.gravitational_constant * body.w;
+
+    if (SOFTENING_KERNEL == SOFTENING_PLUMMER) {
+        return diff * inverseSqrt(softened_sqr) * magnitude;
+    }
+    return diff * inverseSqrt(dist_sqr) * magnitude;
+}
+
+// Sums the acceleration from every other particle, reading them straight from the buffer
+fn direct_acceleration(index: u32, position: vec3<f32>, softening: f32) -> vec3<f32> {
+    var acceleration = vec3<f32>(0.0, 0.0, 0.0);
 
     for (var i = 0u; i < params.num_particles; i = i + 1u) {
-        // Skip self
+        // Skip self-interaction
         if (i == index) {
             continue;
         }
+        acceleration = acceleration + pair_acceleration(position, body_of(i), softening);
+    }
+
+    return acceleration;
+}
+
+// Sums the acceleration from every other particle, one tile of bodies in shared memory at a time.
+// Every invocation of the workgroup has to call this so they all reach the barriers.
+fn tiled_acceleration(index: u32, local_index: u32, position: vec3<f32>, softening: f32) -> vec3<f32> {
+    var acceleration = vec3<f32>(0.0, 0.0, 0.0);
+
+    // Calculate number of tiles needed to process all particles
+    let num_tiles = (params.num_particles + WORKGROUP_SIZE - 1u) / WORKGROUP_SIZE;
+
+    for (var tile = 0u; tile < num_tiles; tile = tile + 1u) {
+        // Collaboratively load particles into shared memory
+        let tile_offset = tile * WORKGROUP_SIZE;
+        let load_index = tile_offset + local_index;
+
+        if (load_index < params.num_particles) {
+            shared_bodies[local_index] = body_of(load_index);
+        }
+
+        workgroupBarrier();
+
+        // Process forces from particles in this tile
+        let tile_particles = min(WORKGROUP_SIZE, params.num_particles - tile_offset);
+
+        for (var i = 0u; i < tile_particles; i = i + 1u) {
+            // Skip self-interaction
+            if (tile_offset + i == index) {
+                continue;
+            }
+            acceleration = acceleration + pair_acceleration(position, shared_bodies[i], softening);
+        }
+
+        // Ensure all threads are done with shared memory before the next tile
+        workgroupBarrier();
+    }
+
+    return acceleration;
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn cs_main(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+) {
+    let index = global_id.x;
+
+    // Invocations past the last particle only help load the tiles
+    let active = index < params.num_particles;
+
+    // Load the current particle and look up the settings of its species
+    var current_particle: Particle;
+    var species_index = 0u;
+    var softening = params.softening;
+    if (active) {
+        current_particle = load_particle(index);
+        species_index = species_of(index);
+        if (species[species_index].softening >= 0.0) {
+            softening = species[species_index].softening;
+        }
+    }
+
+    // Apply N-body gravitational force
+    let drift = drift_time();
+    let position = current_particle.position.xyz + current_particle.velocity * drift;
+    var acceleration = vec3<f32>(0.0, 0.0, 0.0);
+    if (TILED != 0u) {
+        acceleration = tiled_acceleration(index, local_id.x, position, softening);
+    } else if (active) {
+        acceleration = direct_acceleration(index, position, softening);
+    }
+
+    if (!active) {
+        return;
+    }
+
+    var new_particle = current_particle;
 
-        let other_particle = particles[i];
-        let mass_j = other_particle.position.w;
-
-        // Calculate distance vector
-        let diff = other_particle.position.xyz - current_particle.position.xyz;
-        let dist_sqr = dot(diff, diff) + params.softening;
-                
-        // Newton's law of universal gravitation: F = G * m1 * m2 / r^2
-        let inv_dist_sqr = 1.0 / dist_sqr;
-                
-        // Calculate the gravitational force
-        let force = params.gravitational_constant * current_particle.position.w * mass_j * inv_dist_sqr;
-                
-        // Accumulate force
-        total_force = total_force + normalize(diff) * force;
-    }
-
-    // Update velocity based on force (F = ma, so a = F/m)
-    let new_velocity = current_particle.velocity.xyz + (total_force / current_particle.position.w) * params.delta_time;
-    new_particle.velocity.x = new_velocity.x;
-    new_particle.velocity.y = new_velocity.y;
-    new_particle.velocity.z = new_velocity.z;
-
-    // Update position based on velocity
-    let new_position = current_particle.position.xyz + new_velocity * params.delta_time;
-    new_particle.position.x = new_position.x;
-    new_particle.position.y = new_position.y;
-    new_particle.position.z = new_position.z;
+    // Integrate the velocity and position
+    let new_velocity = current_particle.velocity + acceleration * params.delta_time;
+    var new_position: vec3<f32>;
+    if (INTEGRATOR == INTEGRATOR_EULER) {
+        new_position = current_particle.position.xyz + current_particle.velocity * params.delta_time;
+    } else if (INTEGRATOR == INTEGRATOR_LEAPFROG) {
+        new_position = position + new_velocity * drift;
+    } else {
+        new_position = current_particle.position.xyz + new_velocity * params.delta_time;
+    }
+
+    new_particle.velocity = new_velocity;
+    new_particle.position = vec4<f32>(new_position, current_particle.position.w);
 
     // Store updated particle
-    new_particles[index] = new_particle;
+    store_particle(index, new_particle);
+
+    if (params.escape_enabled != 0u) {
+        check_escape(new_particle);
+    }
+}
+
+// Size of the workgroups generating the instances, ParticleInstancesTask sets the define and
+// derives the dispatch size from it
+const INSTANCE_WORKGROUP_SIZE: u32 = #INSTANCE_WORKGROUP_SIZE;
+
+// Whether a sphere is at least partly inside the view frustum of cull_params.view_proj.
+// The frustum planes are sums and differences of the matrix's rows, with the near plane at z = 0.
+fn in_frustum(center: vec3<f32>, radius: f32) -> bool {
+    // the columns of the transpose are the rows of the view-projection
+    let rows = transpose(cull_params.view_proj);
+    var planes = array<vec4<f32>, 6>(
+        rows[3] + rows[0],
+        rows[3] - rows[0],
+        rows[3] + rows[1],
+        rows[3] - rows[1],
+        rows[2],
+        rows[3] - rows[2],
+    );
+
+    for (var i = 0u; i < 6u; i = i + 1u) {
+        let plane = planes[i];
+        if (dot(plane.xyz, center) + plane.w < -radius * length(plane.xyz)) {
+            return false;
+        }
+    }
+    return true;
+}
+
+// The level of detail of a sphere, the first whose smallest screen size its diameter reaches
+fn lod_of(center: vec3<f32>, radius: f32) -> u32 {
+    let w = dot(transpose(cull_params.view_proj)[3], vec4<f32>(center, 1.0));
+    let screen_size = 2.0 * radius * cull_params.screen_scale / max(w, 1e-6);
+
+    var lod = 0u;
+    while (lod + 1u < cull_params.lod_count && screen_size < cull_params.lod_screen_sizes[lod]) {
+        lod = lod + 1u;
+    }
+    return lod;
+}
+
+// The instance list and draw arguments of a species at a level of detail
+fn instance_list(species_index: u32, lod: u32) -> u32 {
+    return species_index * cull_params.lod_count + lod;
+}
+
+// Appends an instance of every particle in `particles` to the range of the instance buffer of
+// its species at the level of detail of its size on screen. Dispatched once per drawn frame on the state after the frame's step, however many
+// steps the simulation took since the last one.
+@compute @workgroup_size(INSTANCE_WORKGROUP_SIZE)
+fn cs_generate_instances(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= params.num_particles) {
+        return;
+    }
+
+    let particle = load_particle(index);
+    let species_index = species_of(index);
+    let particle_species = species[species_index];
 
     // Determine if this particle should be included in the instance buffer for rendering
-    // based on its distance from origin or other criteria
-    let distance_from_origin = length(new_particle.position.xyz);
+    let distance_from_origin = length(particle.position.xyz);
+    let in_range = distance_from_origin >= params.min_distance && distance_from_origin <= params.max_distance;
+    if (cull_params.range_enabled != 0u && !in_range) {
+        atomicAdd(&culling_counts.culled_by_range, 1u);
+        return;
+    }
 
-    if (distance_from_origin >= params.min_distance && distance_from_origin <= params.max_distance) {
-        // Atomically append this particle's data to the instance buffer
-        let old_count = atomicAdd(&indirect_buffer.instance_count, 1u);
+    // The size scales the mesh, see render_particles.wgsl
+    let size = particle_species.size_base + particle.position.w * particle_species.size_scale;
+    let radius = cull_params.bounding_radius * size;
+    if (cull_params.frustum_enabled != 0u && !in_frustum(particle.position.xyz, radius)) {
+        atomicAdd(&culling_counts.culled_by_frustum, 1u);
+        return;
+    }
 
-        // Ensure we don't overflow the instance buffer
-        if (old_count < arrayLength(&instance_buffer)) {
-            // Create an instance based on the particle properties
-            var instance: Instance;
+    let list = instance_list(species_index, lod_of(particle.position.xyz, radius));
 
-            // Set position (xyz) and size based on mass (w)
-            instance.position = vec4<f32>(
-                new_particle.position.xyz, 0.5 + (new_particle.position.w * 0.00001)  // Scale size based on mass
-            );
+    // Atomically append this particle's data to its list's range of the instance buffer
+    let old_count = atomicAdd(&indirect_buffer[list].instance_count, 1u);
+
+    // Ensure we don't overflow the list's range of the instance buffer,
+    // cs_clamp_instance_counts counts the instances that didn't fit
+    if (old_count < params.instance_capacity) {
+        // Create an instance based on the particle properties
+        var instance: Instance;
+
+        // Set position (xyz) and size based on mass (w)
+        instance.position = vec4<f32>(particle.position.xyz, size);
 
+        if (particle_species.color_rule == COLOR_RULE_SOLID) {
+            instance.color = particle_species.color;
+        } else {
             // Set color based on velocity (faster = redder)
-            let speed = length(new_particle.velocity.xyz);
+            let speed = length(particle.velocity.xyz);
             instance.color = vec4<f32>(
                 min(1.0, speed / 20.0),         // R: higher with speed
                 min(1.0, 0.2 + 0.8 / speed),    // G: lower with speed
                 min(1.0, 0.5 / speed),          // B: lower with speed
                 1.0                             // A: fully opaque
             );
+        }
 
-            // Store velocity for visual effects
-            instance.velocity = vec4<f32>(new_particle.velocity.xyz, 0.0);
+        // Store velocity for visual effects
+        instance.velocity = particle.velocity;
+        instance.id = particle.id;
+        instance.index = index;
 
-            // Write the instance
-            instance_buffer[old_count] = instance;
-        }
+        // Write the instance
+        store_instance(list * params.instance_capacity + old_count, instance);
+    }
+}
+
+// Clamps the instance counts cs_generate_instances appended to the capacity of each list's
+// range, so the draws never read past it, and counts the drawn and dropped instances.
+// Dispatched as a single invocation after cs_generate_instances.
+@compute @workgroup_size(1)
+fn cs_clamp_instance_counts() {
+    var drawn = 0u;
+    var dropped = 0u;
+    for (var list = 0u; list < arrayLength(&indirect_buffer); list = list + 1u) {
+        let count = atomicLoad(&indirect_buffer[list].instance_count);
+        let clamped = min(count, params.instance_capacity);
+        atomicStore(&indirect_buffer[list].instance_count, clamped);
+
+        drawn = drawn + clamped;
+        dropped = dropped + (count - clamped);
+    }
+
+    culling_counts.drawn = drawn;
+    culling_counts.dropped = dropped;
+}
+
+// The buffers of the Morton reorder. No kernel uses both them and the instance set, so they
+// share its group number.
+@group(1) @binding(0) var<storage, read_write> morton_codes: array<u32>;
+// The slot each sorted particle comes from, the payload of the sort
+@group(1) @binding(1) var<storage, read_write> reorder_permutation: array<u32>;
+// min xyz then max xyz of the positions, as order preserving bits so they reduce with atomics.
+// Cleared to the empty bounds before cs_particle_bounds.
+@group(1) @binding(2) var<storage, read_write> reorder_bounds: array<atomic<u32>, 6>;
+// The particle attributes in the sorted order, copied back over particle_attributes
+@group(1) @binding(3) var<storage, read_write> reorder_attributes: array<u32>;
+// particle_attributes bound read only, so the reorder kernels leave group 0 to the particles
+// and stay within the storage buffer limit
+@group(1) @binding(4) var<storage, read> reorder_source_attributes: array<u32>;
+
+// Size of the workgroups of the reorder kernels
+const REORDER_WORKGROUP_SIZE = 64u;
+
+// Maps a float to bits that compare as unsigned integers the way the floats compare
+fn to_ordered_bits(value: f32) -> u32 {
+    let bits = bitcast<u32>(value);
+    if ((bits & 0x80000000u) != 0u) {
+        return ~bits;
+    }
+    return bits | 0x80000000u;
+}
+
+fn from_ordered_bits(bits: u32) -> f32 {
+    if ((bits & 0x80000000u) != 0u) {
+        return bitcast<f32>(bits & 0x7fffffffu);
+    }
+    return bitcast<f32>(~bits);
+}
+
+// Spreads the low 10 bits so two zero bits follow each one
+fn spread_bits(value: u32) -> u32 {
+    var bits = value & 0x3ffu;
+    bits = (bits | (bits << 16u)) & 0x030000ffu;
+    bits = (bits | (bits << 8u)) & 0x0300f00fu;
+    bits = (bits | (bits << 4u)) & 0x030c30c3u;
+    bits = (bits | (bits << 2u)) & 0x09249249u;
+    return bits;
+}
+
+// The 30 bit Morton code of a position normalized to the unit cube, 10 bits per axis
+fn morton_code(normalized: vec3<f32>) -> u32 {
+    let cell = vec3<u32>(clamp(normalized, vec3<f32>(0.0), vec3<f32>(1.0)) * 1023.0);
+    return spread_bits(cell.x) | (spread_bits(cell.y) << 1u) | (spread_bits(cell.z) << 2u);
+}
+
+// Grows the bounds to take in every particle
+@compute @workgroup_size(REORDER_WORKGROUP_SIZE)
+fn cs_particle_bounds(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= params.num_particles) {
+        return;
+    }
+
+    let position = load_position(index).xyz;
+    for (var axis = 0u; axis < 3u; axis = axis + 1u) {
+        let bits = to_ordered_bits(position[axis]);
+        atomicMin(&reorder_bounds[axis], bits);
+        atomicMax(&reorder_bounds[axis + 3u], bits);
+    }
+}
+
+// The Morton code of every particle within the bounds, with the slot it is in as the payload
+@compute @workgroup_size(REORDER_WORKGROUP_SIZE)
+fn cs_morton_codes(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= params.num_particles) {
+        return;
+    }
+
+    var bounds_min: vec3<f32>;
+    var bounds_max: vec3<f32>;
+    for (var axis = 0u; axis < 3u; axis = axis + 1u) {
+        bounds_min[axis] = from_ordered_bits(atomicLoad(&reorder_bounds[axis]));
+        bounds_max[axis] = from_ordered_bits(atomicLoad(&reorder_bounds[axis + 3u]));
+    }
+
+    let extent = max(bounds_max - bounds_min, vec3<f32>(1e-20));
+    let position = load_position(index).xyz;
+    morton_codes[index] = morton_code((position - bounds_min) / extent);
+    reorder_permutation[index] = index;
+}
+
+// Moves every particle and its attributes to its sorted slot, the particles into new_particles
+@compute @workgroup_size(REORDER_WORKGROUP_SIZE)
+fn cs_gather_particles(@builtin(global_invocation_id) global_id: vec3<u32>) {
+    let index = global_id.x;
+    if (index >= params.num_particles) {
+        return;
+    }
+
+    let source = reorder_permutation[index];
+    store_particle(index, load_particle(source));
+    for (var word = 0u; word < params.attribute_stride; word = word + 1u) {
+        reorder_attributes[index * params.attribute_stride + word] =
+            reorder_source_attributes[source * params.attribute_stride + word];
     }
 }
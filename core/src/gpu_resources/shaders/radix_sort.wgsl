@@ -0,0 +1,155 @@
+// A stable least significant digit radix sort of u32 keys, moving a u32 payload along.
+// Every pass sorts on one digit: cs_radix_count histograms the digits of every block,
+// cs_radix_scan turns the histograms into the first output slot of each digit in each block and
+// cs_radix_scatter moves the keys there, keeping the order of equal digits.
+
+// Parameters of one pass
+@export struct SortParams {
+    count: u32,        // Keys to sort
+    shift: u32,        // The bit the pass's digit starts at
+    block_count: u32,  // Blocks of BLOCK_SIZE keys, one workgroup each
+    _0: u32,           // Padding
+}
+
+const RADIX_BITS = 4u;
+const RADIX = 16u;  // 1 << RADIX_BITS
+const SORT_WORKGROUP_SIZE = 128u;
+// Keys each invocation of the count and scatter kernels handles
+const ITEMS_PER_THREAD = 32u;
+const BLOCK_SIZE = 4096u;  // SORT_WORKGROUP_SIZE * ITEMS_PER_THREAD
+
+@group(0) @binding(0) var<uniform> params: SortParams;
+@group(0) @binding(1) var<storage, read> keys_in: array<u32>;
+@group(0) @binding(2) var<storage, read> payload_in: array<u32>;
+@group(0) @binding(3) var<storage, read_write> keys_out: array<u32>;
+@group(0) @binding(4) var<storage, read_write> payload_out: array<u32>;
+// Digit major, the count of every block for digit 0 first, so the exclusive scan over the
+// whole array gives every block the first slot of each of its digits
+@group(0) @binding(5) var<storage, read_write> histograms: array<u32>;
+
+fn digit_of(key: u32) -> u32 {
+    return (key >> params.shift) & (RADIX - 1u);
+}
+
+var<workgroup> digit_counts: array<atomic<u32>, RADIX>;
+
+// Counts the digits of one block of keys
+@compute @workgroup_size(SORT_WORKGROUP_SIZE)
+fn cs_radix_count(
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+) {
+    let block = workgroup_id.x;
+    let local_index = local_id.x;
+
+    if (local_index < RADIX) {
+        atomicStore(&digit_counts[local_index], 0u);
+    }
+    workgroupBarrier();
+
+    // the order doesn't matter for counting, so neighbouring invocations read neighbouring keys
+    let block_start = block * BLOCK_SIZE;
+    for (var i = 0u; i < ITEMS_PER_THREAD; i = i + 1u) {
+        let index = block_start + i * SORT_WORKGROUP_SIZE + local_index;
+        if (index < params.count) {
+            atomicAdd(&digit_counts[digit_of(keys_in[index])], 1u);
+        }
+    }
+    workgroupBarrier();
+
+    if (local_index < RADIX) {
+        histograms[local_index * params.block_count + block] = atomicLoad(&digit_counts[local_index]);
+    }
+}
+
+var<workgroup> scan_totals: array<u32, SORT_WORKGROUP_SIZE>;
+
+// Exclusive scan of the histograms in a single workgroup, each invocation scans a contiguous run
+@compute @workgroup_size(SORT_WORKGROUP_SIZE)
+fn cs_radix_scan(@builtin(local_invocation_id) local_id: vec3<u32>) {
+    let local_index = local_id.x;
+    let histogram_length = RADIX * params.block_count;
+    let run_length = (histogram_length + SORT_WORKGROUP_SIZE - 1u) / SORT_WORKGROUP_SIZE;
+    let run_start = min(local_index * run_length, histogram_length);
+    let run_end = min(run_start + run_length, histogram_length);
+
+    var total = 0u;
+    for (var i = run_start; i < run_end; i = i + 1u) {
+        total = total + histograms[i];
+    }
+    scan_totals[local_index] = total;
+    workgroupBarrier();
+
+    // there are only as many runs as invocations, one of them scans the totals
+    if (local_index == 0u) {
+        var sum = 0u;
+        for (var i = 0u; i < SORT_WORKGROUP_SIZE; i = i + 1u) {
+            let run_total = scan_totals[i];
+            scan_totals[i] = sum;
+            sum = sum + run_total;
+        }
+    }
+    workgroupBarrier();
+
+    var sum = scan_totals[local_index];
+    for (var i = run_start; i < run_end; i = i + 1u) {
+        let count = histograms[i];
+        histograms[i] = sum;
+        sum = sum + count;
+    }
+}
+
+// The digit counts of every invocation, digit major, scanned into each invocation's offsets
+var<workgroup> thread_counts: array<u32, 2048>;  // RADIX * SORT_WORKGROUP_SIZE
+
+// Moves one block of keys and their payload to their sorted slots for this pass's digit.
+// Each invocation handles a contiguous run of the block, so walking the runs in invocation order
+// walks the keys in order and keys with equal digits keep their order.
+@compute @workgroup_size(SORT_WORKGROUP_SIZE)
+fn cs_radix_scatter(
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+) {
+    let block = workgroup_id.x;
+    let local_index = local_id.x;
+    let run_start = block * BLOCK_SIZE + local_index * ITEMS_PER_THREAD;
+    let run_end = min(run_start + ITEMS_PER_THREAD, params.count);
+
+    var counts: array<u32, RADIX>;
+    for (var i = run_start; i < run_end; i = i + 1u) {
+        let digit = digit_of(keys_in[i]);
+        counts[digit] = counts[digit] + 1u;
+    }
+    for (var digit = 0u; digit < RADIX; digit = digit + 1u) {
+        thread_counts[digit * SORT_WORKGROUP_SIZE + local_index] = counts[digit];
+    }
+    workgroupBarrier();
+
+    // one invocation per digit scans the counts of that digit over the invocations
+    if (local_index < RADIX) {
+        let first = local_index * SORT_WORKGROUP_SIZE;
+        var sum = 0u;
+        for (var i = 0u; i < SORT_WORKGROUP_SIZE; i = i + 1u) {
+            let count = thread_counts[first + i];
+            thread_counts[first + i] = sum;
+            sum = sum + count;
+        }
+    }
+    workgroupBarrier();
+
+    var offsets: array<u32, RADIX>;
+    for (var digit = 0u; digit < RADIX; digit = digit + 1u) {
+        offsets[digit] = histograms[digit * params.block_count + block]
+            + thread_counts[digit * SORT_WORKGROUP_SIZE + local_index];
+    }
+
+    for (var i = run_start; i < run_end; i = i + 1u) {
+        let key = keys_in[i];
+        let digit = digit_of(key);
+        let slot = offsets[digit];
+        offsets[digit] = slot + 1u;
+
+        keys_out[slot] = key;
+        payload_out[slot] = payload_in[i];
+    }
+}
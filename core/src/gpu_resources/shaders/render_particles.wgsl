@@ -7,10 +7,25 @@
 
 #import include/basic_vertex.wgsl
 
-@export struct ParticleInstance {
+// Extra per-particle attributes declared by the ParticleAttributeSchema, indexed by instance.index
+@group(2) @binding(0) var<storage, read> particle_attributes: array<u32>;
+
+// The full instance format, the vertex layout (see GpuParticleInstance) steps over the padding
+// of the instances written by the simulation
+struct ParticleInstance {
+    @location(2) position: vec4<f32>,
+    @location(3) color: vec4<f32>,
+    @location(4) velocity: vec3<f32>,
+    @location(5) id: u32,
+    @location(6) index: u32,
+}
+
+// The packed instance format, the vertex layout widens the half floats and unorm bytes
+// (see GpuPackedParticleInstance) so the attributes arrive as floats
+struct PackedParticleInstance {
     @location(2) position: vec4<f32>,
     @location(3) color: vec4<f32>,
-    @location(4) velocity: vec4<f32>,
+    @location(6) index: u32,
 }
 
 struct VertexOutput {
@@ -20,27 +35,80 @@ struct VertexOutput {
     @location(2) tex_coords: vec2<f32>,
 }
 
-@vertex
-fn vs_main(
+fn instance_vertex(
     vertex: basic_vertex::BasicVertex,
-    instance: ParticleInstance,
+    position: vec4<f32>,
+    color: vec4<f32>,
+    velocity: vec3<f32>,
 ) -> VertexOutput {
-    var output: VertexOutput;
-
     // Scale the mesh by the instance's size (stored in position.w)
-    let world_position = vertex.position * instance.position.w + instance.position.xyz;
+    let world_position = vertex.position * position.w + position.xyz;
+    return world_vertex(vertex, world_position, color, velocity);
+}
+
+// The XY plane of the mesh turned to face the camera, for the billboard levels of detail
+fn billboard_vertex(
+    vertex: basic_vertex::BasicVertex,
+    position: vec4<f32>,
+    color: vec4<f32>,
+    velocity: vec3<f32>,
+) -> VertexOutput {
+    let offset = (camera::right() * vertex.position.x + camera::up() * vertex.position.y) * position.w;
+    return world_vertex(vertex, position.xyz + offset, color, velocity);
+}
+
+fn world_vertex(
+    vertex: basic_vertex::BasicVertex,
+    world_position: vec3<f32>,
+    color: vec4<f32>,
+    velocity: vec3<f32>,
+) -> VertexOutput {
+    var output: VertexOutput;
 
     // Transform to clip space
     output.clip_position = camera::to_clip(world_position);
 
     // Pass instance color and velocity to fragment shader
-    output.color = instance.color;
-    output.velocity = instance.velocity.xyz;
+    output.color = color;
+    output.velocity = velocity;
     output.tex_coords = vertex.tex_coords;
 
     return output;
 }
 
+@vertex
+fn vs_main(
+    vertex: basic_vertex::BasicVertex,
+    instance: ParticleInstance,
+) -> VertexOutput {
+    return instance_vertex(vertex, instance.position, instance.color, instance.velocity.xyz);
+}
+
+// Packed instances carry no velocity
+@vertex
+fn vs_packed(
+    vertex: basic_vertex::BasicVertex,
+    instance: PackedParticleInstance,
+) -> VertexOutput {
+    return instance_vertex(vertex, instance.position, instance.color, vec3<f32>(0.0, 0.0, 0.0));
+}
+
+@vertex
+fn vs_billboard(
+    vertex: basic_vertex::BasicVertex,
+    instance: ParticleInstance,
+) -> VertexOutput {
+    return billboard_vertex(vertex, instance.position, instance.color, instance.velocity.xyz);
+}
+
+@vertex
+fn vs_packed_billboard(
+    vertex: basic_vertex::BasicVertex,
+    instance: PackedParticleInstance,
+) -> VertexOutput {
+    return billboard_vertex(vertex, instance.position, instance.color, vec3<f32>(0.0, 0.0, 0.0));
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     return diffuse::sample_2D(in.tex_coords.xy) * in.color;
@@ -0,0 +1,84 @@
+#define CAMERA_GROUP 0
+#import include/camera.wgsl
+
+// Laid out like CullParams in n-body-sim-compute.wgsl, bound to the same buffer
+struct CullParams {
+    view_proj: mat4x4<f32>,
+    bounding_radius: f32,
+    frustum_enabled: u32,
+    range_enabled: u32,
+    lod_count: u32,
+    lod_screen_sizes: vec4<f32>,
+    screen_scale: f32,
+    _0: u32,
+    _1: u32,
+    _2: u32,
+}
+
+@group(1) @binding(0) var<uniform> cull_params: CullParams;
+
+// The full instance format, laid out like ParticleInstance in render_particles.wgsl
+struct SpriteInstance {
+    @location(2) position: vec4<f32>,
+    @location(3) color: vec4<f32>,
+}
+
+// The packed instance format, widened to floats by its vertex layout
+struct PackedSpriteInstance {
+    @location(2) position: vec4<f32>,
+    @location(3) color: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    // -1 to 1 across the sprite
+    @location(1) offset: vec2<f32>,
+}
+
+// The quad is indexed 0, 1, 2, 2, 1, 3 (see RenderSpritesPipeline), one corner per index
+fn corner(vertex_index: u32) -> vec2<f32> {
+    return vec2<f32>(f32(vertex_index & 1u), f32(vertex_index >> 1u)) * 2.0 - 1.0;
+}
+
+fn sprite_vertex(vertex_index: u32, position: vec4<f32>, color: vec4<f32>) -> VertexOutput {
+    var output: VertexOutput;
+
+    let offset = corner(vertex_index);
+    let center = camera::to_clip(position.xyz);
+
+    // The quad covers the sphere the instance is culled by, the bounding radius scaled by the
+    // instance's size (stored in position.w). The divide by w shrinks it with the distance as a
+    // mesh of that size would.
+    let scale = camera::proj_scale() * cull_params.bounding_radius * position.w;
+    output.clip_position = center + vec4<f32>(offset * scale, 0.0, 0.0);
+    output.color = color;
+    output.offset = offset;
+
+    return output;
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32, instance: SpriteInstance) -> VertexOutput {
+    return sprite_vertex(vertex_index, instance.position, instance.color);
+}
+
+@vertex
+fn vs_packed(
+    @builtin(vertex_index) vertex_index: u32,
+    instance: PackedSpriteInstance,
+) -> VertexOutput {
+    return sprite_vertex(vertex_index, instance.position, instance.color);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let distance_squared = dot(in.offset, in.offset);
+    if (distance_squared > 1.0) {
+        discard;
+    }
+
+    // Fades out towards the rim of the circle
+    let falloff = (1.0 - distance_squared) * (1.0 - distance_squared);
+    return vec4<f32>(in.color.rgb, in.color.a * falloff);
+}
@@ -0,0 +1,55 @@
+use glam::{Mat4, Vec3, Vec4};
+
+use crate::define_gpu_data_type;
+
+define_gpu_data_type!(
+    super::super::shaders::n_body_sim_compute::naga::types::CullParams as GpuCullParams
+);
+
+impl GpuCullParams {
+    /// Culls nothing until frustum or range culling is enabled, with a single level of detail
+    pub fn new(bounding_radius: f32) -> Self {
+        Self {
+            view_proj: Mat4::IDENTITY,
+            bounding_radius,
+            frustum_enabled: 0,
+            range_enabled: 0,
+            lod_count: 1,
+            lod_screen_sizes: Vec4::ZERO,
+            screen_scale: 1.0,
+            _0: 0,
+            _1: 0,
+            _2: 0,
+        }
+    }
+
+    /// Whether a sphere is at least partly inside the view frustum, like `in_frustum` in the
+    /// compute shader
+    pub fn in_frustum(&self, center: Vec3, radius: f32) -> bool {
+        let rows = [0, 1, 2, 3].map(|index| self.view_proj.row(index));
+        let planes = [
+            rows[3] + rows[0],
+            rows[3] - rows[0],
+            rows[3] + rows[1],
+            rows[3] - rows[1],
+            rows[2],
+            rows[3] - rows[2],
+        ];
+
+        planes.iter().all(|plane| {
+            plane.truncate().dot(center) + plane.w >= -radius * plane.truncate().length()
+        })
+    }
+
+    /// The level of detail of a sphere, like `lod_of` in the compute shader
+    pub fn lod_of(&self, center: Vec3, radius: f32) -> u32 {
+        let w = self.view_proj.row(3).dot(center.extend(1.0));
+        let screen_size = 2.0 * radius * self.screen_scale / w.max(1e-6);
+
+        let mut lod = 0;
+        while lod + 1 < self.lod_count && screen_size < self.lod_screen_sizes[lod as usize] {
+            lod += 1;
+        }
+        lod
+    }
+}
@@ -0,0 +1,149 @@
+use crate::define_gpu_data_type;
+
+use super::gpu_particle_instance::GpuParticleInstance;
+
+define_gpu_data_type!(
+    super::super::shaders::n_body_sim_compute::naga::types::PackedInstance
+        as GpuPackedParticleInstance
+);
+
+impl GpuPackedParticleInstance {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 3] =
+        wgpu::vertex_attr_array![2 => Float16x4, 3 => Unorm8x4, 6 => Uint32];
+
+    /// Packs an instance the same way the compute shader does, the velocity and id are dropped
+    pub fn from_instance(instance: &GpuParticleInstance) -> Self {
+        let position = instance.position;
+        let color = instance.color;
+
+        Self {
+            position_xy: pack_half2(position.x, position.y),
+            position_z_size: pack_half2(position.z, position.w),
+            color: pack_unorm8(color.x)
+                | (pack_unorm8(color.y) << 8)
+                | (pack_unorm8(color.z) << 16)
+                | (pack_unorm8(color.w) << 24),
+            index: instance.index,
+        }
+    }
+
+    /// The vertex fetch widens the halves and the color bytes back to floats, so the vertex shader
+    /// sees the same attribute types as with the full instances
+    pub fn instance_vertex_layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+/// Matches `pack2x16float`, the first value in the low half
+fn pack_half2(low: f32, high: f32) -> u32 {
+    f32_to_f16_bits(low) as u32 | ((f32_to_f16_bits(high) as u32) << 16)
+}
+
+/// Matches one component of `pack4x8unorm`
+fn pack_unorm8(value: f32) -> u32 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u32
+}
+
+/// Converts to a half float, rounding to nearest with ties to even
+fn f32_to_f16_bits(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x007f_ffff;
+
+    // infinity stays infinity and nan stays nan
+    if exponent == 0xff {
+        let nan = if mantissa != 0 { 0x0200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+        // subnormal, the implicit leading one becomes part of the mantissa
+        let mantissa = mantissa | 0x0080_0000;
+        return sign | round_shifted(mantissa, (14 - exponent) as u32) as u16;
+    }
+
+    // a rounding carry into the exponent is still the right result, up to infinity
+    sign | round_shifted(((exponent as u32) << 23) | mantissa, 13) as u16
+}
+
+/// `value >> shift` rounded to nearest, ties to even
+fn round_shifted(value: u32, shift: u32) -> u32 {
+    let shifted = value >> shift;
+    let rest = value & ((1 << shift) - 1);
+    let halfway = 1 << (shift - 1);
+    if rest > halfway || (rest == halfway && shifted & 1 == 1) {
+        shifted + 1
+    } else {
+        shifted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_matches_half(value: f32) {
+        let bits = f32_to_f16_bits(value);
+        let expected = half::f16::from_f32(value);
+        if value.is_nan() {
+            assert!(
+                half::f16::from_bits(bits).is_nan(),
+                "{value:e} gave {bits:#06x}"
+            );
+        } else {
+            assert_eq!(bits, expected.to_bits(), "{value:e}");
+        }
+    }
+
+    #[test]
+    fn f16_bits_match_half_at_the_edges() {
+        let values = [
+            0.0,
+            -0.0,
+            1.0,
+            -2.5,
+            // halfway between 1 and the next half float, and the one after, ties go to even
+            1.0 + 2f32.powi(-11),
+            1.0 + 3.0 * 2f32.powi(-11),
+            65504.0,
+            65519.0,
+            65520.0,
+            1e9,
+            6.1035156e-5,
+            5.9604645e-8,
+            2.9802322e-8,
+            2.9802326e-8,
+            1e-10,
+            f32::INFINITY,
+            f32::NEG_INFINITY,
+            f32::NAN,
+        ];
+        for value in values {
+            assert_matches_half(value);
+        }
+    }
+
+    #[test]
+    fn f16_bits_match_half_across_the_f32_range() {
+        for bits in (0..=u32::MAX).step_by(4093) {
+            assert_matches_half(f32::from_bits(bits));
+        }
+    }
+
+    #[test]
+    fn pack_half2_puts_the_first_value_low() {
+        assert_eq!(pack_half2(1.0, -2.0), 0xc000_3c00);
+    }
+}
@@ -30,9 +30,16 @@ impl GpuParticle {
         .normalize();
         velocity_vector *= velocity;
 
+        Self::new(position, mass, velocity_vector)
+    }
+
+    /// Creates a particle with the given state.
+    /// The id is assigned when the particle is added to the simulation.
+    pub fn new(position: Vec3, mass: f32, velocity: Vec3) -> Self {
         Self {
             position: Vec4::new(position.x, position.y, position.z, mass),
-            velocity: Vec4::new(velocity_vector.x, velocity_vector.y, velocity_vector.z, 0.0),
+            velocity,
+            id: 0,
         }
     }
 }
@@ -1,5 +1,24 @@
 use crate::define_gpu_data_type;
 
 define_gpu_data_type!(
-    super::super::shaders::render_particles::naga::types::ParticleInstance as GpuParticleInstance
+    super::super::shaders::n_body_sim_compute::naga::types::Instance as GpuParticleInstance
 );
+
+impl GpuParticleInstance {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 5] = wgpu::vertex_attr_array![
+        2 => Float32x4,
+        3 => Float32x4,
+        4 => Float32x3,
+        5 => Uint32,
+        6 => Uint32,
+    ];
+
+    /// The stride is the whole instance, so the padding after the index is never fetched
+    pub fn instance_layout() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
@@ -5,6 +5,9 @@ define_gpu_data_type!(
 );
 
 impl GpuSimParams {
+    /// Marks an attribute offset as absent from the particle attribute schema
+    pub const NO_ATTRIBUTE: u32 = u32::MAX;
+
     pub fn new(delta_time: f32, num_particles: u32, gravitational_constant: f32) -> Self {
         Self {
             delta_time,
@@ -13,8 +16,22 @@ impl GpuSimParams {
             softening: 0.1,
             min_distance: 1.0,
             max_distance: 100.0,
-            _0: 0,
-            _1: 0,
+            escape_radius: 0.0,
+            escape_enabled: 0,
+            attribute_stride: 0,
+            species_offset: Self::NO_ATTRIBUTE,
+            species_count: 1,
+            instance_capacity: 0,
         }
     }
+
+    /// Enables escape detection beyond the given radius, or disables it when `None`
+    pub fn set_escape_radius(&mut self, escape_radius: Option<f32>) {
+        self.escape_radius = escape_radius.unwrap_or(0.0);
+        self.escape_enabled = escape_radius.is_some() as u32;
+    }
+
+    pub fn escape_radius(&self) -> Option<f32> {
+        (self.escape_enabled != 0).then_some(self.escape_radius)
+    }
 }
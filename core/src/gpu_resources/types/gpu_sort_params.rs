@@ -0,0 +1,3 @@
+use crate::define_gpu_data_type;
+
+define_gpu_data_type!(super::super::shaders::radix_sort::naga::types::SortParams as GpuSortParams);
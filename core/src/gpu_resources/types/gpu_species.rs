@@ -0,0 +1,5 @@
+use crate::define_gpu_data_type;
+
+define_gpu_data_type!(
+    super::super::shaders::n_body_sim_compute::naga::types::Species as GpuSpecies
+);
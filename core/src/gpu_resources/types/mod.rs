@@ -1,8 +1,15 @@
 pub mod basic_vertex;
 pub mod gpu_camera;
+pub mod gpu_cull_params;
+pub mod gpu_culling_counts;
+pub mod gpu_escape_record;
 pub mod gpu_indirect_args;
 pub mod gpu_model;
+pub mod gpu_packed_particle_instance;
 pub mod gpu_particle;
 pub mod gpu_particle_instance;
 pub mod gpu_sim_params;
+pub mod gpu_sort_params;
+pub mod gpu_species;
+pub mod gpu_system_state;
 pub mod gpu_type_macros;
@@ -2,7 +2,9 @@
 
 pub mod core;
 mod ecs;
+mod events;
 mod gpu_resources;
 mod render;
+pub mod simulation;
 pub mod traits;
 mod utils;
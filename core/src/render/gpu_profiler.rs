@@ -0,0 +1,201 @@
+use std::sync::Arc;
+
+use crossbeam::channel::{Receiver, Sender, unbounded};
+
+use crate::{ecs::resources::frame_timings::FrameTimings, traits::apc_traits::ApcCallback};
+
+/// The most passes timed in a single frame, later passes go untimed
+pub const MAX_PROFILED_PASSES: u32 = 32;
+/// Frames whose timestamps can be in flight at once, a frame finding none free goes untimed
+const READBACK_BUFFER_COUNT: usize = 3;
+
+const TIMESTAMP_SIZE: u64 = std::mem::size_of::<u64>() as u64;
+const QUERY_COUNT: u32 = MAX_PROFILED_PASSES * 2;
+const QUERY_BUFFER_SIZE: u64 = QUERY_COUNT as u64 * TIMESTAMP_SIZE;
+
+/// A frame whose timestamps were copied to a readback buffer
+struct ProfiledFrame {
+    buffer: Arc<wgpu::Buffer>,
+    // the pass that wrote each pair of queries, in query order
+    passes: Vec<Option<&'static str>>,
+}
+
+/// Times the passes of the render graph with timestamp queries, one pair of queries per pass.
+/// The timestamps are resolved at the end of the frame and read back once the frame has been
+/// submitted, the durations end up in the `FrameTimings` resource a few frames later.
+pub struct GpuProfiler {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    // nanoseconds per timestamp tick
+    timestamp_period: f32,
+
+    free_buffers: Vec<Arc<wgpu::Buffer>>,
+    returned_sender: Sender<Arc<wgpu::Buffer>>,
+    returned_receiver: Receiver<Arc<wgpu::Buffer>>,
+
+    // the readback buffer of the frame being recorded
+    current_buffer: Option<Arc<wgpu::Buffer>>,
+    // frames recorded but not yet submitted when they were resolved
+    unmapped_frames: Vec<ProfiledFrame>,
+    // whether the timings read back count as a frame of the render graph
+    counts_frames: bool,
+}
+
+impl GpuProfiler {
+    /// `None` if the device wasn't created with `Features::TIMESTAMP_QUERY`
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("Pass Timestamp Query Set"),
+            ty: wgpu::QueryType::Timestamp,
+            count: QUERY_COUNT,
+        });
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Pass Timestamp Resolve Buffer"),
+            size: QUERY_BUFFER_SIZE,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let free_buffers = (0..READBACK_BUFFER_COUNT)
+            .map(|_| {
+                Arc::new(device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Pass Timestamp Readback Buffer"),
+                    size: QUERY_BUFFER_SIZE,
+                    usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }))
+            })
+            .collect();
+        let (returned_sender, returned_receiver) = unbounded();
+
+        Some(Self {
+            query_set,
+            resolve_buffer,
+            timestamp_period: queue.get_timestamp_period(),
+            free_buffers,
+            returned_sender,
+            returned_receiver,
+            current_buffer: None,
+            unmapped_frames: Vec::new(),
+            counts_frames: true,
+        })
+    }
+
+    /// Reports the timings without counting them as frames of the render graph,
+    /// for passes submitted outside of it
+    pub fn outside_graph(mut self) -> Self {
+        self.counts_frames = false;
+        self
+    }
+
+    /// Starts reading back the frames submitted since the last call and picks a readback buffer
+    /// for the frame about to be recorded. Completed readbacks are sent through the apc queue.
+    pub fn begin_frame(&mut self, device: &wgpu::Device, sender: &Sender<ApcCallback>) {
+        // the previous frames were submitted before this one started, so they can be mapped
+        for frame in self.unmapped_frames.drain(..) {
+            Self::map_frame(
+                frame,
+                self.timestamp_period,
+                self.counts_frames,
+                &self.returned_sender,
+                sender,
+            );
+        }
+        device.poll(wgpu::Maintain::Poll);
+
+        while let Ok(buffer) = self.returned_receiver.try_recv() {
+            self.free_buffers.push(buffer);
+        }
+        if self.current_buffer.is_none() {
+            self.current_buffer = self.free_buffers.pop();
+        }
+    }
+
+    /// The query set the passes of this frame write to, `None` if this frame goes untimed
+    pub fn get_query_set(&self) -> Option<&wgpu::QuerySet> {
+        self.current_buffer.as_ref().map(|_| &self.query_set)
+    }
+
+    /// Resolves the timestamps of the frame and copies them to its readback buffer.
+    /// `passes` holds the pass that wrote each pair of queries, `None` for pairs left unwritten.
+    pub fn end_frame(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        passes: Vec<Option<&'static str>>,
+    ) {
+        if passes.iter().all(Option::is_none) {
+            return;
+        }
+        let Some(buffer) = self.current_buffer.take() else {
+            return;
+        };
+
+        let query_count = passes.len() as u32 * 2;
+        let size = query_count as u64 * TIMESTAMP_SIZE;
+        encoder.resolve_query_set(&self.query_set, 0..query_count, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve_buffer, 0, &buffer, 0, size);
+
+        self.unmapped_frames.push(ProfiledFrame { buffer, passes });
+    }
+
+    fn map_frame(
+        frame: ProfiledFrame,
+        timestamp_period: f32,
+        counts_frames: bool,
+        returned_sender: &Sender<Arc<wgpu::Buffer>>,
+        sender: &Sender<ApcCallback>,
+    ) {
+        let returned_sender = returned_sender.clone();
+        let sender = sender.clone();
+        let size = frame.passes.len() as u64 * 2 * TIMESTAMP_SIZE;
+        let buffer = frame.buffer.clone();
+
+        buffer
+            .slice(0..size)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let mapped = result.is_ok();
+                let callback: ApcCallback = Box::new(move |world| {
+                    if mapped {
+                        let timings = Self::read_frame(&frame, size, timestamp_period);
+                        frame.buffer.unmap();
+                        if let Some(mut frame_timings) = world.get_resource_mut::<FrameTimings>() {
+                            if counts_frames {
+                                frame_timings.record_gpu_frame(&timings);
+                            } else {
+                                frame_timings.record_gpu_passes(&timings);
+                            }
+                        }
+                    }
+                    let _ = returned_sender.send(frame.buffer);
+                });
+                let _ = sender.send(callback);
+            });
+    }
+
+    /// The duration of every timed pass in milliseconds
+    fn read_frame(
+        frame: &ProfiledFrame,
+        size: u64,
+        timestamp_period: f32,
+    ) -> Vec<(&'static str, f32)> {
+        let timestamps: Vec<u64> = {
+            let data = frame.buffer.slice(0..size).get_mapped_range();
+            bytemuck::pod_collect_to_vec(&data)
+        };
+
+        frame
+            .passes
+            .iter()
+            .zip(timestamps.chunks_exact(2))
+            .filter_map(|(pass, pair)| {
+                let label = (*pass)?;
+                // a timestamp counter that wrapped or was reset gives no usable duration
+                let ticks = pair[1].checked_sub(pair[0])?;
+                Some((label, ticks as f32 * timestamp_period / 1_000_000.0))
+            })
+            .collect()
+    }
+}
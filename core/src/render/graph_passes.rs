@@ -0,0 +1,245 @@
+use bevy_ecs::{
+    system::{Query, Res, SystemState},
+    world::World,
+};
+
+use crate::{
+    ecs::components::gpu_bindings::camera_bindings::CameraBindings,
+    gpu_resources::pipelines::compute_task_registry::ComputeTaskRegistry,
+    traits::render_graph_traits::RenderGraphPass,
+};
+
+use super::{
+    nbody_sim_renderer::NBodySimRenderer,
+    render_graph::{GraphResource, RenderGraphContext},
+    unlit_diffuse_sub_renderer::UnlitDiffuseSubRenderer,
+};
+
+/// The depth texture shared by the scene passes
+pub const DEPTH_TEXTURE_NAME: &str = "Depth Texture";
+pub const DEPTH_TEXTURE: GraphResource = GraphResource::Texture(DEPTH_TEXTURE_NAME);
+/// The particle instances and draw arguments the compute tasks write for the particle pass.
+/// With double buffered instances the particle pass draws the ones written the frame before.
+pub const PARTICLE_INSTANCES: GraphResource = GraphResource::Buffer("Particle Instances");
+
+type CameraQuery = Query<'static, 'static, (&'static CameraBindings,)>;
+
+/// Begins a render pass drawing on top of the surface and depth texture
+fn begin_scene_pass<'a>(
+    context: &'a mut RenderGraphContext,
+    label: &str,
+) -> Result<wgpu::RenderPass<'a>, String> {
+    let view = context.get_texture_view(GraphResource::Surface)?;
+    let depth_view = context.get_texture_view(DEPTH_TEXTURE)?;
+    let timestamp_writes = context.render_timestamp_writes();
+
+    Ok(context
+        .encoder
+        .begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(label),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes,
+            occlusion_query_set: None,
+        }))
+}
+
+/// Runs every enabled task of the `ComputeTaskRegistry` in one compute pass
+pub struct ComputeTasksPass {
+    system_state: SystemState<Res<'static, ComputeTaskRegistry>>,
+}
+
+impl ComputeTasksPass {
+    pub const LABEL: &'static str = "Compute";
+
+    pub fn new(world: &mut World) -> Self {
+        Self {
+            system_state: SystemState::new(world),
+        }
+    }
+}
+
+impl RenderGraphPass for ComputeTasksPass {
+    fn label(&self) -> &'static str {
+        Self::LABEL
+    }
+
+    fn writes(&self) -> Vec<GraphResource> {
+        vec![PARTICLE_INSTANCES]
+    }
+
+    fn run(&mut self, world: &World, context: &mut RenderGraphContext) -> Result<(), String> {
+        let compute_task_registry = self.system_state.get(world).into_inner();
+        let timestamp_writes = context.compute_timestamp_writes();
+
+        let mut compute_pass = context
+            .encoder
+            .begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some(Self::LABEL),
+                timestamp_writes,
+            });
+        compute_task_registry.dispatch(world, &mut compute_pass);
+
+        Ok(())
+    }
+}
+
+/// Clears the surface and depth texture, every scene pass draws on top of it
+pub struct ClearPass {
+    pub color: wgpu::Color,
+}
+
+impl ClearPass {
+    pub const LABEL: &'static str = "Clear";
+
+    pub fn new(color: wgpu::Color) -> Self {
+        Self { color }
+    }
+}
+
+impl RenderGraphPass for ClearPass {
+    fn label(&self) -> &'static str {
+        Self::LABEL
+    }
+
+    fn writes(&self) -> Vec<GraphResource> {
+        vec![GraphResource::Surface, DEPTH_TEXTURE]
+    }
+
+    fn run(&mut self, _world: &World, context: &mut RenderGraphContext) -> Result<(), String> {
+        let view = context.get_texture_view(GraphResource::Surface)?;
+        let depth_view = context.get_texture_view(DEPTH_TEXTURE)?;
+        let timestamp_writes = context.render_timestamp_writes();
+
+        // the pass only clears, it ends as soon as it is dropped
+        let _render_pass = context
+            .encoder
+            .begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(Self::LABEL),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(self.color),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes,
+                occlusion_query_set: None,
+            });
+
+        Ok(())
+    }
+}
+
+/// Draws the simulated particles from the compute tasks' instances
+pub struct ParticlesPass {
+    system_state: SystemState<CameraQuery>,
+    nbody_sim_renderer: NBodySimRenderer,
+}
+
+impl ParticlesPass {
+    pub const LABEL: &'static str = "Particles";
+
+    pub fn new(world: &mut World) -> Self {
+        Self {
+            system_state: SystemState::new(world),
+            nbody_sim_renderer: NBodySimRenderer::new(world),
+        }
+    }
+}
+
+impl RenderGraphPass for ParticlesPass {
+    fn label(&self) -> &'static str {
+        Self::LABEL
+    }
+
+    fn reads(&self) -> Vec<GraphResource> {
+        vec![GraphResource::Surface, DEPTH_TEXTURE, PARTICLE_INSTANCES]
+    }
+
+    fn writes(&self) -> Vec<GraphResource> {
+        vec![GraphResource::Surface, DEPTH_TEXTURE]
+    }
+
+    fn run(&mut self, world: &World, context: &mut RenderGraphContext) -> Result<(), String> {
+        let camera_query = self.system_state.get(world);
+        // TODO: Support multiple cameras
+        let (main_camera,) = camera_query
+            .get_single()
+            .map_err(|_| "Expected exactly one camera".to_string())?;
+
+        let mut render_pass = begin_scene_pass(context, Self::LABEL)?;
+        render_pass.set_bind_group(0, &main_camera.bind_group, &[]);
+        self.nbody_sim_renderer.render(world, &mut render_pass);
+
+        Ok(())
+    }
+}
+
+/// Draws the entities with an unlit diffuse material
+pub struct UnlitDiffusePass {
+    system_state: SystemState<CameraQuery>,
+    unlit_diffuse_sub_renderer: UnlitDiffuseSubRenderer,
+}
+
+impl UnlitDiffusePass {
+    pub const LABEL: &'static str = "Unlit Diffuse";
+
+    pub fn new(world: &mut World) -> Self {
+        Self {
+            system_state: SystemState::new(world),
+            unlit_diffuse_sub_renderer: UnlitDiffuseSubRenderer::new(world),
+        }
+    }
+}
+
+impl RenderGraphPass for UnlitDiffusePass {
+    fn label(&self) -> &'static str {
+        Self::LABEL
+    }
+
+    fn reads(&self) -> Vec<GraphResource> {
+        vec![GraphResource::Surface, DEPTH_TEXTURE]
+    }
+
+    fn writes(&self) -> Vec<GraphResource> {
+        vec![GraphResource::Surface, DEPTH_TEXTURE]
+    }
+
+    fn run(&mut self, world: &World, context: &mut RenderGraphContext) -> Result<(), String> {
+        let camera_query = self.system_state.get(world);
+        let (main_camera,) = camera_query
+            .get_single()
+            .map_err(|_| "Expected exactly one camera".to_string())?;
+
+        let mut render_pass = begin_scene_pass(context, Self::LABEL)?;
+        render_pass.set_bind_group(0, &main_camera.bind_group, &[]);
+        self.unlit_diffuse_sub_renderer
+            .render(world, &mut render_pass);
+
+        Ok(())
+    }
+}
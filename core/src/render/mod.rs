@@ -1,4 +1,6 @@
-pub mod nbody_sim_dispatch;
+pub mod gpu_profiler;
+pub mod graph_passes;
 pub mod nbody_sim_renderer;
+pub mod render_graph;
 pub mod root_renderer;
 mod unlit_diffuse_sub_renderer;
@@ -2,12 +2,19 @@ use bevy_ecs::system::{Res, SystemState};
 
 use crate::{
     ecs::resources::nbody_sim_resources::NBodySimResources,
-    gpu_resources::pipelines::render_particles_pipeline::RenderParticlesPipeline,
+    gpu_resources::{
+        pipelines::{
+            render_particles_pipeline::RenderParticlesPipeline,
+            render_sprites_pipeline::{ParticleRenderMode, RenderSpritesPipeline},
+        },
+        types::gpu_indirect_args::GpuIndirectArgs,
+    },
 };
 
 type NBodySimRendererSystemState = SystemState<(
     Res<'static, NBodySimResources>,
     Res<'static, RenderParticlesPipeline>,
+    Res<'static, RenderSpritesPipeline>,
 )>;
 
 pub struct NBodySimRenderer {
@@ -28,22 +35,72 @@ impl NBodySimRenderer {
     ) where
         'w: 'a,
     {
-        let (nbody_sim_resources, render_particles_pipeline) = self.system_state.get(world);
-        let (nbody_sim_resources, render_particles_pipeline) = (
+        let (nbody_sim_resources, render_particles_pipeline, render_sprites_pipeline) =
+            self.system_state.get(world);
+        let (nbody_sim_resources, render_particles_pipeline, render_sprites_pipeline) = (
             nbody_sim_resources.into_inner(),
             render_particles_pipeline.into_inner(),
+            render_sprites_pipeline.into_inner(),
         );
 
-        render_pass.set_pipeline(&render_particles_pipeline.render_pipeline);
-        render_pass.set_bind_group(1, nbody_sim_resources.get_material_bind_group(), &[]);
+        if nbody_sim_resources.get_render_mode() == ParticleRenderMode::Sprite {
+            Self::render_sprites(nbody_sim_resources, render_sprites_pipeline, render_pass);
+            return;
+        }
+
+        let instance_format = nbody_sim_resources.get_instance_format();
+        render_pass.set_bind_group(2, nbody_sim_resources.get_attribute_bind_group(), &[]);
+
+        // one indirect draw per species and level of detail, each with its own mesh, material and
+        // range of instances
+        for (index, species) in nbody_sim_resources.get_species().iter().enumerate() {
+            render_pass.set_bind_group(1, &species.material.bind_group, &[]);
 
-        render_pass.set_vertex_buffer(0, nbody_sim_resources.get_vertex_buffer().slice());
-        render_pass.set_vertex_buffer(1, nbody_sim_resources.get_instance_buffer().slice());
+            for lod in 0..nbody_sim_resources.get_lod_count() {
+                let (mesh_filter, billboard) = species.get_lod_mesh(lod);
+                let mesh = &mesh_filter.filter;
+                let list = nbody_sim_resources.get_instance_list(index, lod);
+
+                render_pass.set_pipeline(
+                    render_particles_pipeline.get_pipeline(instance_format, billboard),
+                );
+                render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice());
+                render_pass.set_vertex_buffer(1, nbody_sim_resources.get_instance_list_slice(list));
+                render_pass.set_index_buffer(mesh.index_buffer.slice(), mesh.index_format);
+
+                let indirect_offset = (list * std::mem::size_of::<GpuIndirectArgs>()) as u64;
+                render_pass.draw_indexed_indirect(
+                    &nbody_sim_resources.get_indirect_buffer().buffer,
+                    indirect_offset,
+                );
+            }
+        }
+    }
+
+    /// Draws every instance list as sprites, the quad's indices stand in for a mesh
+    fn render_sprites<'a>(
+        nbody_sim_resources: &'a NBodySimResources,
+        render_sprites_pipeline: &'a RenderSpritesPipeline,
+        render_pass: &mut wgpu::RenderPass<'a>,
+    ) {
+        let instance_format = nbody_sim_resources.get_instance_format();
+        render_pass.set_pipeline(render_sprites_pipeline.get_pipeline(instance_format));
+        render_pass.set_bind_group(1, nbody_sim_resources.get_sprite_bind_group(), &[]);
         render_pass.set_index_buffer(
-            nbody_sim_resources.get_index_buffer().slice(),
+            render_sprites_pipeline.index_buffer.slice(),
             wgpu::IndexFormat::Uint32,
         );
 
-        render_pass.draw_indexed_indirect(&nbody_sim_resources.get_indirect_buffer().buffer, 0);
+        let list_count =
+            nbody_sim_resources.get_species().len() * nbody_sim_resources.get_lod_count();
+        for list in 0..list_count {
+            render_pass.set_vertex_buffer(0, nbody_sim_resources.get_instance_list_slice(list));
+
+            let indirect_offset = (list * std::mem::size_of::<GpuIndirectArgs>()) as u64;
+            render_pass.draw_indexed_indirect(
+                &nbody_sim_resources.get_indirect_buffer().buffer,
+                indirect_offset,
+            );
+        }
     }
 }
@@ -0,0 +1,506 @@
+use std::collections::HashMap;
+
+use bevy_ecs::world::World;
+use crossbeam::channel::Sender;
+use log::warn;
+
+use crate::{
+    gpu_resources::render_resources::RenderResources,
+    traits::{apc_traits::ApcCallback, render_graph_traits::RenderGraphPass},
+    utils::texture::{Texture, TextureBuilder},
+};
+
+use super::gpu_profiler::{GpuProfiler, MAX_PROFILED_PASSES};
+
+/// A resource passes of the render graph read or write
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GraphResource {
+    /// The texture view the frame is rendered into
+    Surface,
+    /// A transient texture owned by the graph, sized to the surface
+    Texture(&'static str),
+    /// A buffer owned by the world, only used to order the passes
+    Buffer(&'static str),
+}
+
+/// A texture the graph creates and recreates whenever the surface is resized
+#[derive(Debug, Clone, Copy)]
+pub struct TransientTextureDescriptor {
+    pub format: wgpu::TextureFormat,
+    pub depth: bool,
+}
+
+impl TransientTextureDescriptor {
+    pub fn color(format: wgpu::TextureFormat) -> Self {
+        Self {
+            format,
+            depth: false,
+        }
+    }
+
+    pub fn depth() -> Self {
+        Self {
+            format: wgpu::TextureFormat::Depth32Float,
+            depth: true,
+        }
+    }
+}
+
+/// What a pass gets to record itself with
+pub struct RenderGraphContext<'a> {
+    pub encoder: &'a mut wgpu::CommandEncoder,
+    surface_view: &'a wgpu::TextureView,
+    textures: &'a HashMap<&'static str, Texture>,
+    // the query set and pair of queries the running pass is timed with, when profiling
+    timestamps: Option<(&'a wgpu::QuerySet, u32)>,
+    timestamps_written: bool,
+}
+
+impl<'a> RenderGraphContext<'a> {
+    /// The timestamp writes for the compute pass the running pass begins, `None` when the frame
+    /// isn't profiled. Only one wgpu pass per graph pass can be timed.
+    pub fn compute_timestamp_writes(&mut self) -> Option<wgpu::ComputePassTimestampWrites<'a>> {
+        let (query_set, index) = self.take_timestamps()?;
+        Some(wgpu::ComputePassTimestampWrites {
+            query_set,
+            beginning_of_pass_write_index: Some(index),
+            end_of_pass_write_index: Some(index + 1),
+        })
+    }
+
+    /// The timestamp writes for the render pass the running pass begins, `None` when the frame
+    /// isn't profiled. Only one wgpu pass per graph pass can be timed.
+    pub fn render_timestamp_writes(&mut self) -> Option<wgpu::RenderPassTimestampWrites<'a>> {
+        let (query_set, index) = self.take_timestamps()?;
+        Some(wgpu::RenderPassTimestampWrites {
+            query_set,
+            beginning_of_pass_write_index: Some(index),
+            end_of_pass_write_index: Some(index + 1),
+        })
+    }
+
+    fn take_timestamps(&mut self) -> Option<(&'a wgpu::QuerySet, u32)> {
+        if self.timestamps_written {
+            return None;
+        }
+        self.timestamps_written = true;
+        self.timestamps
+    }
+
+    /// The view of a texture resource, the surface or a transient texture
+    pub fn get_texture_view(
+        &self,
+        resource: GraphResource,
+    ) -> Result<&'a wgpu::TextureView, String> {
+        match resource {
+            GraphResource::Surface => Ok(self.surface_view),
+            GraphResource::Texture(name) => self
+                .textures
+                .get(name)
+                .map(|texture| &texture.view)
+                .ok_or(format!("No transient texture named {}", name)),
+            GraphResource::Buffer(name) => Err(format!("{} is a buffer, not a texture", name)),
+        }
+    }
+}
+
+struct RenderGraphNode {
+    pass: Box<dyn RenderGraphPass>,
+    enabled: bool,
+    reads: Vec<GraphResource>,
+    writes: Vec<GraphResource>,
+}
+
+/// The passes rendering a frame, run in the order their resource reads and writes imply.
+/// Passes that don't depend on each other run in the order they were added.
+pub struct RenderGraph {
+    nodes: Vec<RenderGraphNode>,
+    // indices into nodes, in the order they run
+    order: Vec<usize>,
+
+    transient_descriptors: HashMap<&'static str, TransientTextureDescriptor>,
+    transient_textures: HashMap<&'static str, Texture>,
+    width: u32,
+    height: u32,
+
+    profiler: Option<GpuProfiler>,
+}
+
+impl RenderGraph {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            nodes: Vec::new(),
+            order: Vec::new(),
+            transient_descriptors: HashMap::new(),
+            transient_textures: HashMap::new(),
+            width,
+            height,
+            profiler: None,
+        }
+    }
+
+    /// Times every pass with timestamp queries, `None` stops profiling
+    pub fn set_profiler(&mut self, profiler: Option<GpuProfiler>) {
+        self.profiler = profiler;
+    }
+
+    pub fn is_profiling(&self) -> bool {
+        self.profiler.is_some()
+    }
+
+    /// Declares a texture the graph owns, passes refer to it as `GraphResource::Texture(name)`
+    pub fn add_transient_texture(
+        &mut self,
+        device: &wgpu::Device,
+        name: &'static str,
+        descriptor: TransientTextureDescriptor,
+    ) -> Result<(), String> {
+        if self.transient_descriptors.contains_key(name) {
+            return Err(format!("A transient texture named {} already exists", name));
+        }
+
+        let texture =
+            Self::create_transient_texture(device, name, descriptor, self.width, self.height)?;
+        self.transient_descriptors.insert(name, descriptor);
+        self.transient_textures.insert(name, texture);
+        Ok(())
+    }
+
+    /// Adds a pass and works out where it runs.
+    /// Fails if the label is taken, it uses an undeclared texture or its resources form a cycle.
+    pub fn add_pass(
+        &mut self,
+        pass: impl RenderGraphPass + 'static,
+        enabled: bool,
+    ) -> Result<(), String> {
+        let label = pass.label();
+        if self.contains(label) {
+            return Err(format!("A render pass named {} already exists", label));
+        }
+
+        let reads = pass.reads();
+        let writes = pass.writes();
+        for resource in reads.iter().chain(&writes) {
+            match resource {
+                GraphResource::Texture(name) if !self.transient_descriptors.contains_key(name) => {
+                    return Err(format!(
+                        "Render pass {} uses the undeclared texture {}",
+                        label, name
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        self.nodes.push(RenderGraphNode {
+            pass: Box::new(pass),
+            enabled,
+            reads,
+            writes,
+        });
+
+        match Self::sort_nodes(&self.nodes) {
+            Ok(order) => {
+                self.order = order;
+                Ok(())
+            }
+            Err(error) => {
+                self.nodes.pop();
+                Err(error)
+            }
+        }
+    }
+
+    /// Removes the pass with the given label, returns whether it existed
+    pub fn remove_pass(&mut self, label: &str) -> bool {
+        let count = self.nodes.len();
+        self.nodes.retain(|node| node.pass.label() != label);
+        if self.nodes.len() == count {
+            return false;
+        }
+
+        // removing a pass only drops constraints, so the rest still sorts
+        self.order = Self::sort_nodes(&self.nodes).unwrap();
+        true
+    }
+
+    pub fn contains(&self, label: &str) -> bool {
+        self.nodes.iter().any(|node| node.pass.label() == label)
+    }
+
+    /// Enables or disables a pass, a disabled pass keeps its place in the order
+    pub fn set_enabled(&mut self, label: &str, enabled: bool) -> Result<(), String> {
+        let node = self
+            .nodes
+            .iter_mut()
+            .find(|node| node.pass.label() == label)
+            .ok_or(format!("No render pass named {}", label))?;
+        node.enabled = enabled;
+        Ok(())
+    }
+
+    pub fn is_enabled(&self, label: &str) -> bool {
+        self.nodes
+            .iter()
+            .any(|node| node.pass.label() == label && node.enabled)
+    }
+
+    /// The labels of every pass in the order they run
+    pub fn get_order(&self) -> Vec<&'static str> {
+        self.order
+            .iter()
+            .map(|&index| self.nodes[index].pass.label())
+            .collect()
+    }
+
+    /// Recreates the transient textures at the new surface size
+    pub fn set_size(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+
+        for (&name, &descriptor) in &self.transient_descriptors {
+            let texture = Self::create_transient_texture(device, name, descriptor, width, height)
+                .expect("Failed to create transient texture");
+            self.transient_textures.insert(name, texture);
+        }
+    }
+
+    /// Records every enabled pass into the encoder.
+    /// A failing pass is logged and skipped, the rest of the frame still renders.
+    /// When profiling, the timings of earlier frames are sent through `sender` as they arrive.
+    pub fn execute(
+        &mut self,
+        world: &World,
+        encoder: &mut wgpu::CommandEncoder,
+        surface_view: &wgpu::TextureView,
+        sender: &Sender<ApcCallback>,
+    ) {
+        if let Some(profiler) = &mut self.profiler {
+            let device = &world.resource::<RenderResources>().device;
+            profiler.begin_frame(device, sender);
+        }
+
+        let query_set = self.profiler.as_ref().and_then(GpuProfiler::get_query_set);
+        // the pass that wrote each pair of queries
+        let mut timed_passes: Vec<Option<&'static str>> = Vec::new();
+
+        let mut context = RenderGraphContext {
+            encoder: &mut *encoder,
+            surface_view,
+            textures: &self.transient_textures,
+            timestamps: None,
+            timestamps_written: false,
+        };
+
+        for &index in &self.order {
+            let node = &mut self.nodes[index];
+            if !node.enabled {
+                continue;
+            }
+
+            let query_pair = timed_passes.len() as u32;
+            context.timestamps = query_set
+                .filter(|_| query_pair < MAX_PROFILED_PASSES)
+                .map(|query_set| (query_set, query_pair * 2));
+            context.timestamps_written = false;
+
+            if let Err(error) = node.pass.run(world, &mut context) {
+                warn!("Render pass {} failed: {}", node.pass.label(), error);
+            }
+
+            if context.timestamps.is_some() {
+                timed_passes.push(context.timestamps_written.then_some(node.pass.label()));
+            }
+        }
+
+        if let Some(profiler) = &mut self.profiler {
+            profiler.end_frame(encoder, timed_passes);
+        }
+    }
+
+    fn create_transient_texture(
+        device: &wgpu::Device,
+        name: &str,
+        descriptor: TransientTextureDescriptor,
+        width: u32,
+        height: u32,
+    ) -> Result<Texture, String> {
+        let builder = TextureBuilder::new(device)
+            .size(width.max(1), height.max(1))
+            .label(name);
+
+        if descriptor.depth {
+            builder.depth_texture().format(descriptor.format).build()
+        } else {
+            builder.render_target(1).format(descriptor.format).build()
+        }
+    }
+
+    /// Orders the nodes so that for every resource its pure writers run first, then the passes
+    /// that read and write it, then the passes that only read it
+    fn sort_nodes(nodes: &[RenderGraphNode]) -> Result<Vec<usize>, String> {
+        let mut edges: Vec<Vec<usize>> = vec![Vec::new(); nodes.len()];
+
+        let mut resources: Vec<GraphResource> = Vec::new();
+        for node in nodes {
+            for resource in node.reads.iter().chain(&node.writes) {
+                if !resources.contains(resource) {
+                    resources.push(*resource);
+                }
+            }
+        }
+
+        for resource in &resources {
+            let mut producers = Vec::new();
+            let mut modifiers = Vec::new();
+            let mut consumers = Vec::new();
+            for (index, node) in nodes.iter().enumerate() {
+                match (
+                    node.reads.contains(resource),
+                    node.writes.contains(resource),
+                ) {
+                    (false, true) => producers.push(index),
+                    (true, true) => modifiers.push(index),
+                    (true, false) => consumers.push(index),
+                    (false, false) => {}
+                }
+            }
+
+            // writers of the same resource keep the order they were added in
+            let writers: Vec<usize> = producers.into_iter().chain(modifiers).collect();
+            for pair in writers.windows(2) {
+                edges[pair[0]].push(pair[1]);
+            }
+            if let Some(&last_writer) = writers.last() {
+                for &consumer in &consumers {
+                    edges[last_writer].push(consumer);
+                }
+            }
+        }
+
+        let mut in_degree = vec![0; nodes.len()];
+        for targets in &edges {
+            for &target in targets {
+                in_degree[target] += 1;
+            }
+        }
+
+        // always take the earliest added ready pass so independent passes keep their order
+        let mut order = Vec::with_capacity(nodes.len());
+        let mut done = vec![false; nodes.len()];
+        while let Some(next) = (0..nodes.len()).find(|&index| !done[index] && in_degree[index] == 0)
+        {
+            done[next] = true;
+            order.push(next);
+            for &target in &edges[next] {
+                in_degree[target] -= 1;
+            }
+        }
+
+        if order.len() != nodes.len() {
+            let cycle: Vec<&str> = (0..nodes.len())
+                .filter(|&index| !done[index])
+                .map(|index| nodes[index].pass.label())
+                .collect();
+            return Err(format!(
+                "The render passes {} depend on each other",
+                cycle.join(", ")
+            ));
+        }
+
+        Ok(order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestPass(&'static str);
+
+    impl RenderGraphPass for TestPass {
+        fn label(&self) -> &'static str {
+            self.0
+        }
+
+        fn run(&mut self, _world: &World, _context: &mut RenderGraphContext) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    fn node(
+        label: &'static str,
+        reads: &[GraphResource],
+        writes: &[GraphResource],
+    ) -> RenderGraphNode {
+        RenderGraphNode {
+            pass: Box::new(TestPass(label)),
+            enabled: true,
+            reads: reads.to_vec(),
+            writes: writes.to_vec(),
+        }
+    }
+
+    fn sorted_labels(nodes: &[RenderGraphNode]) -> Result<Vec<&'static str>, String> {
+        let order = RenderGraph::sort_nodes(nodes)?;
+        Ok(order
+            .iter()
+            .map(|&index| nodes[index].pass.label())
+            .collect())
+    }
+
+    const COLOR: GraphResource = GraphResource::Texture("color");
+    const DEPTH: GraphResource = GraphResource::Texture("depth");
+
+    #[test]
+    fn writers_run_before_modifiers_before_readers() {
+        let nodes = [
+            node("present", &[COLOR], &[GraphResource::Surface]),
+            node("overlay", &[COLOR], &[COLOR]),
+            node("clear", &[], &[COLOR]),
+        ];
+
+        assert_eq!(
+            sorted_labels(&nodes).unwrap(),
+            ["clear", "overlay", "present"]
+        );
+    }
+
+    #[test]
+    fn modifiers_keep_the_order_they_were_added_in() {
+        let nodes = [
+            node("text", &[COLOR], &[COLOR]),
+            node("particles", &[COLOR, DEPTH], &[COLOR, DEPTH]),
+            node("clear", &[], &[COLOR, DEPTH]),
+        ];
+
+        assert_eq!(
+            sorted_labels(&nodes).unwrap(),
+            ["clear", "text", "particles"]
+        );
+    }
+
+    #[test]
+    fn independent_passes_keep_the_order_they_were_added_in() {
+        let nodes = [
+            node("b", &[], &[GraphResource::Buffer("b")]),
+            node("a", &[], &[GraphResource::Buffer("a")]),
+            node("c", &[], &[]),
+        ];
+
+        assert_eq!(sorted_labels(&nodes).unwrap(), ["b", "a", "c"]);
+    }
+
+    #[test]
+    fn passes_reading_each_others_output_are_a_cycle() {
+        let nodes = [
+            node("free", &[], &[]),
+            node("first", &[DEPTH], &[COLOR]),
+            node("second", &[COLOR], &[DEPTH]),
+        ];
+
+        let error = sorted_labels(&nodes).unwrap_err();
+        assert!(error.contains("first, second"), "{error}");
+        assert!(!error.contains("free"), "{error}");
+    }
+}
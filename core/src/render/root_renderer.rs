@@ -1,133 +1,87 @@
-use bevy_ecs::{
-    system::{Query, Res, SystemState},
-    world::World,
-};
+use bevy_ecs::world::World;
 
 use wgpu::{CommandBuffer, TextureView};
 
 use crate::{
-    ecs::components::gpu_bindings::camera_bindings::CameraBindings,
-    gpu_resources::render_resources::RenderResources,
-    utils::texture::{Texture, TextureBuilder},
+    ecs::resources::apc_resources::ApcQueue, gpu_resources::render_resources::RenderResources,
 };
 
 use super::{
-    nbody_sim_dispatch::NBodySimDispatcher, nbody_sim_renderer::NBodySimRenderer,
-    unlit_diffuse_sub_renderer::UnlitDiffuseSubRenderer,
+    gpu_profiler::GpuProfiler,
+    graph_passes::{
+        ClearPass, ComputeTasksPass, DEPTH_TEXTURE_NAME, ParticlesPass, UnlitDiffusePass,
+    },
+    render_graph::{RenderGraph, TransientTextureDescriptor},
 };
 
-type RootRendererSystemState = SystemState<(
-    Res<'static, RenderResources>,
-    Query<'static, 'static, (&'static CameraBindings,)>,
-)>;
-
 pub struct RootRenderer {
-    system_state: RootRendererSystemState,
-
-    nbody_sim_dispatcher: NBodySimDispatcher,
-    unlit_diffuse_sub_renderer: UnlitDiffuseSubRenderer,
-    nbody_sim_renderer: NBodySimRenderer,
-
-    depth_texture: Texture,
+    render_graph: RenderGraph,
 }
 
 impl std::fmt::Debug for RootRenderer {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("RootRenderer").finish()
+        f.debug_struct("RootRenderer")
+            .field("passes", &self.render_graph.get_order())
+            .finish()
     }
 }
 
 impl RootRenderer {
     pub fn new(world: &mut World, width: u32, height: u32) -> Self {
-        let unlit_diffuse_sub_renderer = UnlitDiffuseSubRenderer::new(world);
-        let system_state: RootRendererSystemState = SystemState::new(world);
+        let mut render_graph = RenderGraph::new(width, height);
 
-        let nbody_sim_dispatcher = NBodySimDispatcher::new(world);
-        let nbody_sim_renderer = NBodySimRenderer::new(world);
+        let compute_tasks_pass = ComputeTasksPass::new(world);
+        let unlit_diffuse_pass = UnlitDiffusePass::new(world);
+        let particles_pass = ParticlesPass::new(world);
 
         let render_resources = world.get_resource::<RenderResources>().unwrap();
         let device = &render_resources.device;
 
-        let mut renderer = Self {
-            system_state,
-            nbody_sim_dispatcher,
-            unlit_diffuse_sub_renderer,
-            nbody_sim_renderer,
-            depth_texture: TextureBuilder::new(device)
-                .size(width, height)
-                .depth_texture()
-                .label("Depth Texture")
-                .build()
-                .expect("Failed to create depth texture"),
-        };
-
-        renderer.set_size(device, width, height);
-        renderer
+        render_graph
+            .add_transient_texture(
+                device,
+                DEPTH_TEXTURE_NAME,
+                TransientTextureDescriptor::depth(),
+            )
+            .expect("Failed to create depth texture");
+
+        render_graph.add_pass(compute_tasks_pass, true).unwrap();
+        render_graph
+            .add_pass(ClearPass::new(wgpu::Color::BLACK), true)
+            .unwrap();
+        // no entities use the unlit diffuse material yet
+        render_graph.add_pass(unlit_diffuse_pass, false).unwrap();
+        render_graph.add_pass(particles_pass, true).unwrap();
+
+        // every pass is timed when the device supports timestamp queries
+        render_graph.set_profiler(GpuProfiler::new(device, &render_resources.queue));
+
+        Self { render_graph }
     }
 
     pub fn set_size(&mut self, device: &wgpu::Device, width: u32, height: u32) {
-        self.depth_texture = TextureBuilder::new(device)
-            .size(width, height)
-            .depth_texture()
-            .label("Depth Texture")
-            .build()
-            .expect("Failed to create depth texture");
+        self.render_graph.set_size(device, width, height);
     }
 
-    pub fn render(&mut self, world: &World, output_view: &TextureView) -> CommandBuffer {
-        let (render_resources, camera_query) = self.system_state.get(world);
-        let device = &render_resources.device;
+    pub fn get_render_graph(&self) -> &RenderGraph {
+        &self.render_graph
+    }
 
-        // TODO: Support multiple cameras
-        let main_camera = camera_query.single().0;
+    /// Passes can be added, removed or toggled through the graph
+    pub fn get_render_graph_mut(&mut self) -> &mut RenderGraph {
+        &mut self.render_graph
+    }
+
+    pub fn render(&mut self, world: &World, output_view: &TextureView) -> CommandBuffer {
+        let device = &world.resource::<RenderResources>().device;
 
-        // set up command encoder for render pass
         let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
             label: Some("Render Encoder"),
         });
 
-        // compute passes
-        {
-            let pass_descriptor = wgpu::ComputePassDescriptor {
-                label: Some("NBodySim Compute Pass"),
-                timestamp_writes: None,
-            };
-
-            let mut compute_pass = encoder.begin_compute_pass(&pass_descriptor);
-            self.nbody_sim_dispatcher.dispatch(world, &mut compute_pass);
-        }
-
-        // render passes
-        {
-            let pass_descriptor = wgpu::RenderPassDescriptor {
-                label: Some("Background Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: output_view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &self.depth_texture.view,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: wgpu::StoreOp::Store,
-                    }),
-                    stencil_ops: None,
-                }),
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            };
-            let mut render_pass = encoder.begin_render_pass(&pass_descriptor);
-            render_pass.set_bind_group(0, &main_camera.bind_group, &[]);
-
-            // self.unlit_diffuse_sub_renderer
-            //     .render(world, &mut render_pass);
-
-            self.nbody_sim_renderer.render(world, &mut render_pass);
-        }
+        let sender = &world.resource::<ApcQueue>().sender;
+        self.render_graph
+            .execute(world, &mut encoder, output_view, sender);
 
         encoder.finish()
     }
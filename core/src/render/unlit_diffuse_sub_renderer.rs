@@ -4,15 +4,19 @@ use bevy_ecs::{
 };
 
 use crate::{
-    ecs::components::{
-        gpu_bindings::model_bindings::ModelBindings,
-        materials::unlit_diffuse_material::UnlitDiffuseMaterial, mesh_filter::BasicMeshFilter,
+    ecs::{
+        components::{
+            gpu_bindings::model_bindings::ModelBindings,
+            materials::unlit_diffuse_material::UnlitDiffuseMaterial, mesh_filter::BasicMeshFilter,
+        },
+        resources::model_uniforms::ModelUniforms,
     },
     gpu_resources::pipelines::unlit_diffuse_pipeline::UnlitDiffusePipeline,
 };
 
 type UnlitDiffuseSubRendererSystemState = SystemState<(
     Res<'static, UnlitDiffusePipeline>,
+    Res<'static, ModelUniforms>,
     Query<
         'static,
         'static,
@@ -39,11 +43,12 @@ impl UnlitDiffuseSubRenderer {
     where
         'w: 'a,
     {
-        let (pipeline, model_query) = self.system_state.get(world);
+        let (pipeline, model_uniforms, model_query) = self.system_state.get(world);
 
         render_pass.set_pipeline(&pipeline.into_inner().render_pipeline);
+        let model_bind_group = model_uniforms.into_inner().get_bind_group();
         for (model_binding, mesh_filter, material) in model_query.iter_inner() {
-            render_pass.set_bind_group(1, &model_binding.bind_group, &[]);
+            render_pass.set_bind_group(1, model_bind_group, &[model_binding.get_offset()]);
             render_pass.set_bind_group(2, &material.bind_group, &[]);
 
             mesh_filter.filter.draw(render_pass);
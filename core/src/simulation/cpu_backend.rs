@@ -0,0 +1,386 @@
+use bytemuck::Zeroable;
+use glam::{Vec3, Vec4};
+
+use crate::{
+    ecs::resources::n_body_kernel_config::{
+        ForceLaw, Integrator, NBodyKernelConfig, SofteningKernel,
+    },
+    gpu_resources::types::{
+        gpu_cull_params::GpuCullParams, gpu_culling_counts::GpuCullingCounts,
+        gpu_particle_instance::GpuParticleInstance, gpu_sim_params::GpuSimParams,
+        gpu_species::GpuSpecies,
+    },
+    simulation::{GpuParticle, diagnostics::SimulationDiagnostics},
+    traits::simulation_traits::SimulationBackend,
+    utils::parallel,
+};
+
+/// Matches `COLOR_RULE_SOLID` in the compute shader
+const COLOR_RULE_SOLID: u32 = 1;
+
+/// Runs the n-body step on the cpu, split over every available thread, or on the calling one on
+/// the web.
+/// Steps like the compute kernel's direct variant with the same integrator, softening kernel and
+/// force law, so both backends produce the same state up to float rounding.
+pub struct CpuSimulationBackend {
+    particles: Vec<GpuParticle>,
+    // the state being written during a step, swapped with `particles` afterwards
+    scratch: Vec<GpuParticle>,
+    gravitational_constant: f32,
+    softening: f32,
+    // the softening of each particle's species, `softening` for the particles past its end
+    particle_softening: Vec<f32>,
+    kernel_config: NBodyKernelConfig,
+}
+
+impl CpuSimulationBackend {
+    pub fn new(gravitational_constant: f32, softening: f32) -> Self {
+        Self {
+            particles: Vec::new(),
+            scratch: Vec::new(),
+            gravitational_constant,
+            softening,
+            particle_softening: Vec::new(),
+            kernel_config: NBodyKernelConfig::default(),
+        }
+    }
+
+    /// Uses the same constants as the gpu simulation
+    pub fn from_sim_params(sim_params: &GpuSimParams) -> Self {
+        Self::new(sim_params.gravitational_constant, sim_params.softening)
+    }
+
+    pub fn get_particles(&self) -> &[GpuParticle] {
+        &self.particles
+    }
+
+    /// Steps with the integrator, softening kernel and force law of the config,
+    /// the tiling and workgroup size only matter to the gpu
+    pub fn set_kernel_config(&mut self, kernel_config: NBodyKernelConfig) {
+        self.kernel_config = kernel_config;
+    }
+
+    pub fn get_kernel_config(&self) -> &NBodyKernelConfig {
+        &self.kernel_config
+    }
+
+    /// The softening of each particle, in the order they were uploaded, as their species override it
+    pub fn set_particle_softening(&mut self, particle_softening: Vec<f32>) {
+        self.particle_softening = particle_softening;
+    }
+
+    /// Builds the render instances of the current state the same way the compute shader does,
+    /// one list per level of detail. Also returns how many particles were culled by range and by
+    /// the frustum.
+    pub fn build_instances(
+        &self,
+        species: &GpuSpecies,
+        sim_params: &GpuSimParams,
+        cull_params: &GpuCullParams,
+    ) -> (Vec<Vec<GpuParticleInstance>>, GpuCullingCounts) {
+        let mut culled = GpuCullingCounts::zeroed();
+        let mut lod_instances = vec![Vec::new(); cull_params.lod_count.max(1) as usize];
+
+        for (index, particle) in self.particles.iter().enumerate() {
+            let position = particle.position.truncate();
+            let distance = position.length();
+            let in_range =
+                distance >= sim_params.min_distance && distance <= sim_params.max_distance;
+            if cull_params.range_enabled != 0 && !in_range {
+                culled.culled_by_range += 1;
+                continue;
+            }
+
+            let size = species.size_base + particle.position.w * species.size_scale;
+            let radius = cull_params.bounding_radius * size;
+            if cull_params.frustum_enabled != 0 && !cull_params.in_frustum(position, radius) {
+                culled.culled_by_frustum += 1;
+                continue;
+            }
+
+            let mut instance = GpuParticleInstance::zeroed();
+            instance.position = position.extend(size);
+            instance.color = if species.color_rule == COLOR_RULE_SOLID {
+                species.color
+            } else {
+                let speed = particle.velocity.length();
+                Vec4::new(
+                    (speed / 20.0).min(1.0),
+                    (0.2 + 0.8 / speed).min(1.0),
+                    (0.5 / speed).min(1.0),
+                    1.0,
+                )
+            };
+            instance.velocity = particle.velocity;
+            instance.id = particle.id;
+            instance.index = index as u32;
+            lod_instances[cull_params.lod_of(position, radius) as usize].push(instance);
+        }
+
+        (lod_instances, culled)
+    }
+
+    fn step_once(&mut self, delta_time: f32) {
+        self.scratch
+            .resize(self.particles.len(), GpuParticle::zeroed());
+
+        let step = Step {
+            particles: &self.particles,
+            particle_softening: &self.particle_softening,
+            gravitational_constant: self.gravitational_constant,
+            softening: self.softening,
+            kernel_config: self.kernel_config,
+            delta_time,
+        };
+        parallel::for_each_chunk_mut(&mut self.scratch, |first, chunk| {
+            step.run_chunk(first, chunk)
+        });
+
+        std::mem::swap(&mut self.particles, &mut self.scratch);
+    }
+}
+
+/// One step of every particle, shared by the threads stepping a chunk each
+struct Step<'a> {
+    particles: &'a [GpuParticle],
+    particle_softening: &'a [f32],
+    gravitational_constant: f32,
+    softening: f32,
+    kernel_config: NBodyKernelConfig,
+    delta_time: f32,
+}
+
+impl Step<'_> {
+    /// Writes the stepped state of the particles from `first` on into `chunk`, like `cs_main`
+    fn run_chunk(&self, first: usize, chunk: &mut [GpuParticle]) {
+        let delta_time = self.delta_time;
+        // the leapfrog evaluates the forces half a step ahead
+        let drift = match self.kernel_config.integrator {
+            Integrator::Leapfrog => 0.5 * delta_time,
+            _ => 0.0,
+        };
+
+        for (offset, new_particle) in chunk.iter_mut().enumerate() {
+            let index = first + offset;
+            let current = &self.particles[index];
+            let softening = self
+                .particle_softening
+                .get(index)
+                .copied()
+                .unwrap_or(self.softening);
+            let position = current.position.truncate() + current.velocity * drift;
+
+            let mut acceleration = Vec3::ZERO;
+            for (other_index, other) in self.particles.iter().enumerate() {
+                if other_index == index {
+                    continue;
+                }
+
+                let body =
+                    (other.position.truncate() + other.velocity * drift).extend(other.position.w);
+                acceleration += pair_acceleration(
+                    position,
+                    body,
+                    softening,
+                    self.gravitational_constant,
+                    &self.kernel_config,
+                );
+            }
+
+            let velocity = current.velocity + acceleration * delta_time;
+            let new_position = match self.kernel_config.integrator {
+                Integrator::Euler => current.position.truncate() + current.velocity * delta_time,
+                Integrator::SemiImplicitEuler => {
+                    current.position.truncate() + velocity * delta_time
+                }
+                Integrator::Leapfrog => position + velocity * drift,
+            };
+
+            *new_particle = GpuParticle {
+                position: new_position.extend(current.position.w),
+                velocity,
+                id: current.id,
+            };
+        }
+    }
+}
+
+/// Acceleration of a particle at `position` towards `body` (xyz = position, w = mass),
+/// `pair_acceleration` of the compute kernel. Never divides by the particle's own mass, so
+/// massless tracers are pulled like any other body.
+fn pair_acceleration(
+    position: Vec3,
+    body: Vec4,
+    softening: f32,
+    gravitational_constant: f32,
+    kernel_config: &NBodyKernelConfig,
+) -> Vec3 {
+    let diff = body.truncate() - position;
+    let dist_sqr = diff.length_squared();
+
+    // coincident bodies have no direction to pull in
+    if dist_sqr == 0.0 {
+        return Vec3::ZERO;
+    }
+
+    let softened_sqr = match kernel_config.softening_kernel {
+        SofteningKernel::None => dist_sqr,
+        SofteningKernel::Additive | SofteningKernel::Plummer => dist_sqr + softening,
+    };
+
+    let magnitude = match kernel_config.force_law {
+        ForceLaw::InverseSquare => 1.0 / softened_sqr,
+        ForceLaw::InverseLinear => 1.0 / softened_sqr.sqrt(),
+        ForceLaw::Spring => softened_sqr.sqrt(),
+    } * gravitational_constant
+        * body.w;
+
+    match kernel_config.softening_kernel {
+        SofteningKernel::Plummer => diff / softened_sqr.sqrt() * magnitude,
+        _ => diff / dist_sqr.sqrt() * magnitude,
+    }
+}
+
+impl SimulationBackend for CpuSimulationBackend {
+    /// The bodies are softened with the simulation's softening until `set_particle_softening`
+    fn upload_state(&mut self, particles: &[GpuParticle]) {
+        self.particles = particles.to_vec();
+        self.particle_softening.clear();
+    }
+
+    fn step(&mut self, delta_time: f32, steps: u32) {
+        for _ in 0..steps {
+            self.step_once(delta_time);
+        }
+    }
+
+    fn download_state(&mut self) -> Vec<GpuParticle> {
+        self.particles.clone()
+    }
+
+    fn diagnostics(&mut self) -> SimulationDiagnostics {
+        SimulationDiagnostics::from_particles(
+            &self.particles,
+            self.gravitational_constant,
+            self.softening,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Vec3, expected: Vec3) {
+        assert!(
+            actual.abs_diff_eq(expected, 1e-6),
+            "{actual:?} != {expected:?}"
+        );
+    }
+
+    // a few dozen bodies spread out deterministically, enough for every thread to get a chunk
+    fn scattered_bodies(count: usize) -> Vec<GpuParticle> {
+        (0..count)
+            .map(|i| {
+                let t = i as f32;
+                let mut body = GpuParticle::new(
+                    Vec3::new(
+                        (t * 1.7).sin() * 5.0,
+                        (t * 0.9).cos() * 5.0,
+                        (t * 2.3).sin(),
+                    ),
+                    1.0 + (i % 3) as f32,
+                    Vec3::new((t * 0.4).cos(), (t * 1.3).sin(), 0.0) * 0.1,
+                );
+                body.id = i as u32;
+                body
+            })
+            .collect()
+    }
+
+    fn momentum(particles: &[GpuParticle]) -> Vec3 {
+        particles
+            .iter()
+            .map(|particle| particle.velocity * particle.position.w)
+            .sum()
+    }
+
+    #[test]
+    fn two_bodies_step_like_computed_by_hand() {
+        let mut backend = CpuSimulationBackend::new(1.0, 0.0);
+        backend.upload_state(&[
+            GpuParticle::new(Vec3::ZERO, 1.0, Vec3::ZERO),
+            GpuParticle::new(Vec3::new(2.0, 0.0, 0.0), 3.0, Vec3::ZERO),
+        ]);
+        backend.step(0.1, 1);
+
+        // a = G * m_other / r^2 = 3 / 4 and 1 / 4 towards each other,
+        // v = a * dt, then x += v * dt
+        let [first, second] = backend.get_particles() else {
+            panic!("expected two bodies");
+        };
+        assert_close(first.velocity, Vec3::new(0.075, 0.0, 0.0));
+        assert_close(first.position.truncate(), Vec3::new(0.0075, 0.0, 0.0));
+        assert_close(second.velocity, Vec3::new(-0.025, 0.0, 0.0));
+        assert_close(second.position.truncate(), Vec3::new(1.9975, 0.0, 0.0));
+    }
+
+    #[test]
+    fn massless_tracers_are_pulled_without_nan() {
+        let mut backend = CpuSimulationBackend::new(1.0, 0.0);
+        backend.upload_state(&[
+            GpuParticle::new(Vec3::ZERO, 1.0, Vec3::ZERO),
+            GpuParticle::new(Vec3::new(1.0, 0.0, 0.0), 0.0, Vec3::ZERO),
+        ]);
+        backend.step(0.1, 1);
+
+        let tracer = &backend.get_particles()[1];
+        assert_close(tracer.velocity, Vec3::new(-0.1, 0.0, 0.0));
+        // the tracer pulls on nothing
+        assert_close(backend.get_particles()[0].velocity, Vec3::ZERO);
+    }
+
+    #[test]
+    fn momentum_is_conserved_over_many_steps() {
+        let mut backend = CpuSimulationBackend::new(1.0, 0.01);
+        backend.upload_state(&scattered_bodies(32));
+        let initial = momentum(backend.get_particles());
+
+        backend.step(0.01, 100);
+
+        let drift = (momentum(backend.get_particles()) - initial).length();
+        assert!(drift < 1e-4, "momentum drifted by {drift}");
+    }
+
+    #[test]
+    fn threads_step_like_a_single_thread() {
+        let particles = scattered_bodies(61);
+        let particle_softening: Vec<f32> = (0..particles.len())
+            .map(|i| if i % 2 == 0 { 0.01 } else { 0.1 })
+            .collect();
+
+        let mut backend = CpuSimulationBackend::new(1.0, 0.01);
+        backend.set_kernel_config(NBodyKernelConfig {
+            integrator: Integrator::Leapfrog,
+            softening_kernel: SofteningKernel::Plummer,
+            ..Default::default()
+        });
+        backend.upload_state(&particles);
+        backend.set_particle_softening(particle_softening.clone());
+        backend.step(0.01, 1);
+
+        let mut expected = vec![GpuParticle::zeroed(); particles.len()];
+        Step {
+            particles: &particles,
+            particle_softening: &particle_softening,
+            gravitational_constant: 1.0,
+            softening: 0.01,
+            kernel_config: *backend.get_kernel_config(),
+            delta_time: 0.01,
+        }
+        .run_chunk(0, &mut expected);
+
+        let stepped: &[u32] = bytemuck::cast_slice(backend.get_particles());
+        assert_eq!(stepped, bytemuck::cast_slice::<GpuParticle, u32>(&expected));
+    }
+}
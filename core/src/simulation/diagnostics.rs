@@ -0,0 +1,152 @@
+use glam::{DVec3, Vec3};
+
+use crate::{simulation::GpuParticle, utils::parallel};
+
+/// Conserved quantities of a simulation state, used to check the integration is sound
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SimulationDiagnostics {
+    pub particle_count: usize,
+    pub total_mass: f32,
+    pub center_of_mass: Vec3,
+    pub momentum: Vec3,
+    pub angular_momentum: Vec3,
+    pub kinetic_energy: f32,
+    /// Softened the same way as the force, so it matches what the integrator sees
+    pub potential_energy: f32,
+}
+
+/// How far the conserved quantities may drift before a run counts as failed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConservationTolerances {
+    pub energy: f32,
+    pub momentum: f32,
+}
+
+impl Default for ConservationTolerances {
+    fn default() -> Self {
+        Self {
+            energy: 0.01,
+            momentum: 1e-3,
+        }
+    }
+}
+
+impl SimulationDiagnostics {
+    pub fn total_energy(&self) -> f32 {
+        self.kinetic_energy + self.potential_energy
+    }
+
+    /// Checks the energy and momentum of this state against an earlier one.
+    /// Energy drift is relative to the initial total energy, momentum drift is
+    /// the change in the center of mass velocity.
+    pub fn check_conservation(
+        &self,
+        initial: &Self,
+        tolerances: &ConservationTolerances,
+    ) -> Result<(), String> {
+        let energy_drift = self.energy_drift(initial);
+        if energy_drift > tolerances.energy {
+            return Err(format!(
+                "Energy drifted by {:.3e}, tolerance is {:.3e}",
+                energy_drift, tolerances.energy
+            ));
+        }
+
+        let momentum_drift = self.momentum_drift(initial);
+        if momentum_drift > tolerances.momentum {
+            return Err(format!(
+                "Momentum drifted by {:.3e}, tolerance is {:.3e}",
+                momentum_drift, tolerances.momentum
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Relative change in total energy since `initial`
+    pub fn energy_drift(&self, initial: &Self) -> f32 {
+        let initial_energy = initial.total_energy();
+        let drift = (self.total_energy() - initial_energy).abs();
+        if initial_energy != 0.0 {
+            drift / initial_energy.abs()
+        } else {
+            drift
+        }
+    }
+
+    /// Change in the center of mass velocity since `initial`
+    pub fn momentum_drift(&self, initial: &Self) -> f32 {
+        if self.total_mass <= 0.0 {
+            return 0.0;
+        }
+
+        (self.momentum - initial.momentum).length() / self.total_mass
+    }
+
+    /// Sums the diagnostics over every particle, accumulating in double precision
+    pub fn from_particles(
+        particles: &[GpuParticle],
+        gravitational_constant: f32,
+        softening: f32,
+    ) -> Self {
+        let mut total_mass = 0.0;
+        let mut mass_position = DVec3::ZERO;
+        let mut momentum = DVec3::ZERO;
+        let mut angular_momentum = DVec3::ZERO;
+        let mut kinetic_energy = 0.0;
+
+        for particle in particles {
+            let mass = particle.position.w as f64;
+            let position = particle.position.truncate().as_dvec3();
+            let velocity = particle.velocity.as_dvec3();
+
+            total_mass += mass;
+            mass_position += position * mass;
+            momentum += velocity * mass;
+            angular_momentum += position.cross(velocity * mass);
+            kinetic_energy += 0.5 * mass * velocity.length_squared();
+        }
+
+        let center_of_mass = if total_mass > 0.0 {
+            mass_position / total_mass
+        } else {
+            DVec3::ZERO
+        };
+
+        Self {
+            particle_count: particles.len(),
+            total_mass: total_mass as f32,
+            center_of_mass: center_of_mass.as_vec3(),
+            momentum: momentum.as_vec3(),
+            angular_momentum: angular_momentum.as_vec3(),
+            kinetic_energy: kinetic_energy as f32,
+            potential_energy: Self::potential_energy(particles, gravitational_constant, softening)
+                as f32,
+        }
+    }
+
+    /// Sums the potential energy of every pair, split over all threads.
+    /// A thread that panics panics the caller, a missing term would skew the conservation checks.
+    fn potential_energy(
+        particles: &[GpuParticle],
+        gravitational_constant: f32,
+        softening: f32,
+    ) -> f64 {
+        parallel::map_chunks(particles.len(), |range| {
+            let mut energy = 0.0;
+            for i in range {
+                let a = &particles[i];
+                for b in &particles[i + 1..] {
+                    let diff = (b.position.truncate() - a.position.truncate()).as_dvec3();
+                    let distance = (diff.length_squared() + softening as f64).sqrt();
+                    energy -=
+                        gravitational_constant as f64 * a.position.w as f64 * b.position.w as f64
+                            / distance;
+                }
+            }
+            energy
+        })
+        .into_iter()
+        .sum()
+    }
+}
@@ -0,0 +1,177 @@
+use bevy_ecs::world::{Mut, World};
+
+use crate::{
+    ecs::resources::{
+        apc_resources::ApcQueue, nbody_sim_resources::NBodySimResources,
+        simulation_step_profiler::SimulationStepProfiler,
+    },
+    gpu_resources::{
+        pipelines::{compute_task_registry::ComputeTaskRegistry, n_body_sim_task::NBodySimTask},
+        render_resources::RenderResources,
+    },
+};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::{
+    gpu_resources::{
+        layouts::nbody_simparams_uniform_layout::NBodySimParamsUniformLayout,
+        particle_storage::{InstanceFormat, ParticleLayout},
+    },
+    simulation::{GpuParticle, diagnostics::SimulationDiagnostics},
+    traits::simulation_traits::SimulationBackend,
+};
+
+/// Runs the n-body compute shader on the simulation resources of a world.
+/// Steps both the app, once per frame before it is recorded, and the headless tools.
+/// Each step is submitted on its own, so the frame's render loop isn't needed.
+/// No instances are generated, the next rendered frame generates them from the final state.
+/// The steps are timed when the world has a `SimulationStepProfiler`.
+/// Reading the state back blocks, so `SimulationBackend` isn't implemented on the web.
+pub struct GpuSimulationBackend<'w> {
+    world: &'w mut World,
+}
+
+impl<'w> GpuSimulationBackend<'w> {
+    /// The world needs the render resources and the n-body simulation resources
+    pub fn new(world: &'w mut World) -> Self {
+        Self { world }
+    }
+
+    /// Sets the force constants used by the compute shader
+    pub fn set_force_params(&mut self, gravitational_constant: f32, softening: f32) {
+        self.world
+            .resource_scope(|world, mut sim: bevy_ecs::world::Mut<NBodySimResources>| {
+                let render_resources = world.get_resource::<RenderResources>().unwrap();
+                sim.set_force_params(&render_resources.queue, gravitational_constant, softening);
+            });
+    }
+
+    /// Sets how the particles and their render instances are stored, converting the current state
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_storage(
+        &mut self,
+        particle_layout: ParticleLayout,
+        instance_format: InstanceFormat,
+    ) {
+        self.world
+            .resource_scope(|world, mut sim: bevy_ecs::world::Mut<NBodySimResources>| {
+                let render_resources = world.get_resource::<RenderResources>().unwrap();
+                sim.set_particle_layout(render_resources, particle_layout);
+                sim.set_instance_format(&render_resources.queue, instance_format);
+            });
+    }
+
+    /// Advances the simulation by `steps` steps of `delta_time` each
+    pub fn advance(&mut self, delta_time: f32, steps: u32) {
+        let profiling = self
+            .world
+            .get_resource::<SimulationStepProfiler>()
+            .is_some_and(SimulationStepProfiler::is_profiling);
+
+        if profiling {
+            self.world
+                .resource_scope(|world, mut profiler: Mut<SimulationStepProfiler>| {
+                    let device = &world.resource::<RenderResources>().device;
+                    if let Some(apc_queue) = world.get_resource::<ApcQueue>() {
+                        profiler.begin_frame(device, &apc_queue.sender);
+                    }
+                });
+        }
+
+        for step in 0..steps {
+            self.step_once(delta_time, step);
+        }
+
+        if profiling {
+            self.world
+                .resource_scope(|world, mut profiler: Mut<SimulationStepProfiler>| {
+                    let (device, queue) = world.resource::<RenderResources>().get_device_queue();
+                    let mut encoder =
+                        device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                            label: Some("Simulation Step Timestamp Encoder"),
+                        });
+                    profiler.end_frame(&mut encoder, steps);
+                    queue.submit(std::iter::once(encoder.finish()));
+                });
+        }
+    }
+
+    /// Records and submits one step, timed as the step at `step` of this call to `advance`
+    fn step_once(&mut self, delta_time: f32, step: u32) {
+        self.world
+            .resource_scope(|world, mut sim: bevy_ecs::world::Mut<NBodySimResources>| {
+                let render_resources = world.get_resource::<RenderResources>().unwrap();
+                sim.set_delta_time(&render_resources.queue, delta_time);
+            });
+        // picks up changes to the NBodyKernelConfig made since the last step
+        self.world.resource_scope(
+            |world, mut registry: bevy_ecs::world::Mut<ComputeTaskRegistry>| {
+                registry.prepare(world);
+            },
+        );
+
+        let world = &*self.world;
+        let render_resources = world.get_resource::<RenderResources>().unwrap();
+        let (device, queue) = render_resources.get_device_queue();
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Simulation Backend Encoder"),
+        });
+        {
+            let timestamp_writes = world
+                .get_resource::<SimulationStepProfiler>()
+                .and_then(|profiler| profiler.get_timestamp_writes(step));
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some(SimulationStepProfiler::LABEL),
+                timestamp_writes,
+            });
+            world
+                .resource::<ComputeTaskRegistry>()
+                .dispatch_task(world, NBodySimTask::LABEL, &mut compute_pass)
+                .unwrap();
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+
+        self.world
+            .get_resource_mut::<NBodySimResources>()
+            .unwrap()
+            .swap_buffers();
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl SimulationBackend for GpuSimulationBackend<'_> {
+    fn upload_state(&mut self, particles: &[GpuParticle]) {
+        self.world
+            .resource_scope(|world, mut sim: bevy_ecs::world::Mut<NBodySimResources>| {
+                let render_resources = world.get_resource::<RenderResources>().unwrap();
+                let layout = world.get_resource::<NBodySimParamsUniformLayout>().unwrap();
+                sim.set_bodies(render_resources, layout, particles);
+            });
+    }
+
+    fn step(&mut self, delta_time: f32, steps: u32) {
+        self.advance(delta_time, steps);
+    }
+
+    fn download_state(&mut self) -> Vec<GpuParticle> {
+        let render_resources = self.world.get_resource::<RenderResources>().unwrap();
+        self.world
+            .get_resource::<NBodySimResources>()
+            .unwrap()
+            .read_particles_blocking(render_resources)
+    }
+
+    fn diagnostics(&mut self) -> SimulationDiagnostics {
+        let particles = self.download_state();
+        let sim_params = self
+            .world
+            .get_resource::<NBodySimResources>()
+            .unwrap()
+            .get_sim_params();
+
+        SimulationDiagnostics::from_particles(
+            &particles,
+            sim_params.gravitational_constant,
+            sim_params.softening,
+        )
+    }
+}
@@ -0,0 +1,9 @@
+pub mod cpu_backend;
+pub mod diagnostics;
+pub mod gpu_backend;
+pub mod scenario;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod storage_benchmark;
+pub mod workgroup_benchmark;
+
+pub use crate::gpu_resources::types::gpu_particle::GpuParticle;
@@ -0,0 +1,125 @@
+use glam::Vec3;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::simulation::GpuParticle;
+
+/// The initial bodies of a simulation run, reproducible from a seed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScenarioKind {
+    /// A heavy body at the origin in a cloud of light bodies, like the demo starts with
+    Cloud,
+    /// Two equal bodies on a circular orbit around each other
+    Binary,
+    /// Light bodies on circular orbits in a flat disk around a heavy body
+    Disk,
+}
+
+impl ScenarioKind {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "cloud" => Ok(Self::Cloud),
+            "binary" => Ok(Self::Binary),
+            "disk" => Ok(Self::Disk),
+            _ => Err(format!(
+                "Unknown scenario {}, expected cloud, binary or disk",
+                name
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Scenario {
+    pub kind: ScenarioKind,
+    /// Ignored by the binary scenario, which always has two bodies
+    pub particle_count: usize,
+    pub seed: u64,
+    pub gravitational_constant: f32,
+    pub softening: f32,
+}
+
+impl Scenario {
+    /// Uses the same force constants as the gpu simulation defaults
+    pub fn new(kind: ScenarioKind, particle_count: usize, seed: u64) -> Self {
+        Self {
+            kind,
+            particle_count,
+            seed,
+            gravitational_constant: 2.0,
+            softening: 0.1,
+        }
+    }
+
+    /// Generates the bodies, with ids assigned in slot order
+    pub fn bodies(&self) -> Vec<GpuParticle> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+
+        let bodies = match self.kind {
+            ScenarioKind::Cloud => self.cloud(&mut rng),
+            ScenarioKind::Binary => self.binary(),
+            ScenarioKind::Disk => self.disk(&mut rng),
+        };
+
+        bodies
+            .into_iter()
+            .enumerate()
+            .map(|(id, body)| GpuParticle {
+                id: id as u32,
+                ..body
+            })
+            .collect()
+    }
+
+    fn cloud(&self, rng: &mut StdRng) -> Vec<GpuParticle> {
+        let mut bodies = vec![GpuParticle::new(Vec3::ZERO, 500.0, Vec3::ZERO)];
+        bodies.extend(
+            (1..self.particle_count)
+                .map(|_| GpuParticle::new_random(rng, 10.0, 0.1, 0.11, 0.1, 0.11)),
+        );
+        bodies.truncate(self.particle_count);
+        bodies
+    }
+
+    fn binary(&self) -> Vec<GpuParticle> {
+        let mass = 100.0;
+        let separation = 10.0;
+
+        // each body circles the center at half the separation
+        let force =
+            self.gravitational_constant * mass * mass / (separation * separation + self.softening);
+        let speed = (force * separation * 0.5 / mass).sqrt();
+
+        vec![
+            GpuParticle::new(
+                Vec3::new(-separation * 0.5, 0.0, 0.0),
+                mass,
+                Vec3::new(0.0, 0.0, -speed),
+            ),
+            GpuParticle::new(
+                Vec3::new(separation * 0.5, 0.0, 0.0),
+                mass,
+                Vec3::new(0.0, 0.0, speed),
+            ),
+        ]
+    }
+
+    fn disk(&self, rng: &mut StdRng) -> Vec<GpuParticle> {
+        let central_mass = 1000.0;
+        let mut bodies = vec![GpuParticle::new(Vec3::ZERO, central_mass, Vec3::ZERO)];
+        bodies.extend((1..self.particle_count).map(|_| {
+            let radius = rng.gen_range(5.0..50.0f32);
+            let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+            let position = Vec3::new(angle.cos(), 0.0, angle.sin()) * radius;
+
+            // circular orbit around the central body, ignoring the other light bodies
+            let speed = (self.gravitational_constant * central_mass * radius
+                / (radius * radius + self.softening))
+                .sqrt();
+            let velocity = Vec3::new(-angle.sin(), 0.0, angle.cos()) * speed;
+
+            GpuParticle::new(position, 0.01, velocity)
+        }));
+        bodies.truncate(self.particle_count);
+        bodies
+    }
+}
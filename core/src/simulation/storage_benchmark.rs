@@ -0,0 +1,282 @@
+use std::fmt::Write;
+
+use bevy_ecs::world::{Mut, World};
+use web_time::Instant;
+
+use crate::{
+    ecs::resources::{
+        n_body_kernel_config::{Integrator, NBodyKernelConfig},
+        nbody_sim_resources::NBodySimResources,
+    },
+    gpu_resources::{
+        pipelines::{
+            compute_task_registry::ComputeTaskRegistry,
+            particle_instances_task::ParticleInstancesTask,
+        },
+        render_resources::RenderResources,
+    },
+    simulation::{GpuParticle, gpu_backend::GpuSimulationBackend},
+    traits::simulation_traits::SimulationBackend,
+};
+
+pub use crate::gpu_resources::particle_storage::{InstanceFormat, ParticleLayout};
+
+/// The combinations of particle layout and instance format the benchmark compares
+pub const STORAGE_CONFIGURATIONS: [(ParticleLayout, InstanceFormat); 4] = [
+    (ParticleLayout::ArrayOfStructs, InstanceFormat::Full),
+    (ParticleLayout::ArrayOfStructs, InstanceFormat::Packed),
+    (ParticleLayout::StructOfArrays, InstanceFormat::Full),
+    (ParticleLayout::StructOfArrays, InstanceFormat::Packed),
+];
+
+const PARTICLE_SIZE: u64 = std::mem::size_of::<GpuParticle>() as u64;
+// the position and mass word, all the force loop needs
+const POSITION_SIZE: u64 = PARTICLE_SIZE / 2;
+
+/// The bytes a step and the instances move, modelled from the kernels' accesses.
+/// Loads are assumed to fetch whole cache lines, so reading the position of an interleaved
+/// particle fetches its velocity along with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StorageTraffic {
+    /// Read by the force loop plus reading and writing every particle's own state
+    pub step_bytes: u64,
+    /// Written when the instances are generated and read again when they are drawn
+    pub instance_bytes: u64,
+}
+
+impl StorageTraffic {
+    pub fn estimate(
+        particle_layout: ParticleLayout,
+        instance_format: InstanceFormat,
+        config: &NBodyKernelConfig,
+        particle_count: u64,
+    ) -> Self {
+        // the leapfrog drift reads the velocity of every body as well
+        let body_size = match particle_layout {
+            ParticleLayout::StructOfArrays if config.integrator != Integrator::Leapfrog => {
+                POSITION_SIZE
+            }
+            _ => PARTICLE_SIZE,
+        };
+        // tiled, every workgroup streams every body once, direct, every invocation does
+        let body_passes = if config.tiled {
+            particle_count.div_ceil(config.workgroup_size.max(1) as u64)
+        } else {
+            particle_count
+        };
+
+        Self {
+            step_bytes: body_passes * particle_count * body_size
+                + particle_count * PARTICLE_SIZE * 2,
+            instance_bytes: particle_count * instance_format.get_instance_size() * 2,
+        }
+    }
+}
+
+/// How one combination of particle layout and instance format performed
+#[derive(Debug, Clone, Copy)]
+pub struct StorageBenchmarkResult {
+    pub particle_layout: ParticleLayout,
+    pub instance_format: InstanceFormat,
+    pub milliseconds_per_step: f32,
+    pub milliseconds_per_instance_generation: f32,
+    pub traffic: StorageTraffic,
+}
+
+/// Generates the instances `count` times, each submitted on its own
+fn generate_instances(world: &mut World, count: u32) {
+    world.resource_scope(|world, mut registry: Mut<ComputeTaskRegistry>| {
+        registry.prepare(world);
+    });
+
+    for _ in 0..count {
+        world.resource_scope(|world, mut sim: Mut<NBodySimResources>| {
+            sim.reset_indirect_buffer(&world.resource::<RenderResources>().queue);
+        });
+
+        let render_resources = world.resource::<RenderResources>();
+        let (device, queue) = render_resources.get_device_queue();
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Storage Benchmark Encoder"),
+        });
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Storage Benchmark Compute Pass"),
+                timestamp_writes: None,
+            });
+            world
+                .resource::<ComputeTaskRegistry>()
+                .dispatch_task(world, ParticleInstancesTask::LABEL, &mut compute_pass)
+                .unwrap();
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+}
+
+/// Times `steps` gpu simulation steps and instance generations with every combination of
+/// particle layout and instance format on the bodies in the simulation.
+/// The steps are zero length, so the simulation is left as it was. The particle layout and
+/// instance format are restored afterwards and the instances are generated again on the next frame.
+pub fn benchmark_storage(world: &mut World, steps: u32) -> Vec<StorageBenchmarkResult> {
+    let device = world.resource::<RenderResources>().device.clone();
+    let steps = steps.max(1);
+    let config = *world.resource::<NBodyKernelConfig>();
+
+    let sim = world.resource::<NBodySimResources>();
+    let particle_count = sim.get_particle_count() as u64;
+    let (original_layout, original_format) = (sim.get_particle_layout(), sim.get_instance_format());
+
+    let mut results = Vec::new();
+    for (particle_layout, instance_format) in STORAGE_CONFIGURATIONS {
+        GpuSimulationBackend::new(world).set_storage(particle_layout, instance_format);
+
+        // the first run builds the variants and keeps their compile time out of the timing
+        GpuSimulationBackend::new(world).step(0.0, 1);
+        generate_instances(world, 1);
+        device.poll(wgpu::Maintain::Wait);
+
+        let start = Instant::now();
+        GpuSimulationBackend::new(world).step(0.0, steps);
+        device.poll(wgpu::Maintain::Wait);
+        let milliseconds_per_step = start.elapsed().as_secs_f32() * 1000.0 / steps as f32;
+
+        let start = Instant::now();
+        generate_instances(world, steps);
+        device.poll(wgpu::Maintain::Wait);
+        let milliseconds_per_instance_generation =
+            start.elapsed().as_secs_f32() * 1000.0 / steps as f32;
+
+        results.push(StorageBenchmarkResult {
+            particle_layout,
+            instance_format,
+            milliseconds_per_step,
+            milliseconds_per_instance_generation,
+            traffic: StorageTraffic::estimate(
+                particle_layout,
+                instance_format,
+                &config,
+                particle_count,
+            ),
+        });
+    }
+
+    GpuSimulationBackend::new(world).set_storage(original_layout, original_format);
+    world.resource_scope(|world, mut registry: Mut<ComputeTaskRegistry>| {
+        registry.prepare(world);
+    });
+    world
+        .resource_mut::<NBodySimResources>()
+        .mark_instances_stale();
+
+    results
+}
+
+/// A table of the results with the modelled traffic in megabytes
+pub fn storage_report(results: &[StorageBenchmarkResult]) -> String {
+    let mut report = String::new();
+    let _ = writeln!(
+        report,
+        "layout format    step ms  step traffic MB  instances ms  instance traffic MB"
+    );
+    for result in results {
+        let _ = writeln!(
+            report,
+            "{:<6} {:<7} {:>10.3} {:>16.1} {:>13.3} {:>20.1}",
+            result.particle_layout.get_name(),
+            result.instance_format.get_name(),
+            result.milliseconds_per_step,
+            result.traffic.step_bytes as f64 / 1_000_000.0,
+            result.milliseconds_per_instance_generation,
+            result.traffic.instance_bytes as f64 / 1_000_000.0,
+        );
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(integrator: Integrator, tiled: bool) -> NBodyKernelConfig {
+        NBodyKernelConfig {
+            integrator,
+            tiled,
+            workgroup_size: 64,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn split_layout_streams_only_the_positions() {
+        let direct = config(Integrator::Euler, false);
+        let interleaved = StorageTraffic::estimate(
+            ParticleLayout::ArrayOfStructs,
+            InstanceFormat::Full,
+            &direct,
+            100,
+        );
+        let split = StorageTraffic::estimate(
+            ParticleLayout::StructOfArrays,
+            InstanceFormat::Full,
+            &direct,
+            100,
+        );
+
+        let own_state = 100 * PARTICLE_SIZE * 2;
+        assert_eq!(
+            interleaved.step_bytes,
+            100 * 100 * PARTICLE_SIZE + own_state
+        );
+        assert_eq!(split.step_bytes, 100 * 100 * POSITION_SIZE + own_state);
+    }
+
+    #[test]
+    fn leapfrog_streams_whole_particles_in_either_layout() {
+        let leapfrog = config(Integrator::Leapfrog, false);
+        assert_eq!(
+            StorageTraffic::estimate(
+                ParticleLayout::StructOfArrays,
+                InstanceFormat::Full,
+                &leapfrog,
+                100
+            ),
+            StorageTraffic::estimate(
+                ParticleLayout::ArrayOfStructs,
+                InstanceFormat::Full,
+                &leapfrog,
+                100
+            ),
+        );
+    }
+
+    #[test]
+    fn tiling_streams_the_bodies_once_per_workgroup() {
+        let tiled = config(Integrator::Euler, true);
+        let traffic = StorageTraffic::estimate(
+            ParticleLayout::ArrayOfStructs,
+            InstanceFormat::Full,
+            &tiled,
+            130,
+        );
+
+        // 130 bodies are three workgroups of 64
+        assert_eq!(
+            traffic.step_bytes,
+            3 * 130 * PARTICLE_SIZE + 130 * PARTICLE_SIZE * 2
+        );
+    }
+
+    #[test]
+    fn instance_traffic_follows_the_instance_size() {
+        let config = NBodyKernelConfig::default();
+        for format in [InstanceFormat::Full, InstanceFormat::Packed] {
+            let traffic =
+                StorageTraffic::estimate(ParticleLayout::ArrayOfStructs, format, &config, 1000);
+            assert_eq!(
+                traffic.instance_bytes,
+                1000 * format.get_instance_size() * 2
+            );
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use bevy_ecs::world::{Mut, World};
+use log::{info, warn};
+use web_time::Instant;
+
+use crate::{
+    ecs::resources::n_body_kernel_config::NBodyKernelConfig,
+    gpu_resources::{
+        pipelines::{
+            compute_task_registry::ComputeTaskRegistry,
+            n_body_sim_task::{NBodySimTask, workgroup_size_of},
+        },
+        render_resources::RenderResources,
+    },
+    simulation::gpu_backend::GpuSimulationBackend,
+};
+
+pub use crate::ecs::resources::workgroup_tuning::{
+    DEFAULT_TUNING_FILE, TunedWorkgroupSizes, WorkgroupTuning,
+};
+
+/// The workgroup sizes the benchmark tries, the ones the device can't run are skipped
+pub const WORKGROUP_SIZE_CANDIDATES: [u32; 5] = [32, 64, 128, 256, 512];
+
+// workgroup memory the step kernel uses per invocation, one vec4<f32> body of the tile
+const TILE_BYTES_PER_INVOCATION: u32 = 16;
+
+/// How long a step took with one workgroup size
+#[derive(Debug, Clone, Copy)]
+pub struct WorkgroupBenchmarkResult {
+    pub workgroup_size: u32,
+    pub milliseconds_per_step: f32,
+}
+
+/// Whether the device can run the step kernel with `workgroup_size` invocations per workgroup
+pub fn is_workgroup_size_supported(limits: &wgpu::Limits, workgroup_size: u32) -> bool {
+    workgroup_size > 0
+        && workgroup_size <= limits.max_compute_invocations_per_workgroup
+        && workgroup_size <= limits.max_compute_workgroup_size_x
+        && workgroup_size * TILE_BYTES_PER_INVOCATION <= limits.max_compute_workgroup_storage_size
+}
+
+/// The workgroup size the n-body pipelines in use were built with
+pub fn get_active_workgroup_size(world: &World) -> Option<u32> {
+    world
+        .resource::<ComputeTaskRegistry>()
+        .get_active_defines(NBodySimTask::LABEL)
+        .and_then(|defines| workgroup_size_of(defines))
+}
+
+/// Times `steps` gpu simulation steps with every candidate size the device supports.
+/// The steps are zero length, they do all the force work without moving the particles,
+/// so the simulation is left as it was. The kernel config is restored afterwards.
+pub fn benchmark_workgroup_sizes(
+    world: &mut World,
+    candidates: &[u32],
+    steps: u32,
+) -> Vec<WorkgroupBenchmarkResult> {
+    let device = world.resource::<RenderResources>().device.clone();
+    let limits = device.limits();
+    let steps = steps.max(1);
+    let original_config = *world.resource::<NBodyKernelConfig>();
+
+    let mut results = Vec::new();
+    for &workgroup_size in candidates {
+        if !is_workgroup_size_supported(&limits, workgroup_size) {
+            info!(
+                "Skipping workgroup size {}, the device doesn't support it",
+                workgroup_size
+            );
+            continue;
+        }
+        world.resource_mut::<NBodyKernelConfig>().workgroup_size = workgroup_size;
+
+        // the first step builds the variant and keeps its compile time out of the timing
+        GpuSimulationBackend::new(world).advance(0.0, 1);
+        if get_active_workgroup_size(world) != Some(workgroup_size) {
+            warn!(
+                "Skipping workgroup size {}, its kernels failed to build",
+                workgroup_size
+            );
+            continue;
+        }
+        device.poll(wgpu::Maintain::Wait);
+
+        let start = Instant::now();
+        GpuSimulationBackend::new(world).advance(0.0, steps);
+        device.poll(wgpu::Maintain::Wait);
+
+        results.push(WorkgroupBenchmarkResult {
+            workgroup_size,
+            milliseconds_per_step: start.elapsed().as_secs_f32() * 1000.0 / steps as f32,
+        });
+    }
+
+    *world.resource_mut::<NBodyKernelConfig>() = original_config;
+    world.resource_scope(|world, mut registry: Mut<ComputeTaskRegistry>| {
+        registry.prepare(world);
+    });
+
+    results
+}
+
+/// Benchmarks the candidate sizes, switches the kernel config to the fastest and persists it
+/// for the adapter named by the `WorkgroupTuning` resource
+pub fn tune_workgroup_size(world: &mut World) -> Result<u32, String> {
+    let tuning = world
+        .get_resource::<WorkgroupTuning>()
+        .ok_or("No WorkgroupTuning resource, the adapter to tune for is unknown")?
+        .clone();
+
+    let results =
+        benchmark_workgroup_sizes(world, &WORKGROUP_SIZE_CANDIDATES, tuning.benchmark_steps);
+    for result in &results {
+        info!(
+            "Workgroup size {:>4}: {:.3} ms per step",
+            result.workgroup_size, result.milliseconds_per_step
+        );
+    }
+
+    let fastest = results
+        .iter()
+        .min_by(|a, b| a.milliseconds_per_step.total_cmp(&b.milliseconds_per_step))
+        .ok_or("No workgroup size could be benchmarked")?
+        .workgroup_size;
+    world.resource_mut::<NBodyKernelConfig>().workgroup_size = fastest;
+
+    // a broken file is replaced rather than blocking the new result
+    let mut tuned = TunedWorkgroupSizes::load(&tuning.path).unwrap_or_else(|e| {
+        warn!("{}", e);
+        TunedWorkgroupSizes::default()
+    });
+    tuned.set(&tuning.adapter_name, fastest);
+    tuned.save(&tuning.path)?;
+
+    Ok(fastest)
+}
+
+/// Inserts the `WorkgroupTuning` resource and switches to the size tuned for its adapter before,
+/// returns the size if there was one the device supports
+pub fn load_tuned_workgroup_size(world: &mut World, tuning: WorkgroupTuning) -> Option<u32> {
+    let tuned = TunedWorkgroupSizes::load(&tuning.path).unwrap_or_else(|e| {
+        warn!("{}", e);
+        TunedWorkgroupSizes::default()
+    });
+    let workgroup_size = tuned.get(&tuning.adapter_name);
+    world.insert_resource(tuning);
+
+    let workgroup_size = workgroup_size?;
+    let limits = world.resource::<RenderResources>().device.limits();
+    if !is_workgroup_size_supported(&limits, workgroup_size) {
+        warn!(
+            "Ignoring the tuned workgroup size {}, the device doesn't support it",
+            workgroup_size
+        );
+        return None;
+    }
+
+    world.resource_mut::<NBodyKernelConfig>().workgroup_size = workgroup_size;
+    Some(workgroup_size)
+}
@@ -0,0 +1,53 @@
+use bevy_ecs::world::World;
+
+use crate::gpu_resources::{reflection::ReflectedBindGroupLayout, shader_variants::ShaderDefines};
+
+/// One entry point of a compute task and the shader it lives in
+#[derive(Debug, Clone, Copy)]
+pub struct ComputeKernel {
+    pub entry_point: &'static str,
+    /// The shader before preprocessing, built with the task's `shader_defines`
+    pub source: &'static str,
+    /// The file the shader is built from, relative to the shader directory, for hot reloading
+    pub source_file: &'static str,
+}
+
+/// A self-contained unit of gpu work recorded into the frame's compute pass.
+/// Registered with the `ComputeTaskRegistry`, which builds the pipelines and runs the tasks in order.
+pub trait ComputeTask: Send + Sync {
+    /// Unique name of the task, also used to label its pipelines
+    fn label(&self) -> &'static str;
+
+    /// The kernels of the task, dispatched in this order
+    fn kernels(&self) -> Vec<ComputeKernel>;
+
+    /// The `#define` values the kernels are built with this frame.
+    /// Each distinct set builds its own pipelines, which are cached by the registry.
+    fn shader_defines(&self, _world: &World) -> ShaderDefines {
+        Vec::new()
+    }
+
+    /// The layouts of the bind groups every kernel uses, group 0 first
+    fn bind_group_layouts<'w>(&self, world: &'w World) -> Vec<&'w wgpu::BindGroupLayout>;
+
+    /// The bindings of `bind_group_layouts`, in the same order, checked against the device limits
+    fn bind_group_reflections<'w>(&self, world: &'w World) -> Vec<&'w ReflectedBindGroupLayout>;
+
+    /// The bind groups to dispatch with this frame, matching `bind_group_layouts`
+    fn bind_groups<'w>(&self, world: &'w World) -> Vec<&'w wgpu::BindGroup>;
+
+    /// The number of workgroups to dispatch for the kernel at `kernel`, `None` skips it this frame.
+    /// `defines` are the ones the pipelines in use were built with, they may lag behind
+    /// `shader_defines` when a new variant failed to build.
+    fn dispatch_size(
+        &self,
+        world: &World,
+        kernel: usize,
+        defines: &[(&'static str, String)],
+    ) -> Option<[u32; 3]>;
+
+    /// Whether the task runs in this frame's compute pass
+    fn is_enabled(&self, _world: &World) -> bool {
+        true
+    }
+}
@@ -1,2 +1,5 @@
 pub mod apc_traits;
+pub mod compute_task_traits;
 pub mod http_traits;
+pub mod render_graph_traits;
+pub mod simulation_traits;
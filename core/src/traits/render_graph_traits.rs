@@ -0,0 +1,25 @@
+use bevy_ecs::world::World;
+
+use crate::render::render_graph::{GraphResource, RenderGraphContext};
+
+/// A named pass of the render graph.
+/// The graph orders passes by the resources they read and write, a pass that writes a resource
+/// without reading it runs before the passes that read and write it, which run before the passes
+/// that only read it.
+pub trait RenderGraphPass: Send + Sync {
+    /// Unique name of the pass, also used to label the gpu pass it records
+    fn label(&self) -> &'static str;
+
+    /// The resources the pass reads
+    fn reads(&self) -> Vec<GraphResource> {
+        Vec::new()
+    }
+
+    /// The resources the pass writes
+    fn writes(&self) -> Vec<GraphResource> {
+        Vec::new()
+    }
+
+    /// Records the pass into the frame's command encoder
+    fn run(&mut self, world: &World, context: &mut RenderGraphContext) -> Result<(), String>;
+}
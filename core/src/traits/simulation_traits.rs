@@ -0,0 +1,17 @@
+use crate::simulation::{GpuParticle, diagnostics::SimulationDiagnostics};
+
+/// Something that can advance the n-body simulation.
+/// Bodies keep their slot order and ids between upload and download.
+pub trait SimulationBackend {
+    /// Replaces the simulated bodies with the given ones
+    fn upload_state(&mut self, particles: &[GpuParticle]);
+
+    /// Advances the simulation by `steps` steps of `delta_time` each
+    fn step(&mut self, delta_time: f32, steps: u32);
+
+    /// Copies the simulated bodies back to the cpu
+    fn download_state(&mut self) -> Vec<GpuParticle>;
+
+    /// Conservation quantities of the current state
+    fn diagnostics(&mut self) -> SimulationDiagnostics;
+}
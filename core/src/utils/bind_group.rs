@@ -0,0 +1,179 @@
+use bytemuck::{Pod, Zeroable};
+
+use crate::{
+    gpu_resources::reflection::{ReflectedBindGroupLayout, ReflectedSize},
+    utils::buffer::Buffer,
+};
+
+/// Builder for bind groups checked against the bindings reflected from the shader.
+/// A resource that doesn't match what the shader declares is reported by `build`
+/// instead of failing wgpu validation.
+pub struct BindGroupBuilder<'a> {
+    /// The device used to create the bind group
+    device: &'a wgpu::Device,
+    /// The layout created from `reflection`
+    layout: &'a wgpu::BindGroupLayout,
+    /// The bindings declared by the shader
+    reflection: &'a ReflectedBindGroupLayout,
+    /// The label for the bind group
+    label: String,
+    /// The resources added so far
+    entries: Vec<wgpu::BindGroupEntry<'a>>,
+    /// Mismatches found while adding resources
+    errors: Vec<String>,
+}
+
+impl<'a> BindGroupBuilder<'a> {
+    /// Creates a new bind group builder
+    pub fn new(
+        device: &'a wgpu::Device,
+        layout: &'a wgpu::BindGroupLayout,
+        reflection: &'a ReflectedBindGroupLayout,
+    ) -> Self {
+        Self {
+            device,
+            layout,
+            reflection,
+            label: "bind_group".to_string(),
+            entries: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Sets the label for the bind group
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.label = label.into();
+        self
+    }
+
+    /// Binds a whole buffer, checking its usage and element size against the shader.
+    /// The elements have to be a whole number of the shader's array elements.
+    pub fn buffer<T: Pod + Zeroable>(mut self, binding: u32, buffer: &'a Buffer<T>) -> Self {
+        if let Err(error) = self.check_buffer(binding, buffer) {
+            self.errors.push(error);
+        }
+
+        self.entries.push(wgpu::BindGroupEntry {
+            binding,
+            resource: buffer.as_entire_binding(),
+        });
+        self
+    }
+
+    /// Binds a texture view
+    pub fn texture_view(mut self, binding: u32, view: &'a wgpu::TextureView) -> Self {
+        if let Err(error) = self.check_kind(binding, "texture", |ty| {
+            matches!(ty, wgpu::BindingType::Texture { .. })
+        }) {
+            self.errors.push(error);
+        }
+
+        self.entries.push(wgpu::BindGroupEntry {
+            binding,
+            resource: wgpu::BindingResource::TextureView(view),
+        });
+        self
+    }
+
+    /// Binds a sampler
+    pub fn sampler(mut self, binding: u32, sampler: &'a wgpu::Sampler) -> Self {
+        if let Err(error) = self.check_kind(binding, "sampler", |ty| {
+            matches!(ty, wgpu::BindingType::Sampler(_))
+        }) {
+            self.errors.push(error);
+        }
+
+        self.entries.push(wgpu::BindGroupEntry {
+            binding,
+            resource: wgpu::BindingResource::Sampler(sampler),
+        });
+        self
+    }
+
+    fn check_kind(
+        &self,
+        binding: u32,
+        kind: &str,
+        is_kind: impl Fn(&wgpu::BindingType) -> bool,
+    ) -> Result<(), String> {
+        let reflected = self.reflection.get_binding(binding).ok_or(format!(
+            "{}: the shader declares no binding {} in group {}",
+            self.label, binding, self.reflection.group
+        ))?;
+
+        if !is_kind(&reflected.entry.ty) {
+            return Err(format!(
+                "{}: binding {} ({}) is {:?}, not a {}",
+                self.label, binding, reflected.name, reflected.entry.ty, kind
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn check_buffer<T: Pod + Zeroable>(
+        &self,
+        binding: u32,
+        buffer: &Buffer<T>,
+    ) -> Result<(), String> {
+        self.check_kind(binding, "buffer", |ty| {
+            matches!(ty, wgpu::BindingType::Buffer { .. })
+        })?;
+        let reflected = self.reflection.get_binding(binding).unwrap();
+
+        let required_usage = match reflected.entry.ty {
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                ..
+            } => wgpu::BufferUsages::UNIFORM,
+            _ => wgpu::BufferUsages::STORAGE,
+        };
+        if !buffer.usage.contains(required_usage) {
+            return Err(format!(
+                "{}: binding {} ({}) needs a buffer with {:?} usage",
+                self.label, binding, reflected.name, required_usage
+            ));
+        }
+
+        // a buffer element may span several array elements, like a particle read as two vec4 words
+        let element_size = std::mem::size_of::<T>() as u32;
+        match reflected.size {
+            ReflectedSize::RuntimeArray { stride } if element_size % stride != 0 => Err(format!(
+                "{}: binding {} ({}) has an element stride of {} bytes, the buffer's elements are {} bytes",
+                self.label, binding, reflected.name, stride, element_size
+            )),
+            ReflectedSize::Fixed(size) if buffer.size < size as u64 => Err(format!(
+                "{}: binding {} ({}) needs {} bytes, the buffer has {}",
+                self.label, binding, reflected.name, size, buffer.size
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Builds the bind group, failing if any resource doesn't match the shader
+    /// or a binding the shader declares was left out
+    pub fn build(mut self) -> Result<wgpu::BindGroup, String> {
+        for reflected in &self.reflection.bindings {
+            let bound = self
+                .entries
+                .iter()
+                .any(|entry| entry.binding == reflected.entry.binding);
+            if !bound {
+                self.errors.push(format!(
+                    "{}: binding {} ({}) was not bound",
+                    self.label, reflected.entry.binding, reflected.name
+                ));
+            }
+        }
+
+        if !self.errors.is_empty() {
+            return Err(self.errors.join("\n"));
+        }
+
+        Ok(self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&self.label),
+            layout: self.layout,
+            entries: &self.entries,
+        }))
+    }
+}
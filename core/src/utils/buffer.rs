@@ -1,3 +1,4 @@
+use bevy_ecs::system::Resource;
 use bytemuck::{Pod, Zeroable};
 use std::marker::PhantomData;
 use std::ops::Range;
@@ -172,6 +173,17 @@ impl<T: Pod + Zeroable> Buffer<T> {
         queue.write_buffer(&self.buffer, offset_bytes, bytemuck::cast_slice(data));
     }
 
+    /// Stages new data to be written with the frame's other uploads, see `StagingBelt`
+    pub fn stage(&self, device: &wgpu::Device, belt: &mut StagingBelt, data: &[T], offset: usize) {
+        let offset_bytes = (offset * std::mem::size_of::<T>()) as u64;
+        belt.write(
+            device,
+            &self.buffer,
+            offset_bytes,
+            bytemuck::cast_slice(data),
+        );
+    }
+
     /// Updates the entire buffer with new data
     pub fn update_all(&self, queue: &wgpu::Queue, data: &[T]) {
         self.update(queue, data, 0);
@@ -191,6 +203,30 @@ impl<T: Pod + Zeroable> Buffer<T> {
     pub fn slice_range(&self, range: Range<u64>) -> wgpu::BufferSlice {
         self.buffer.slice(range)
     }
+
+    /// Records a copy of `count` elements from this buffer into `destination`
+    /// note: offsets and count are in elements, not bytes
+    pub fn copy_to(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        source_offset: usize,
+        destination: &Buffer<T>,
+        destination_offset: usize,
+        count: usize,
+    ) {
+        if count == 0 {
+            return;
+        }
+
+        let element_size = std::mem::size_of::<T>() as u64;
+        encoder.copy_buffer_to_buffer(
+            &self.buffer,
+            source_offset as u64 * element_size,
+            &destination.buffer,
+            destination_offset as u64 * element_size,
+            count as u64 * element_size,
+        );
+    }
 }
 
 /// Implementation of DynamicBuffer for updating data frequently
@@ -224,6 +260,74 @@ impl<T: Pod + Zeroable> DynamicBuffer<T> {
         }
     }
 
+    /// Creates a new dynamic buffer with room for `capacity` elements and no initial data
+    pub fn with_capacity(
+        device: &wgpu::Device,
+        capacity: usize,
+        usage: wgpu::BufferUsages,
+        label: &str,
+    ) -> Self {
+        let build = |suffix: &str| {
+            BufferBuilder::new(device)
+                .size(capacity)
+                .usage(usage)
+                .label(format!("{}_{}", label, suffix))
+                .build()
+                .expect("Failed to create dynamic buffer")
+        };
+
+        Self {
+            read_buffer: build("read"),
+            write_buffer: build("write"),
+            usage,
+            label: label.to_string(),
+        }
+    }
+
+    /// Swaps the read and write buffers
+    pub fn swap(&mut self) {
+        std::mem::swap(&mut self.read_buffer, &mut self.write_buffer);
+    }
+
+    /// Reallocates both buffers with room for `new_capacity` elements,
+    /// copying the first `preserve` elements of the read buffer into the new read buffer.
+    /// Returns true if the buffers were reallocated.
+    /// note: the buffers must have been created with COPY_SRC and COPY_DST usage
+    pub fn grow(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        new_capacity: usize,
+        preserve: usize,
+    ) -> bool {
+        if new_capacity <= self.read_buffer.length {
+            return false;
+        }
+
+        debug_assert!(
+            self.usage
+                .contains(wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST),
+            "DynamicBuffer::grow requires COPY_SRC and COPY_DST usage"
+        );
+
+        let grown = Self::with_capacity(device, new_capacity, self.usage, &self.label);
+        self.read_buffer.copy_to(
+            encoder,
+            0,
+            &grown.read_buffer,
+            0,
+            preserve.min(self.read_buffer.length),
+        );
+
+        *self = grown;
+        true
+    }
+
+    /// Gets the number of elements each buffer can hold
+    pub fn capacity(&self) -> usize {
+        self.read_buffer.length
+    }
+
     /// Updates the buffer, automatically resizing if needed
     pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, data: &[T]) {
         if data.len() > self.write_buffer.length {
@@ -270,3 +374,205 @@ impl<T: Pod + Zeroable> DynamicBuffer<T> {
         }
     }
 }
+
+/// The size of the staging chunks the belt allocates, larger writes get a chunk of their own
+pub const STAGING_CHUNK_SIZE: u64 = 1 << 16;
+
+/// Batches small buffer writes into one command buffer instead of a `queue.write_buffer` each.
+/// The data is copied from staging chunks that are reused once the gpu is done with them.
+/// Staged writes land when the belt is flushed, before any work submitted after the flush.
+#[derive(Resource)]
+pub struct StagingBelt {
+    belt: wgpu::util::StagingBelt,
+    /// Records the copies out of the staging chunks, created by the first write after a flush
+    encoder: Option<wgpu::CommandEncoder>,
+    /// Bytes staged since the last flush
+    staged_bytes: u64,
+}
+
+impl StagingBelt {
+    pub fn new(chunk_size: u64) -> Self {
+        Self {
+            belt: wgpu::util::StagingBelt::new(chunk_size),
+            encoder: None,
+            staged_bytes: 0,
+        }
+    }
+
+    /// The encoder the staged writes are recorded into, for copies that have to be ordered with them
+    pub fn get_encoder(&mut self, device: &wgpu::Device) -> &mut wgpu::CommandEncoder {
+        self.encoder
+            .get_or_insert_with(|| Self::create_encoder(device))
+    }
+
+    fn create_encoder(device: &wgpu::Device) -> wgpu::CommandEncoder {
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Staging Belt Encoder"),
+        })
+    }
+
+    /// Stages a write of `data` to `buffer` at the byte `offset`.
+    /// note: the offset and the size of the data have to be multiples of 4 bytes
+    pub fn write(
+        &mut self,
+        device: &wgpu::Device,
+        buffer: &wgpu::Buffer,
+        offset: u64,
+        data: &[u8],
+    ) {
+        let Some(size) = wgpu::BufferSize::new(data.len() as u64) else {
+            return;
+        };
+
+        let encoder = self
+            .encoder
+            .get_or_insert_with(|| Self::create_encoder(device));
+        self.belt
+            .write_buffer(encoder, buffer, offset, size, device)
+            .copy_from_slice(data);
+        self.staged_bytes += size.get();
+    }
+
+    pub fn has_staged_writes(&self) -> bool {
+        self.encoder.is_some()
+    }
+
+    /// Submits the writes staged since the last flush and returns how many bytes they were.
+    /// The chunks are reused by later writes once the gpu has copied out of them.
+    pub fn flush(&mut self, queue: &wgpu::Queue) -> u64 {
+        let Some(encoder) = self.encoder.take() else {
+            return 0;
+        };
+
+        self.belt.finish();
+        queue.submit(std::iter::once(encoder.finish()));
+        self.belt.recall();
+
+        std::mem::take(&mut self.staged_bytes)
+    }
+}
+
+/// A slot of a `UniformPool`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UniformSlot(u32);
+
+/// Uniform slots of a fixed size sub-allocated from one buffer. Each slot is bound with its own
+/// dynamic offset, so many small uniforms share one buffer and one bind group.
+#[derive(Debug)]
+pub struct UniformPool {
+    buffer: Buffer,
+    /// Bytes of uniform data per slot
+    slot_size: u64,
+    /// Bytes between the starts of two slots, the slot size rounded up to the offset alignment
+    stride: u64,
+    capacity: usize,
+    /// Slots below `allocated` that were freed, handed out again first
+    free_slots: Vec<UniformSlot>,
+    allocated: usize,
+    label: String,
+}
+
+impl UniformPool {
+    pub fn new(device: &wgpu::Device, slot_size: u64, capacity: usize, label: &str) -> Self {
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as u64;
+        let stride = slot_size.max(1).next_multiple_of(alignment);
+        let capacity = capacity.max(1);
+
+        Self {
+            buffer: Self::create_buffer(device, stride, capacity, label),
+            slot_size,
+            stride,
+            capacity,
+            free_slots: Vec::new(),
+            allocated: 0,
+            label: label.to_string(),
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, stride: u64, capacity: usize, label: &str) -> Buffer {
+        BufferBuilder::new(device)
+            .label(label)
+            .size(stride as usize * capacity)
+            .usage(
+                wgpu::BufferUsages::UNIFORM
+                    | wgpu::BufferUsages::COPY_SRC
+                    | wgpu::BufferUsages::COPY_DST,
+            )
+            .build()
+            .expect("Failed to create uniform pool buffer")
+    }
+
+    /// Hands out a free slot, doubling the buffer when every slot is taken.
+    /// Returns whether the buffer was replaced, which invalidates the bind groups made from it.
+    /// The contents move over through the belt, so writes staged before stay ordered with the copy.
+    pub fn allocate(
+        &mut self,
+        device: &wgpu::Device,
+        belt: &mut StagingBelt,
+    ) -> (UniformSlot, bool) {
+        if let Some(slot) = self.free_slots.pop() {
+            return (slot, false);
+        }
+
+        let mut grown = false;
+        if self.allocated == self.capacity {
+            let capacity = self.capacity * 2;
+            let buffer = Self::create_buffer(device, self.stride, capacity, &self.label);
+            self.buffer
+                .copy_to(belt.get_encoder(device), 0, &buffer, 0, self.buffer.length);
+
+            self.buffer = buffer;
+            self.capacity = capacity;
+            grown = true;
+        }
+
+        let slot = UniformSlot(self.allocated as u32);
+        self.allocated += 1;
+        (slot, grown)
+    }
+
+    /// Returns the slot to the pool, its data is left as it was
+    pub fn free(&mut self, slot: UniformSlot) {
+        debug_assert!((slot.0 as usize) < self.allocated && !self.free_slots.contains(&slot));
+        self.free_slots.push(slot);
+    }
+
+    /// The dynamic offset to bind the slot with, it stays the same when the buffer grows
+    pub fn get_offset(&self, slot: UniformSlot) -> u32 {
+        (slot.0 as u64 * self.stride) as u32
+    }
+
+    /// Stages the slot's data, at most the slot size
+    pub fn write(
+        &self,
+        device: &wgpu::Device,
+        belt: &mut StagingBelt,
+        slot: UniformSlot,
+        data: &[u8],
+    ) {
+        debug_assert!(data.len() as u64 <= self.slot_size);
+        belt.write(
+            device,
+            &self.buffer.buffer,
+            self.get_offset(slot) as u64,
+            data,
+        );
+    }
+
+    /// One slot of the buffer, bound with the dynamic offset of the slot in use
+    pub fn binding(&self) -> wgpu::BindingResource {
+        wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+            buffer: &self.buffer.buffer,
+            offset: 0,
+            size: wgpu::BufferSize::new(self.slot_size),
+        })
+    }
+
+    pub fn get_slot_size(&self) -> u64 {
+        self.slot_size
+    }
+
+    pub fn get_capacity(&self) -> usize {
+        self.capacity
+    }
+}
@@ -1,4 +1,7 @@
+pub mod bind_group;
 pub mod buffer;
 pub mod degrees_and_radians;
+pub mod parallel;
 pub mod primitives;
+pub mod radix_sort;
 pub mod texture;
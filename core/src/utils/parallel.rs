@@ -0,0 +1,58 @@
+use std::ops::Range;
+
+/// The number of items each thread handles when `len` items are split over every available thread
+#[cfg(not(target_arch = "wasm32"))]
+pub fn chunk_size(len: usize) -> usize {
+    let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+    len.div_ceil(threads).max(1)
+}
+
+/// Calls `f` with the index of the first item of each chunk of `items` and the chunk,
+/// each chunk on its own thread
+#[cfg(not(target_arch = "wasm32"))]
+pub fn for_each_chunk_mut<T: Send>(items: &mut [T], f: impl Fn(usize, &mut [T]) + Sync) {
+    let chunk_size = chunk_size(items.len());
+    let f = &f;
+
+    std::thread::scope(|scope| {
+        for (chunk_index, chunk) in items.chunks_mut(chunk_size).enumerate() {
+            scope.spawn(move || f(chunk_index * chunk_size, chunk));
+        }
+    });
+}
+
+/// The web has no threads, the items are handled as one chunk
+#[cfg(target_arch = "wasm32")]
+pub fn for_each_chunk_mut<T: Send>(items: &mut [T], f: impl Fn(usize, &mut [T]) + Sync) {
+    f(0, items);
+}
+
+/// Calls `f` with the range of each chunk of `len` items, each chunk on its own thread,
+/// and returns the results in chunk order. A thread that panics panics the caller.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn map_chunks<R: Send>(len: usize, f: impl Fn(Range<usize>) -> R + Sync) -> Vec<R> {
+    let chunk_size = chunk_size(len);
+    let f = &f;
+
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..len)
+            .step_by(chunk_size)
+            .map(|start| scope.spawn(move || f(start..(start + chunk_size).min(len))))
+            .collect();
+
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            })
+            .collect()
+    })
+}
+
+/// The web has no threads, the items are handled as one chunk
+#[cfg(target_arch = "wasm32")]
+pub fn map_chunks<R: Send>(len: usize, f: impl Fn(Range<usize>) -> R + Sync) -> Vec<R> {
+    vec![f(0..len)]
+}
@@ -0,0 +1,371 @@
+use wgpu::BufferUsages;
+
+use crate::{
+    gpu_resources::{
+        reflection::ReflectedBindGroupLayout,
+        render_resources::RenderResources,
+        shaders::radix_sort::{
+            self, SHADER_DESCRIPTOR_COUNT, SHADER_DESCRIPTOR_SCAN, SHADER_DESCRIPTOR_SCATTER,
+        },
+        types::gpu_sort_params::GpuSortParams,
+    },
+    utils::{
+        bind_group::BindGroupBuilder,
+        buffer::{Buffer, BufferBuilder},
+    },
+};
+
+// the digit width and block size radix_sort.wgsl is written with
+const RADIX_BITS: u32 = 4;
+const RADIX: usize = 16;
+const BLOCK_SIZE: u32 = 4096;
+
+/// Passes needed for 32 bit keys
+const MAX_PASSES: usize = 8;
+
+/// A stable gpu radix sort of u32 keys that moves a u32 payload along with them, usually the
+/// index each key came from. Sorts on 4 bits per pass, so keys narrower than 32 bits sort faster.
+/// The pass parameters are written through the queue, so only one sort can be recorded per submit.
+pub struct GpuRadixSort {
+    layout: wgpu::BindGroupLayout,
+    reflection: ReflectedBindGroupLayout,
+    count_pipeline: wgpu::ComputePipeline,
+    scan_pipeline: wgpu::ComputePipeline,
+    scatter_pipeline: wgpu::ComputePipeline,
+
+    // the other side of the ping pong, the keys and payload of every odd pass land here
+    keys_scratch: Buffer<u32>,
+    payload_scratch: Buffer<u32>,
+    histograms: Buffer<u32>,
+    pass_params: Vec<Buffer<GpuSortParams>>,
+    capacity: usize,
+}
+
+impl GpuRadixSort {
+    /// Creates the sort with room for `capacity` keys, it grows when asked to sort more
+    pub fn new(device: &wgpu::Device, capacity: usize) -> Result<Self, String> {
+        let reflection = ReflectedBindGroupLayout::from_shaders(
+            0,
+            &[
+                (
+                    wgpu::ShaderStages::COMPUTE,
+                    radix_sort::naga::entry_points::cs_radix_count::EXCLUSIVE_SOURCE,
+                ),
+                (
+                    wgpu::ShaderStages::COMPUTE,
+                    radix_sort::naga::entry_points::cs_radix_scan::EXCLUSIVE_SOURCE,
+                ),
+                (
+                    wgpu::ShaderStages::COMPUTE,
+                    radix_sort::naga::entry_points::cs_radix_scatter::EXCLUSIVE_SOURCE,
+                ),
+            ],
+        )?;
+        let layout = reflection.create_layout(device, "Radix Sort Bind Group Layout");
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Radix Sort Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let create_pipeline = |descriptor: wgpu::ShaderModuleDescriptor, entry_point: &str| {
+            let module = device.create_shader_module(descriptor);
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(&format!("Radix Sort {}", entry_point)),
+                layout: Some(&pipeline_layout),
+                module: &module,
+                entry_point,
+                compilation_options: Default::default(),
+            })
+        };
+
+        let pass_params = (0..MAX_PASSES)
+            .map(|_| {
+                BufferBuilder::<GpuSortParams>::new(device)
+                    .label("Radix Sort Params Buffer")
+                    .size(1)
+                    .usage(BufferUsages::UNIFORM | BufferUsages::COPY_DST)
+                    .build()
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let capacity = capacity.max(1);
+        let (keys_scratch, payload_scratch, histograms) = Self::create_buffers(device, capacity)?;
+
+        Ok(Self {
+            count_pipeline: create_pipeline(SHADER_DESCRIPTOR_COUNT, "cs_radix_count"),
+            scan_pipeline: create_pipeline(SHADER_DESCRIPTOR_SCAN, "cs_radix_scan"),
+            scatter_pipeline: create_pipeline(SHADER_DESCRIPTOR_SCATTER, "cs_radix_scatter"),
+            layout,
+            reflection,
+            keys_scratch,
+            payload_scratch,
+            histograms,
+            pass_params,
+            capacity,
+        })
+    }
+
+    /// The scratch keys and payload, and the digit histograms of every block
+    fn create_buffers(
+        device: &wgpu::Device,
+        capacity: usize,
+    ) -> Result<(Buffer<u32>, Buffer<u32>, Buffer<u32>), String> {
+        let usage = BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST;
+        let keys_scratch = BufferBuilder::<u32>::new(device)
+            .label("Radix Sort Keys Scratch Buffer")
+            .size(capacity)
+            .usage(usage)
+            .build()?;
+        let payload_scratch = BufferBuilder::<u32>::new(device)
+            .label("Radix Sort Payload Scratch Buffer")
+            .size(capacity)
+            .usage(usage)
+            .build()?;
+        let histograms = BufferBuilder::<u32>::new(device)
+            .label("Radix Sort Histogram Buffer")
+            .size(RADIX * Self::block_count(capacity) as usize)
+            .usage(BufferUsages::STORAGE)
+            .build()?;
+
+        Ok((keys_scratch, payload_scratch, histograms))
+    }
+
+    fn block_count(count: usize) -> u32 {
+        (count as u32).div_ceil(BLOCK_SIZE).max(1)
+    }
+
+    /// The passes sorting on the low `key_bits` bits, one per digit, rounded up to whole digits
+    fn pass_count(key_bits: u32) -> usize {
+        key_bits.min(32).div_ceil(RADIX_BITS) as usize
+    }
+
+    /// The parameters of every pass sorting `count` keys on their low `key_bits` bits
+    fn pass_params(count: usize, key_bits: u32) -> Vec<GpuSortParams> {
+        let block_count = Self::block_count(count);
+        (0..Self::pass_count(key_bits))
+            .map(|pass| GpuSortParams {
+                count: count as u32,
+                shift: pass as u32 * RADIX_BITS,
+                block_count,
+                _0: 0,
+            })
+            .collect()
+    }
+
+    pub fn get_capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Grows the scratch buffers to hold at least `capacity` keys
+    pub fn ensure_capacity(
+        &mut self,
+        device: &wgpu::Device,
+        capacity: usize,
+    ) -> Result<(), String> {
+        if capacity <= self.capacity {
+            return Ok(());
+        }
+
+        let capacity = capacity.max(self.capacity * 2);
+        (self.keys_scratch, self.payload_scratch, self.histograms) =
+            Self::create_buffers(device, capacity)?;
+        self.capacity = capacity;
+        Ok(())
+    }
+
+    /// Records a sort of the first `count` keys, ascending, with keys that compare equal keeping
+    /// their order. The keys have to fit in their low `key_bits` bits, the passes sort on whole
+    /// digits so up to 3 bits above them are sorted on as well. The payload is permuted the same
+    /// way and both end up back in the given buffers, which need storage and copy usages.
+    pub fn sort(
+        &mut self,
+        render_resources: &RenderResources,
+        encoder: &mut wgpu::CommandEncoder,
+        keys: &Buffer<u32>,
+        payload: &Buffer<u32>,
+        count: usize,
+        key_bits: u32,
+    ) -> Result<(), String> {
+        if count > keys.length || count > payload.length {
+            return Err(format!(
+                "Can't sort {} keys in buffers of {} keys and {} payloads",
+                count, keys.length, payload.length
+            ));
+        }
+        if count < 2 || key_bits == 0 {
+            return Ok(());
+        }
+
+        let (device, queue) = render_resources.get_device_queue();
+        self.ensure_capacity(device, count)?;
+
+        let pass_params = Self::pass_params(count, key_bits);
+        let passes = pass_params.len();
+        let block_count = Self::block_count(count);
+
+        let mut bind_groups = Vec::with_capacity(passes);
+        for (pass, (params_buffer, params)) in self.pass_params.iter().zip(&pass_params).enumerate()
+        {
+            params_buffer.update(queue, &[*params], 0);
+
+            let ((keys_in, payload_in), (keys_out, payload_out)) = if pass % 2 == 0 {
+                ((keys, payload), (&self.keys_scratch, &self.payload_scratch))
+            } else {
+                ((&self.keys_scratch, &self.payload_scratch), (keys, payload))
+            };
+            bind_groups.push(
+                BindGroupBuilder::new(device, &self.layout, &self.reflection)
+                    .label("Radix Sort Bind Group")
+                    .buffer(0, params_buffer)
+                    .buffer(1, keys_in)
+                    .buffer(2, payload_in)
+                    .buffer(3, keys_out)
+                    .buffer(4, payload_out)
+                    .buffer(5, &self.histograms)
+                    .build()?,
+            );
+        }
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Radix Sort Compute Pass"),
+                timestamp_writes: None,
+            });
+            for bind_group in &bind_groups {
+                compute_pass.set_bind_group(0, bind_group, &[]);
+                compute_pass.set_pipeline(&self.count_pipeline);
+                compute_pass.dispatch_workgroups(block_count, 1, 1);
+                compute_pass.set_pipeline(&self.scan_pipeline);
+                compute_pass.dispatch_workgroups(1, 1, 1);
+                compute_pass.set_pipeline(&self.scatter_pipeline);
+                compute_pass.dispatch_workgroups(block_count, 1, 1);
+            }
+        }
+
+        // an odd number of passes leaves the result in the scratch buffers
+        if passes % 2 == 1 {
+            self.keys_scratch.copy_to(encoder, 0, keys, 0, count);
+            self.payload_scratch.copy_to(encoder, 0, payload, 0, count);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the invocations per workgroup and keys per invocation radix_sort.wgsl is written with
+    const SORT_WORKGROUP_SIZE: usize = 128;
+    const ITEMS_PER_THREAD: usize = 32;
+
+    /// The passes the kernels run, on the cpu and in the same order of runs and slots
+    fn sort_like_the_kernels(keys: &mut Vec<u32>, payload: &mut Vec<u32>, key_bits: u32) {
+        let count = keys.len();
+        for params in GpuRadixSort::pass_params(count, key_bits) {
+            let block_count = params.block_count as usize;
+            let digit_of = |key: u32| ((key >> params.shift) as usize) & (RADIX - 1);
+
+            // cs_radix_count, digit major
+            let mut histograms = vec![0u32; RADIX * block_count];
+            for (index, &key) in keys.iter().enumerate() {
+                histograms[digit_of(key) * block_count + index / BLOCK_SIZE as usize] += 1;
+            }
+
+            // cs_radix_scan
+            let mut sum = 0;
+            for slot in histograms.iter_mut() {
+                let count = *slot;
+                *slot = sum;
+                sum += count;
+            }
+
+            // cs_radix_scatter, the runs of a block in invocation order
+            let mut keys_out = vec![0; count];
+            let mut payload_out = vec![0; count];
+            for block in 0..block_count {
+                let mut offsets: Vec<u32> = (0..RADIX)
+                    .map(|digit| histograms[digit * block_count + block])
+                    .collect();
+                for invocation in 0..SORT_WORKGROUP_SIZE {
+                    let run_start = block * BLOCK_SIZE as usize + invocation * ITEMS_PER_THREAD;
+                    for index in run_start..(run_start + ITEMS_PER_THREAD).min(count) {
+                        let slot = &mut offsets[digit_of(keys[index])];
+                        keys_out[*slot as usize] = keys[index];
+                        payload_out[*slot as usize] = payload[index];
+                        *slot += 1;
+                    }
+                }
+            }
+
+            *keys = keys_out;
+            *payload = payload_out;
+        }
+    }
+
+    fn pseudo_random_keys(count: usize) -> Vec<u32> {
+        let mut state = 0x2545_f491u32;
+        (0..count)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state
+            })
+            .collect()
+    }
+
+    #[test]
+    fn blocks_cover_the_keys() {
+        assert_eq!(GpuRadixSort::block_count(0), 1);
+        assert_eq!(GpuRadixSort::block_count(BLOCK_SIZE as usize), 1);
+        assert_eq!(GpuRadixSort::block_count(BLOCK_SIZE as usize + 1), 2);
+        assert_eq!(RADIX, 1 << RADIX_BITS);
+    }
+
+    #[test]
+    fn passes_cover_the_key_bits() {
+        assert_eq!(GpuRadixSort::pass_count(1), 1);
+        assert_eq!(GpuRadixSort::pass_count(RADIX_BITS), 1);
+        assert_eq!(GpuRadixSort::pass_count(RADIX_BITS + 1), 2);
+        assert_eq!(GpuRadixSort::pass_count(32), MAX_PASSES);
+        assert_eq!(GpuRadixSort::pass_count(64), MAX_PASSES);
+
+        let shifts: Vec<u32> = GpuRadixSort::pass_params(10, 12)
+            .iter()
+            .map(|params| params.shift)
+            .collect();
+        assert_eq!(shifts, [0, 4, 8]);
+    }
+
+    #[test]
+    fn passes_sort_narrow_keys_stably() {
+        // several blocks and a partial last one, with narrow keys so equal keys abound
+        let key_bits = 10;
+        let mut keys: Vec<u32> = pseudo_random_keys(3 * BLOCK_SIZE as usize + 123)
+            .into_iter()
+            .map(|key| key & ((1 << key_bits) - 1))
+            .collect();
+        let mut payload: Vec<u32> = (0..keys.len() as u32).collect();
+
+        let mut expected: Vec<(u32, u32)> = keys.iter().copied().zip(payload.clone()).collect();
+        expected.sort_by_key(|&(key, _)| key);
+
+        sort_like_the_kernels(&mut keys, &mut payload, key_bits);
+        let sorted: Vec<(u32, u32)> = keys.into_iter().zip(payload).collect();
+        assert_eq!(sorted, expected);
+    }
+
+    #[test]
+    fn full_width_keys_sort_completely() {
+        let mut keys = pseudo_random_keys(5000);
+        let mut payload: Vec<u32> = (0..keys.len() as u32).collect();
+        let mut expected = keys.clone();
+        expected.sort();
+
+        sort_like_the_kernels(&mut keys, &mut payload, 32);
+        assert_eq!(keys, expected);
+    }
+}
@@ -0,0 +1,410 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::Arc,
+};
+
+use demo_core::{
+    core::Core,
+    simulation::{
+        GpuParticle,
+        cpu_backend::CpuSimulationBackend,
+        diagnostics::{ConservationTolerances, SimulationDiagnostics},
+        gpu_backend::GpuSimulationBackend,
+        scenario::{Scenario, ScenarioKind},
+        storage_benchmark::{
+            InstanceFormat, ParticleLayout, STORAGE_CONFIGURATIONS, benchmark_storage,
+            storage_report,
+        },
+        workgroup_benchmark::{
+            DEFAULT_TUNING_FILE, WorkgroupTuning, load_tuned_workgroup_size, tune_workgroup_size,
+        },
+    },
+    traits::simulation_traits::SimulationBackend,
+};
+use demo_native::{
+    native_apc_handler::NativeApcHandler, native_http_requester::NativeHttpRequester,
+};
+use log::{error, info};
+
+const USAGE: &str = "\
+Runs a simulation scenario without a window and checks that energy and momentum are conserved.
+
+Usage: headless [options]
+
+Options:
+  --scenario <cloud|binary|disk>   initial bodies (default cloud)
+  --particles <n>                  number of bodies (default 1000)
+  --seed <n>                       seed for the initial bodies (default 0)
+  --backend <cpu|gpu>              simulation backend (default cpu)
+  --steps <n>                      number of steps to run (default 1000)
+  --sim-time <t>                   simulated time to run for, overrides --steps
+  --dt <t>                         delta time of each step (default 0.001)
+  --output <dir>                   directory for snapshots and diagnostics (default headless_output)
+  --snapshot-interval <n>          steps between particle snapshots, 0 disables them (default 100)
+  --diagnostics-interval <n>       steps between diagnostics and conservation checks (default 10)
+  --energy-tolerance <x>           allowed relative energy drift (default 0.01)
+  --momentum-tolerance <x>         allowed drift of the center of mass velocity (default 0.001)
+  --tune-workgroup-size            benchmark the gpu workgroup sizes on the scenario first and
+                                   keep the fastest for the adapter in workgroup_sizes.toml
+  --particle-layout <aos|soa>      how the gpu stores the particles (default aos)
+  --instance-format <full|packed>  how the gpu stores the render instances (default full)
+  --benchmark-storage              time every particle layout and instance format on the
+                                   scenario with --steps steps each, report the time and the
+                                   modelled memory traffic and exit, e.g. with
+                                   --backend gpu --particles 1000000 --steps 3
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    Cpu,
+    Gpu,
+}
+
+struct HeadlessArgs {
+    scenario: Scenario,
+    backend: Backend,
+    steps: u64,
+    delta_time: f32,
+    output: PathBuf,
+    snapshot_interval: u64,
+    diagnostics_interval: u64,
+    tolerances: ConservationTolerances,
+    tune_workgroup_size: bool,
+    particle_layout: ParticleLayout,
+    instance_format: InstanceFormat,
+    benchmark_storage: bool,
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or(format!("Missing value for {}", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for {}: {}", flag, value))
+}
+
+fn parse_args() -> Result<HeadlessArgs, String> {
+    let mut kind = ScenarioKind::Cloud;
+    let mut particle_count = 1000;
+    let mut seed = 0;
+    let mut backend = Backend::Cpu;
+    let mut steps = 1000;
+    let mut sim_time = None;
+    let mut delta_time = 0.001;
+    let mut output = PathBuf::from("headless_output");
+    let mut snapshot_interval = 100;
+    let mut diagnostics_interval = 10;
+    let mut tolerances = ConservationTolerances::default();
+    let mut tune_workgroup_size = false;
+    let mut particle_layout = ParticleLayout::default();
+    let mut instance_format = InstanceFormat::default();
+    let mut benchmark_storage = false;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        match flag.as_str() {
+            "--scenario" => {
+                kind = ScenarioKind::from_name(&parse_value::<String>(&flag, args.next())?)?
+            }
+            "--particles" => particle_count = parse_value(&flag, args.next())?,
+            "--seed" => seed = parse_value(&flag, args.next())?,
+            "--backend" => {
+                backend = match parse_value::<String>(&flag, args.next())?.as_str() {
+                    "cpu" => Backend::Cpu,
+                    "gpu" => Backend::Gpu,
+                    other => return Err(format!("Unknown backend {}, expected cpu or gpu", other)),
+                }
+            }
+            "--steps" => steps = parse_value(&flag, args.next())?,
+            "--sim-time" => sim_time = Some(parse_value::<f32>(&flag, args.next())?),
+            "--dt" => delta_time = parse_value(&flag, args.next())?,
+            "--output" => output = parse_value(&flag, args.next())?,
+            "--snapshot-interval" => snapshot_interval = parse_value(&flag, args.next())?,
+            "--diagnostics-interval" => diagnostics_interval = parse_value(&flag, args.next())?,
+            "--energy-tolerance" => tolerances.energy = parse_value(&flag, args.next())?,
+            "--momentum-tolerance" => tolerances.momentum = parse_value(&flag, args.next())?,
+            "--tune-workgroup-size" => tune_workgroup_size = true,
+            "--particle-layout" => {
+                particle_layout =
+                    ParticleLayout::from_name(&parse_value::<String>(&flag, args.next())?)?
+            }
+            "--instance-format" => {
+                instance_format =
+                    InstanceFormat::from_name(&parse_value::<String>(&flag, args.next())?)?
+            }
+            "--benchmark-storage" => benchmark_storage = true,
+            "--help" | "-h" => {
+                print!("{}", USAGE);
+                std::process::exit(0);
+            }
+            _ => return Err(format!("Unknown option {}\n\n{}", flag, USAGE)),
+        }
+    }
+
+    if delta_time <= 0.0 {
+        return Err("--dt must be positive".to_string());
+    }
+
+    if tune_workgroup_size && backend != Backend::Gpu {
+        return Err("--tune-workgroup-size needs --backend gpu".to_string());
+    }
+
+    let gpu_storage = particle_layout != ParticleLayout::default()
+        || instance_format != InstanceFormat::default()
+        || benchmark_storage;
+    if gpu_storage && backend != Backend::Gpu {
+        return Err(
+            "--particle-layout, --instance-format and --benchmark-storage need --backend gpu"
+                .to_string(),
+        );
+    }
+
+    if let Some(sim_time) = sim_time {
+        steps = (sim_time / delta_time).ceil() as u64;
+    }
+
+    Ok(HeadlessArgs {
+        scenario: Scenario::new(kind, particle_count, seed),
+        backend,
+        steps,
+        delta_time,
+        output,
+        snapshot_interval,
+        diagnostics_interval: diagnostics_interval.max(1),
+        tolerances,
+        tune_workgroup_size,
+        particle_layout,
+        instance_format,
+        benchmark_storage,
+    })
+}
+
+/// Creates a device without a surface, nothing is ever presented.
+/// Also returns the name of the adapter the device was created on.
+fn create_headless_device() -> Result<(Arc<wgpu::Device>, Arc<wgpu::Queue>, String), String> {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+
+    let mut options = wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::HighPerformance,
+        compatible_surface: None,
+        force_fallback_adapter: false,
+    };
+    let mut adapter = futures::executor::block_on(instance.request_adapter(&options));
+    if adapter.is_none() {
+        options.force_fallback_adapter = true;
+        adapter = futures::executor::block_on(instance.request_adapter(&options));
+    }
+    let adapter = adapter.ok_or("Failed to find a graphics adapter")?;
+
+    let info = adapter.get_info();
+    info!("Using adapter: {} ({:?})", info.name, info.backend);
+
+    let (device, queue) = futures::executor::block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            label: Some("Headless Device"),
+            required_features: adapter.features() & wgpu::Features::TIMESTAMP_QUERY,
+            required_limits: adapter.limits(),
+        },
+        None,
+    ))
+    .map_err(|e| format!("Failed to create device: {}", e))?;
+
+    Ok((Arc::new(device), Arc::new(queue), info.name))
+}
+
+fn write_snapshot(output: &Path, step: u64, particles: &[GpuParticle]) -> Result<(), String> {
+    let path = output.join(format!("snapshot_{:08}.csv", step));
+    let file = File::create(&path).map_err(|e| format!("Failed to create {:?}: {}", path, e))?;
+    let mut writer = BufWriter::new(file);
+
+    let write_error = |e: std::io::Error| format!("Failed to write {:?}: {}", path, e);
+    writeln!(writer, "id,x,y,z,vx,vy,vz,mass").map_err(write_error)?;
+    for particle in particles {
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{}",
+            particle.id,
+            particle.position.x,
+            particle.position.y,
+            particle.position.z,
+            particle.velocity.x,
+            particle.velocity.y,
+            particle.velocity.z,
+            particle.position.w,
+        )
+        .map_err(write_error)?;
+    }
+
+    writer.flush().map_err(write_error)
+}
+
+fn write_diagnostics(
+    writer: &mut impl Write,
+    step: u64,
+    sim_time: f32,
+    diagnostics: &SimulationDiagnostics,
+    initial: &SimulationDiagnostics,
+) -> std::io::Result<()> {
+    writeln!(
+        writer,
+        "{},{},{},{},{},{},{},{},{},{},{},{}",
+        step,
+        sim_time,
+        diagnostics.particle_count,
+        diagnostics.total_mass,
+        diagnostics.kinetic_energy,
+        diagnostics.potential_energy,
+        diagnostics.total_energy(),
+        diagnostics.momentum.x,
+        diagnostics.momentum.y,
+        diagnostics.momentum.z,
+        diagnostics.energy_drift(initial),
+        diagnostics.momentum_drift(initial),
+    )
+}
+
+/// Runs the scenario, returning whether every conservation check passed
+fn run(backend: &mut dyn SimulationBackend, args: &HeadlessArgs) -> Result<bool, String> {
+    std::fs::create_dir_all(&args.output)
+        .map_err(|e| format!("Failed to create {:?}: {}", args.output, e))?;
+
+    let diagnostics_path = args.output.join("diagnostics.csv");
+    let file = File::create(&diagnostics_path)
+        .map_err(|e| format!("Failed to create {:?}: {}", diagnostics_path, e))?;
+    let mut diagnostics_writer = BufWriter::new(file);
+    let write_error = |e: std::io::Error| format!("Failed to write {:?}: {}", diagnostics_path, e);
+    writeln!(
+        diagnostics_writer,
+        "step,sim_time,particle_count,total_mass,kinetic_energy,potential_energy,total_energy,\
+         momentum_x,momentum_y,momentum_z,energy_drift,momentum_drift"
+    )
+    .map_err(write_error)?;
+
+    backend.upload_state(&args.scenario.bodies());
+
+    let initial = backend.diagnostics();
+    write_diagnostics(&mut diagnostics_writer, 0, 0.0, &initial, &initial).map_err(write_error)?;
+    if args.snapshot_interval > 0 {
+        write_snapshot(&args.output, 0, &backend.download_state())?;
+    }
+
+    let mut step = 0;
+    while step < args.steps {
+        // step in batches up to the next diagnostics or snapshot
+        let mut next_step = (step / args.diagnostics_interval + 1) * args.diagnostics_interval;
+        if args.snapshot_interval > 0 {
+            next_step = next_step.min((step / args.snapshot_interval + 1) * args.snapshot_interval);
+        }
+        let next_step = next_step.min(args.steps);
+
+        backend.step(args.delta_time, (next_step - step) as u32);
+        step = next_step;
+        let sim_time = step as f32 * args.delta_time;
+
+        if args.snapshot_interval > 0 && step % args.snapshot_interval == 0 {
+            write_snapshot(&args.output, step, &backend.download_state())?;
+        }
+
+        if step % args.diagnostics_interval == 0 || step == args.steps {
+            let diagnostics = backend.diagnostics();
+            write_diagnostics(
+                &mut diagnostics_writer,
+                step,
+                sim_time,
+                &diagnostics,
+                &initial,
+            )
+            .map_err(write_error)?;
+
+            if let Err(failure) = diagnostics.check_conservation(&initial, &args.tolerances) {
+                error!("Conservation check failed at step {}: {}", step, failure);
+                diagnostics_writer.flush().map_err(write_error)?;
+                return Ok(false);
+            }
+        }
+    }
+
+    diagnostics_writer.flush().map_err(write_error)?;
+    info!("Ran {} steps, all conservation checks passed", step);
+
+    Ok(true)
+}
+
+fn main() -> ExitCode {
+    env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .init();
+
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(2);
+        }
+    };
+
+    let result = match args.backend {
+        Backend::Cpu => {
+            let mut backend = CpuSimulationBackend::new(
+                args.scenario.gravitational_constant,
+                args.scenario.softening,
+            );
+            run(&mut backend, &args)
+        }
+        Backend::Gpu => create_headless_device().and_then(|(device, queue, adapter_name)| {
+            // the core owns the simulation resources, its renderer is never used
+            let mut core = Core::new(
+                device,
+                queue,
+                Arc::new(NativeApcHandler),
+                Arc::new(NativeHttpRequester),
+                1,
+                1,
+                wgpu::TextureFormat::Rgba8UnormSrgb,
+            );
+            if let Some(workgroup_size) = load_tuned_workgroup_size(
+                &mut core.world,
+                WorkgroupTuning::new(adapter_name, DEFAULT_TUNING_FILE),
+            ) {
+                info!("Using the tuned workgroup size {}", workgroup_size);
+            }
+            if args.tune_workgroup_size {
+                // benchmark on the scenario bodies, the run uploads them again afterwards
+                GpuSimulationBackend::new(&mut core.world).upload_state(&args.scenario.bodies());
+                let workgroup_size = tune_workgroup_size(&mut core.world)?;
+                info!("Tuned workgroup size: {}", workgroup_size);
+            }
+
+            if args.benchmark_storage {
+                GpuSimulationBackend::new(&mut core.world).upload_state(&args.scenario.bodies());
+                info!(
+                    "Benchmarking {} storage configurations with {} bodies",
+                    STORAGE_CONFIGURATIONS.len(),
+                    args.scenario.particle_count
+                );
+                let results = benchmark_storage(&mut core.world, args.steps as u32);
+                info!("Storage benchmark:\n{}", storage_report(&results));
+                return Ok(true);
+            }
+
+            let mut backend = GpuSimulationBackend::new(&mut core.world);
+            backend.set_storage(args.particle_layout, args.instance_format);
+            backend.set_force_params(
+                args.scenario.gravitational_constant,
+                args.scenario.softening,
+            );
+            run(&mut backend, &args)
+        }),
+    };
+
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            error!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1,5 +1,9 @@
 use std::sync::Arc;
 
+#[cfg(not(target_arch = "wasm32"))]
+use demo_core::simulation::workgroup_benchmark::{
+    DEFAULT_TUNING_FILE, WorkgroupTuning, load_tuned_workgroup_size,
+};
 use demo_core::{
     core::Core,
     traits::{apc_traits::ApcHandler, http_traits::HttpRequester},
@@ -185,8 +189,8 @@ impl<H: DemoWinitHandler> DemoWinitApp<H> {
         let (device, queue) = futures::executor::block_on(adapter.request_device(
             &wgpu::DeviceDescriptor {
                 label: Some("Device"),
-                // Request compute shader feature explicitly
-                required_features: wgpu::Features::empty(),
+                // timestamp queries are optional, passes are only profiled when they're there
+                required_features: adapter.features() & wgpu::Features::TIMESTAMP_QUERY,
                 // Set appropriate limits for RTX 2070
                 required_limits: wgpu::Limits {
                     max_storage_buffers_per_shader_stage: 8, // RTX 2070 supports more than this
@@ -263,7 +267,8 @@ impl<H: DemoWinitHandler> DemoWinitApp<H> {
         let apc_handler = Arc::<dyn ApcHandler>::from(H::build_apc_handler());
         let http_requester = Arc::<dyn HttpRequester>::from(H::build_http_requester());
 
-        let demo_core = Core::new(
+        #[cfg_attr(target_arch = "wasm32", allow(unused_mut))]
+        let mut demo_core = Core::new(
             device.clone(),
             queue.clone(),
             apc_handler.clone(),
@@ -273,6 +278,15 @@ impl<H: DemoWinitHandler> DemoWinitApp<H> {
             surface_config.format,
         );
 
+        // the tuned workgroup sizes are kept on disk, which the web build doesn't have
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(workgroup_size) = load_tuned_workgroup_size(
+            &mut demo_core.world,
+            WorkgroupTuning::new(adapter.get_info().name, DEFAULT_TUNING_FILE),
+        ) {
+            info!("Using the tuned workgroup size {}", workgroup_size);
+        }
+
         let init = DemoWinitAppInit {
             window,
             surface,